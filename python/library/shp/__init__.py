@@ -8,24 +8,50 @@ replaced by Rust-native implementations.
 
 from __future__ import annotations
 
-from typing import Any
+from os import PathLike
+from pathlib import Path
+from typing import Any, Callable, Iterator
 
 __all__ = [
     "ShipProgram",
     "ShipRunnable",
     "ShipResult",
     "CapturedResult",
+    "ShipProcess",
     "ShipEnv",
+    "ShipError",
+    "CommandNotFound",
+    "CommandError",
+    "RedirectError",
     "prog",
     "cmd",
     "pipe",
     "sub",
     "shexec",
     "capture",
+    "capture_all",
+    "watch",
+    "quote",
+    "sh",
+    "from_dict",
     "get_stdout",
     "get_stderr",
+    "sub_out",
     "get_env",
     "set_env",
+    "get_option",
+    "set_option",
+    "get_pipe_size",
+    "set_pipe_size",
+    "last_exit",
+    "pid",
+    "ppid",
+    "dirstack",
+    "reload",
+    "terminal_size",
+    "add_path",
+    "mktemp",
+    "mktemp_dir",
     "env",
     "repl",
 ]
@@ -34,12 +60,77 @@ __all__ = [
 from . import repl
 
 
+class ShipError(Exception):
+    """Base class for errors raised by the shell."""
+
+
+class CommandNotFound(ShipError):
+    """A command could not be found (exit code 127)."""
+
+
+class CommandError(ShipError):
+    """A command exited with a non-zero status."""
+
+
+class RedirectError(ShipError):
+    """Output could not be redirected, e.g. to a file object without a descriptor."""
+
+
 class ShipResult:
-    """Result of executing a command."""
+    """Result of executing a command.
+
+    Results compare equal to ints (and other results) with the same exit code and
+    convert with int(). Truthiness follows shell success rather than the number: a
+    result is True when the exit code is 0, so bool(result) is the opposite of
+    bool(int(result)).
+
+    Examples:
+        if prog('make')('test')() == 0:
+            print("tests passed")
+        if not prog('grep')('-q', 'TODO', 'notes.txt')():
+            print("nothing left to do")
+    """
 
     def __init__(self, exit_code: int = 0) -> None:
         self.exit_code = exit_code
 
+    def __eq__(self, other: object) -> bool:
+        """Compare exit codes with an int or another ShipResult."""
+        if isinstance(other, ShipResult):
+            return self.exit_code == other.exit_code
+        if isinstance(other, int) and not isinstance(other, bool):
+            return self.exit_code == other
+        return NotImplemented
+
+    def __hash__(self) -> int:
+        return hash(self.exit_code)
+
+    def __int__(self) -> int:
+        return self.exit_code
+
+    def __bool__(self) -> bool:
+        """True when the command succeeded (exit code 0)."""
+        return self.exit_code == 0
+
+    def raise_for_status(self) -> ShipResult:
+        """Raise if the command failed.
+
+        Returns:
+            This result, when the exit code is 0 (so calls can be chained).
+
+        Raises:
+            CommandNotFound: If the exit code is 127, the shell's "command not found".
+            CommandError: For any other non-zero exit code.
+
+        Example:
+            prog('make')('install')().raise_for_status()
+        """
+        if self.exit_code == 127:
+            raise CommandNotFound("command not found (exit code 127)")
+        if self.exit_code != 0:
+            raise CommandError(f"command failed with exit code {self.exit_code}")
+        return self
+
 
 class CapturedResult:
     """Result of capturing command output with file descriptors.
@@ -63,6 +154,14 @@ class CapturedResult:
         stdout_fd = result.stdout_fd  # You must close this FD!
         # ... use os.read(stdout_fd, size) for streaming
         os.close(stdout_fd)
+
+        # Close whatever wasn't read as soon as the block ends
+        with capture(prog('make')()) as result:
+            if result.exit_code != 0:
+                print(result.read_stderr())
+
+    Streams that are never read are closed when the result is freed, or on
+    leaving a with block (or calling close()) for deterministic cleanup.
     """
 
     def __init__(self, exit_code: int = 0) -> None:
@@ -73,17 +172,22 @@ class CapturedResult:
         """
         self.exit_code = exit_code
 
-    def read_stdout(self) -> str:
+    def read_stdout(self, errors: str = "replace") -> str:
         """Read all stdout and return as a string.
 
         This method consumes the stdout file descriptor and can only be
         called once. Subsequent calls will raise an error.
 
+        Args:
+            errors: How to handle invalid UTF-8. 'replace' (the default)
+                    substitutes U+FFFD, 'strict' raises UnicodeDecodeError.
+
         Returns:
             The complete stdout content as a string.
 
         Raises:
             RuntimeError: If stdout has already been consumed.
+            UnicodeDecodeError: If errors='strict' and the output is not valid UTF-8.
 
         Examples:
             result = capture(prog('echo')('hello'))
@@ -92,17 +196,22 @@ class CapturedResult:
         """
         raise NotImplementedError("CapturedResult only works in ShipShell REPL")
 
-    def read_stderr(self) -> str:
+    def read_stderr(self, errors: str = "replace") -> str:
         """Read all stderr and return as a string.
 
         This method consumes the stderr file descriptor and can only be
         called once. Subsequent calls will raise an error.
 
+        Args:
+            errors: How to handle invalid UTF-8. 'replace' (the default)
+                    substitutes U+FFFD, 'strict' raises UnicodeDecodeError.
+
         Returns:
             The complete stderr content as a string.
 
         Raises:
             RuntimeError: If stderr has already been consumed.
+            UnicodeDecodeError: If errors='strict' and the output is not valid UTF-8.
 
         Examples:
             result = capture(prog('ls')('/nonexistent'))
@@ -111,6 +220,77 @@ class CapturedResult:
         """
         raise NotImplementedError("CapturedResult only works in ShipShell REPL")
 
+    def read_stdout_bytes(self) -> bytes:
+        """Read all stdout and return the raw bytes.
+
+        Consumes the stdout file descriptor like read_stdout(). Use this for
+        binary output where no decoding should happen.
+
+        Raises:
+            RuntimeError: If stdout has already been consumed.
+
+        Examples:
+            data = capture(prog('gzip')('-c', 'file.txt')).read_stdout_bytes()
+        """
+        raise NotImplementedError("CapturedResult only works in ShipShell REPL")
+
+    def read_stderr_bytes(self) -> bytes:
+        """Read all stderr and return the raw bytes.
+
+        Consumes the stderr file descriptor like read_stderr().
+
+        Raises:
+            RuntimeError: If stderr has already been consumed.
+        """
+        raise NotImplementedError("CapturedResult only works in ShipShell REPL")
+
+    def __str__(self) -> str:
+        """Return the stdout text, with invalid UTF-8 replaced.
+
+        Unlike read_stdout(), this can be called again - the output is kept, and the
+        next read_stdout() (or read_stdout_bytes()) still returns all of it.
+
+        Raises:
+            RuntimeError: If stdout has already been consumed.
+
+        Example:
+            print(str(capture(cmd('date'))))
+        """
+        raise NotImplementedError("CapturedResult only works in ShipShell REPL")
+
+    def __repr__(self) -> str:
+        """Show the exit code and the start of stdout and stderr.
+
+        Keeps the output readable like __str__; a consumed stream shows as <consumed>.
+        """
+        raise NotImplementedError("CapturedResult only works in ShipShell REPL")
+
+    def columns(
+        self, header: bool = True, maxsplit: int | None = None
+    ) -> list[dict[str | int, str]]:
+        """Read stdout as whitespace-separated columns, like `ps` or `df` output.
+
+        Consumes the stdout file descriptor like read_stdout(). Blank lines are skipped.
+
+        Args:
+            header: Treat the first line as column names and key each row by them (fields
+                past the last name are dropped). Otherwise rows are keyed by position.
+            maxsplit: Split each row at most this many times, so the last column keeps its
+                spaces (e.g. ps's COMMAND). With a header this defaults to one less than the
+                number of names; otherwise there's no limit.
+
+        Returns:
+            One dict per row.
+
+        Raises:
+            RuntimeError: If stdout has already been consumed.
+
+        Example:
+            for proc in prog('ps')('-eo', 'pid,args').capture().columns():
+                print(proc['PID'], proc['COMMAND'])
+        """
+        raise NotImplementedError("CapturedResult only works in ShipShell REPL")
+
     @property
     def stdout_fd(self) -> int:
         """Get the raw stdout file descriptor for manual streaming.
@@ -164,6 +344,99 @@ class CapturedResult:
         """
         raise NotImplementedError("CapturedResult only works in ShipShell REPL")
 
+    @property
+    def combined(self) -> str:
+        """Read stdout and stderr interleaved in the order they were written.
+
+        Only available when captured with capture(..., merge_stderr=True), in which
+        case the separate stdout and stderr streams are empty. Can only be read once.
+
+        Returns:
+            The combined output, with invalid UTF-8 replaced.
+
+        Raises:
+            RuntimeError: If stderr wasn't merged, or the output was already read.
+
+        Examples:
+            result = capture(prog('make')(), merge_stderr=True)
+            print(result.combined)
+        """
+        raise NotImplementedError("CapturedResult only works in ShipShell REPL")
+
+    def close(self) -> None:
+        """Close any streams that haven't been read or taken. Safe to call more than once.
+
+        Reading a closed stream raises RuntimeError, as if it had already been read.
+        FDs taken through stdout_fd/stderr_fd are not affected.
+        """
+        raise NotImplementedError("CapturedResult only works in ShipShell REPL")
+
+    def __enter__(self) -> CapturedResult:
+        return self
+
+    def __exit__(self, exc_type: Any, exc_value: Any, traceback: Any) -> bool:
+        """Close unread streams on leaving the with block."""
+        raise NotImplementedError("CapturedResult only works in ShipShell REPL")
+
+
+class ShipProcess:
+    """Handle to a command started with ShipRunnable.spawn().
+
+    Attributes:
+        pid: The process id (also its process group id).
+        command: The command line that was started.
+        exit_code: The exit code once the process has finished, otherwise None.
+
+    Examples:
+        server = prog('server')().spawn()
+        if server.poll() is None:
+            server.kill()
+        server.wait()  # 143
+    """
+
+    def __init__(self, pid: int = 0, command: str = "") -> None:
+        """Initialize a process handle.
+
+        Args:
+            pid: The process id.
+            command: The command line that was started.
+        """
+        self.pid = pid
+        self.command = command
+        self.exit_code: int | None = None
+
+    def poll(self) -> int | None:
+        """Return the exit code if the process has finished, without blocking.
+
+        Returns:
+            The exit code, or None while the process is still running.
+        """
+        raise NotImplementedError("ShipProcess only works in ShipShell REPL")
+
+    def wait(self) -> int:
+        """Wait for the process to finish.
+
+        Returns:
+            The exit code, 128 + N if the process was killed by signal N.
+
+        Raises:
+            KeyboardInterrupt: If Ctrl-C is pressed while waiting (the process keeps running).
+        """
+        raise NotImplementedError("ShipProcess only works in ShipShell REPL")
+
+    def kill(self, sig: int = 15) -> None:
+        """Send a signal (SIGTERM by default) to the process's group.
+
+        Does nothing once the process has finished and been reaped.
+
+        Args:
+            sig: The signal number, e.g. signal.SIGKILL.
+
+        Raises:
+            ValueError: If sig isn't a valid signal.
+        """
+        raise NotImplementedError("ShipProcess only works in ShipShell REPL")
+
 
 class ShipRunnable:
     """Represents a command or pipeline that can be executed."""
@@ -172,26 +445,396 @@ class ShipRunnable:
         """Execute the command or pipeline."""
         raise NotImplementedError("ShipRunnable only works in ShipShell REPL")
 
+    def __str__(self) -> str:
+        """Render as the equivalent shell command line, e.g. "ls -l 'my dir'"."""
+        raise NotImplementedError("ShipRunnable only works in ShipShell REPL")
+
     def __or__(self, other: ShipRunnable) -> ShipRunnable:
         """Pipe this command's output to another command."""
         raise NotImplementedError("Piping only works in ShipShell REPL")
 
+    def arg(self, value: str) -> ShipRunnable:
+        """Return a new command with one more argument appended.
+
+        Raises:
+            TypeError: If this runnable isn't a plain command (e.g. a pipeline).
+
+        Example:
+            base = prog('git')()
+            status = base.arg('status')
+        """
+        raise NotImplementedError("arg() only works in ShipShell REPL")
+
+    def args(self, values: list[str]) -> ShipRunnable:
+        """Return a new command with more arguments appended.
+
+        Raises:
+            TypeError: If this runnable isn't a plain command (e.g. a pipeline).
+
+        Example:
+            ls = prog('ls')()
+            if verbose:
+                ls = ls.args(['-l', '-a'])
+        """
+        raise NotImplementedError("args() only works in ShipShell REPL")
+
+    def stdin_from(self, source: ShipRunnable) -> ShipRunnable:
+        """Take stdin from another runnable (the same as `source | self`).
+
+        Example:
+            prog('sort')().stdin_from(prog('cat')('names.txt'))
+        """
+        raise NotImplementedError("Piping only works in ShipShell REPL")
+
+    def input(self, data: str | bytes) -> ShipRunnable:
+        """Return a new runnable that reads `data` as its stdin (like subprocess's `input=`).
+
+        Applied to a pipeline, the data goes to its first stage. The result can be
+        run, captured or redirected like any other runnable, and piped onward, but
+        not piped into.
+
+        Args:
+            data: Text or bytes to feed to stdin
+
+        Example:
+            get_stdout(prog('sort')().input('b\\na\\n'))  # 'a\\nb\\n'
+        """
+        raise NotImplementedError("input() only works in ShipShell REPL")
+
+    def background(self) -> int:
+        """Run in the background without waiting (like `cmd &` in other shells).
+
+        The job runs in its own process group, so Ctrl-C at the prompt doesn't reach it.
+        Finished jobs are reported before the next prompt.
+
+        Returns:
+            The job id, as listed by jobs()
+
+        Example:
+            prog('sleep')('30').background()  # 1
+        """
+        raise NotImplementedError("background() only works in ShipShell REPL")
+
+    def spawn(self) -> "ShipProcess":
+        """Start running without waiting and return a handle to the process.
+
+        Like background() the command runs in its own process group, but it isn't
+        added to jobs() - use the handle to poll, wait for or signal it.
+
+        Returns:
+            A ShipProcess for the running command
+
+        Example:
+            server = prog('server')('--port', '8080').spawn()
+            ...
+            server.kill()
+        """
+        raise NotImplementedError("spawn() only works in ShipShell REPL")
+
+    def nohup(self) -> int:
+        """Run detached so it keeps going after the shell exits (like `nohup cmd &`).
+
+        The command runs in its own session with SIGHUP ignored. If stdout is a terminal
+        the output is appended to nohup.out (or ~/nohup.out) instead, stderr follows it,
+        and stdin reads from /dev/null. Detached commands aren't listed by jobs().
+
+        Returns:
+            The PID of the detached command
+
+        Example:
+            prog('./long_build.sh')().nohup()  # 48213
+        """
+        raise NotImplementedError("nohup() only works in ShipShell REPL")
+
+    def then(self, callback: Callable[[ShipResult], Any]) -> ShipRunnable:
+        """Call a function with the result once this runnable has run.
+
+        The callback runs in the shell process, so it only fires when the returned
+        runnable is called directly; capture(), background() and the other ways of
+        running it raise TypeError rather than skip the callback. then() must be the
+        final operation - piping, redirecting or adding an environment to it raises
+        TypeError too.
+
+        Args:
+            callback: Called with the ShipResult; its return value is ignored and any
+                      exception it raises propagates. $? is left as this runnable's
+                      exit code even if the callback runs other commands.
+
+        Returns:
+            A runnable that returns the original ShipResult when called.
+
+        Example:
+            prog('make')('build').then(lambda r: print(r.exit_code))()
+        """
+        raise NotImplementedError("then() only works in ShipShell REPL")
+
+    def retry(self, attempts: int = 3, delay: float = 1.0, backoff: float = 2.0) -> ShipRunnable:
+        """Re-run this runnable until it succeeds, up to a number of attempts.
+
+        Runs in the shell process between tries, so retries only happen when the
+        returned runnable is called directly; capture(), background() and the other
+        ways of running it raise TypeError rather than run the command once. retry()
+        must be the final operation - piping, redirecting or adding an environment to
+        it raises TypeError too.
+
+        Args:
+            attempts: Maximum number of runs, at least 1 (default 3)
+            delay: Seconds to wait before the first retry (default 1.0)
+            backoff: Multiplier applied to the wait after each retry (default 2.0)
+
+        Returns:
+            A runnable that returns the last run's ShipResult when called. Ctrl-C
+            stops retrying and returns the result so far.
+
+        Raises:
+            ValueError: If attempts is below 1, delay is negative or backoff isn't positive
+
+        Example:
+            prog('curl')('-fsS', url).retry(attempts=5, delay=0.5)()
+        """
+        raise NotImplementedError("retry() only works in ShipShell REPL")
+
+    def to_dict(self) -> dict[str, Any]:
+        """Describe this runnable as nested dicts and lists, e.g. to save it as JSON.
+
+        Every level has a "type" key: "command" (program, args), "pipeline"
+        (stages), "subshell" (runnable), "redirect" (runnable, target with path,
+        append, clobber and mode), "input_redirect" (runnable, source with path),
+        "with_env" (runnable, env, and inherit only when env_inherit(False)) or "input"
+        (runnable, data - a str, or bytes if it isn't valid UTF-8). Environment values
+        keep their Python types, so pathlib.Path values stay Path objects.
+
+        Returns:
+            A dict that from_dict() turns back into an equivalent runnable.
+
+        Raises:
+            ValueError: If the runnable uses then() or redirects to or from an open file,
+                        neither of which can be described portably.
+
+        Example:
+            prog('ls')('-l').to_dict()
+            # {'type': 'command', 'program': 'ls', 'args': ['-l']}
+        """
+        raise NotImplementedError("to_dict() only works in ShipShell REPL")
+
+    def describe(self) -> dict[str, Any]:
+        """Describe what will run as nested dicts, e.g. for a dry run or a linter.
+
+        Uses the same layout as to_dict(), with some additions:
+        - commands also have "builtin": True if the shell runs them itself,
+          False for a program found in PATH
+        - with_env levels always have "inherit"
+        - redirects to or from an open file give its "fd" instead of a path
+        - then() appears as "then" (runnable) and retry() as "retry" (runnable,
+          attempts, delay, backoff)
+
+        Unlike to_dict(), every runnable can be described.
+
+        Example:
+            (prog('cd')('/tmp') | prog('cat')()).describe()['stages'][0]['builtin']  # True
+        """
+        raise NotImplementedError("describe() only works in ShipShell REPL")
+
+    def capture(self, merge_stderr: bool = False, pty: bool = False) -> CapturedResult:
+        """Run and capture stdout and stderr - the same as capture(runnable).
+
+        For a pipeline the final stage's output is captured. $? is set to the
+        exit code.
+
+        Args:
+            merge_stderr: Send stderr to the same stream as stdout, preserving the order of
+                writes like a terminal would. Read it with `result.combined`.
+            pty: Run with stdout on a pseudo-terminal, so tools that check for one (ls
+                columns, colours, line buffering) behave as they would interactively.
+
+        Returns:
+            A CapturedResult containing exit_code and file descriptors for stdout/stderr.
+
+        Raises:
+            KeyboardInterrupt: If Ctrl-C interrupts the command (its output is discarded).
+
+        Examples:
+            prog('ls')('-l').capture().read_stdout()
+            (prog('make')() | prog('tail')('-5')).capture(merge_stderr=True).combined
+            prog('ls')().capture(pty=True).read_stdout()  # columns, as on the terminal
+        """
+        raise NotImplementedError("capture() only works in ShipShell REPL")
+
+    def fields(self) -> list[str]:
+        """Execute and return stdout split on whitespace into a flat list.
+
+        Returns:
+            Every whitespace-separated field of the output (empty if there was no output).
+
+        Examples:
+            prog('echo')('a b', 'c').fields()  # ['a', 'b', 'c']
+        """
+        raise NotImplementedError("fields() only works in ShipShell REPL")
+
+    def table(self, sep: str | None = None) -> list[list[str]]:
+        """Execute and return stdout as a list of rows, one per line.
+
+        Args:
+            sep: Separator between fields. By default lines are split on runs of whitespace.
+
+        Returns:
+            A list of rows, each a list of fields (empty if there was no output).
+
+        Raises:
+            ValueError: If sep is an empty string.
+
+        Examples:
+            prog('cat')('data.csv').table(',')  # [['name', 'age'], ['ada', '36']]
+        """
+        raise NotImplementedError("table() only works in ShipShell REPL")
+
     def __gt__(self, target: Any) -> ShipRunnable:
         """Redirect output to a file (truncate mode).
 
         Args:
             target: Either a string path or a file-like object with fileno()
+
+        Raises:
+            RedirectError: If a file-like target has no usable file descriptor.
         """
         raise NotImplementedError("Output redirection only works in ShipShell REPL")
 
+    def __lt__(self, source: Any) -> ShipRunnable:
+        """Redirect input from a file.
+
+        Stacks with output redirects, but Python treats `a < b > c` as a chained
+        comparison, so the input redirect needs parentheses:
+        `(prog('sort')() < 'in.txt') > 'out.txt'`.
+
+        Args:
+            source: Either a string path or a file-like object with fileno()
+
+        Raises:
+            RedirectError: If a file-like source has no usable file descriptor.
+        """
+        raise NotImplementedError("Input redirection only works in ShipShell REPL")
+
     def __rshift__(self, target: Any) -> ShipRunnable:
         """Redirect output to a file (append mode).
 
         Args:
             target: Either a string path or a file-like object with fileno()
+
+        Raises:
+            RedirectError: If a file-like target has no usable file descriptor.
         """
         raise NotImplementedError("Output redirection only works in ShipShell REPL")
 
+    def clobber(self) -> ShipRunnable:
+        """Force a file redirect to truncate its target even when noclobber is set.
+
+        Only valid on a runnable created by `>` or `>>` with a string path.
+
+        Returns:
+            A new ShipRunnable whose redirect ignores the noclobber option.
+
+        Raises:
+            TypeError: If this runnable is not a file redirect.
+
+        Examples:
+            set_option('noclobber', True)
+            (prog('echo')('Hello') > 'out.txt')()            # Fails if out.txt exists
+            (prog('echo')('Hello') > 'out.txt').clobber()()  # Always overwrites
+        """
+        raise NotImplementedError("clobber() only works in ShipShell REPL")
+
+    def create_mode(self, mode: int) -> ShipRunnable:
+        """Set the permissions a file redirect uses when it creates its target.
+
+        Existing files keep their permissions, and the process umask still applies.
+        Only valid on a runnable created by `>` or `>>` with a string path.
+
+        Args:
+            mode: Permission bits, e.g. 0o600.
+
+        Returns:
+            A new ShipRunnable whose redirect creates its file with the given mode.
+
+        Raises:
+            TypeError: If this runnable is not a file redirect.
+            ValueError: If mode is not a valid permission mode.
+
+        Examples:
+            (prog('echo')('hunter2') > 'secret.txt').create_mode(0o600)()
+        """
+        raise NotImplementedError("create_mode() only works in ShipShell REPL")
+
+    def pipe_to(
+        self, callback: Callable[[str], Any], abort_on_error: bool = False
+    ) -> ShipResult:
+        """Run this command and feed each line of its stdout to a Python callable.
+
+        The command's stdout is connected to a pipe that is read in the shell
+        process. Each line (without its trailing newline) is passed to the
+        callable as it arrives. stderr is left attached to the terminal.
+
+        Args:
+            callback: Called once per stdout line with the decoded line.
+            abort_on_error: If True, stop reading and re-raise the first exception
+                           raised by the callback. If False (the default), the
+                           traceback is printed and reading continues.
+
+        Returns:
+            The ShipResult of the command once it has finished.
+
+        Examples:
+            # Accumulate output in Python
+            numbers = []
+            prog('seq')('5').pipe_to(lambda line: numbers.append(int(line)))
+
+            # Stop on the first bad line
+            prog('cat')('data.txt').pipe_to(parse_line, abort_on_error=True)
+        """
+        raise NotImplementedError("pipe_to() only works in ShipShell REPL")
+
+    def map_lines(self, callback: Callable[[str], str | None]) -> ShipResult:
+        """Run this command and print each stdout line transformed by a Python callable.
+
+        A lightweight in-process sed/awk: lines are read in the shell process as they
+        arrive and the results are written to the shell's stdout. stderr is left
+        attached to the terminal.
+
+        Args:
+            callback: Called once per stdout line with the decoded line (without its
+                      newline). Return a string to print in its place, or None to drop
+                      the line. An exception (or any other return type) stops reading
+                      and is raised.
+
+        Returns:
+            The ShipResult of the command once it has finished.
+
+        Examples:
+            prog('cat')('names.txt').map_lines(str.upper)
+            prog('ls')().map_lines(lambda line: line if line.endswith('.rs') else None)
+        """
+        raise NotImplementedError("map_lines() only works in ShipShell REPL")
+
+    def on_output(self, callback: Callable[[str], Any]) -> ShipResult:
+        """Run this command, watching its stdout lines as they go to the terminal.
+
+        Output is written to the shell's stdout unchanged as it arrives, and each
+        line is also passed to the callback - handy for progress bars. stderr is
+        left attached to the terminal.
+
+        Args:
+            callback: Called once per stdout line with the decoded line (without its
+                      newline); its return value is ignored. If it raises, the error
+                      is reported, the callback isn't called again, and the command
+                      keeps running.
+
+        Returns:
+            The ShipResult of the command once it has finished.
+
+        Example:
+            prog('make')('all').on_output(lambda line: bar.update())
+        """
+        raise NotImplementedError("on_output() only works in ShipShell REPL")
+
     def with_env(self, **env_vars: Any) -> ShipRunnable:
         """Apply environment variable overlay to this runnable.
 
@@ -224,6 +867,38 @@ class ShipRunnable:
         """
         raise NotImplementedError("with_env() only works in ShipShell REPL")
 
+    def env_inherit(self, inherit: bool) -> ShipRunnable:
+        """Choose whether this runnable gets the shell's environment variables.
+
+        A middle ground between the full shell environment and listing every
+        variable: with False, the command only gets PATH, HOME and PWD plus any
+        with_env() overlay. Applies to every command in a pipeline it wraps.
+
+        Args:
+            inherit: True (the default for every runnable) passes all shell
+                     variables; False passes only the essential ones
+
+        Returns:
+            A new ShipRunnable; with_env() on it keeps the inherit setting.
+
+        Example:
+            prog('make')('test').env_inherit(False).with_env(CC='clang')()
+        """
+        raise NotImplementedError("env_inherit() only works in ShipShell REPL")
+
+    def env(self) -> dict[str, Any]:
+        """Inspect the environment overlay applied by with_env().
+
+        Returns:
+            The merged overlay as a dict, or an empty dict if this runnable
+            isn't wrapped in with_env().
+
+        Example:
+            prog('cmd').with_env(A='1').with_env(A='2', B='3').env()
+            # {'A': '2', 'B': '3'}
+        """
+        raise NotImplementedError("env() only works in ShipShell REPL")
+
 
 class ShipProgram:
     """Represents a program that can be executed."""
@@ -235,6 +910,16 @@ class ShipProgram:
         """Create a runnable command with the given arguments."""
         raise NotImplementedError("ShipProgram only works in ShipShell REPL")
 
+    def __getattr__(self, subcommand: str) -> ShipProgram:
+        """Pick a subcommand, so prog('git').status('-s') builds `git status -s`.
+
+        Underscores become dashes (prog('apt').list_upgradable builds
+        `apt list-upgradable`) and subcommands chain (prog('git').remote.add).
+        Real attributes like name take precedence, and names starting with an
+        underscore raise AttributeError as usual.
+        """
+        raise NotImplementedError("ShipProgram only works in ShipShell REPL")
+
 
 class ShipEnv:
     """Dictionary-like access to environment variables."""
@@ -246,13 +931,21 @@ class ShipEnv:
         return os.environ.get(key)
 
     def __setitem__(self, key: str, value: Any) -> None:
-        """Set an environment variable value."""
+        """Set an environment variable value.
+
+        The shell-managed `$` is always the shell's PID and can't be set, and `?`
+        and PPID only take integers; both raise ValueError.
+        """
         import os
 
         os.environ[key] = str(value)
 
     def __delitem__(self, key: str) -> None:
-        """Delete an environment variable."""
+        """Delete an environment variable.
+
+        OLDPWD goes back to having no value. The shell-managed `?`, `$` and PPID
+        (like readonly variables) can't be deleted and raise ValueError.
+        """
         import os
 
         del os.environ[key]
@@ -269,6 +962,12 @@ class ShipEnv:
 
         return len(os.environ)
 
+    def __iter__(self) -> Iterator[str]:
+        """Iterate over environment variable names."""
+        import os
+
+        return iter(list(os.environ))
+
     def keys(self) -> list[str]:
         """Get all environment variable names."""
         import os
@@ -293,19 +992,163 @@ class ShipEnv:
 
         return os.environ.get(key, default)
 
+    def get_in(self, path: list[Any], default: Any = None) -> Any:
+        """Get a value nested inside a list variable, e.g. one entry of PATH.
+
+        Environment values can be lists (of lists) but not dicts, so only list
+        indexes lead anywhere - a string key after the variable name gets the
+        default, as it would for a missing key.
+
+        Args:
+            path: The variable name followed by list indexes (negative ones count
+                  from the end, like Python).
+            default: Returned if the variable is unset or a step doesn't lead
+                     anywhere (out of range, not a list, or not an index).
+
+        Raises:
+            ValueError: If path is empty.
+
+        Example:
+            env['SEARCH'] = [['/a', '/b'], '/c']
+            env.get_in(['SEARCH', 0, -1])  # '/b'
+            env.get_in(['SEARCH', 'key'], 'none')  # 'none'
+        """
+        raise NotImplementedError("get_in() only works in ShipShell REPL")
+
+    def export_list(self) -> list[str]:
+        """Get the environment a command would receive, as sorted KEY=VALUE strings.
+
+        Shows exactly what child processes see: lists are joined with ':', None
+        becomes an empty value, and shell-managed values like $? are left out.
+
+        Example:
+            env['PATH'] = [Path('/usr/bin'), '/bin']
+            [e for e in env.export_list() if e.startswith('PATH=')]  # ['PATH=/usr/bin:/bin']
+        """
+        import os
 
-def prog(name: str) -> ShipProgram:
-    """Create a program reference by name."""
+        return sorted(f"{key}={value}" for key, value in os.environ.items())
+
+    def clear(self) -> None:
+        """Remove all environment variables except readonly ones.
+
+        Shell-managed values like $?, $$ and PPID are not affected.
+        """
+        import os
+
+        os.environ.clear()
+
+    def __ior__(self, other: dict[str, Any]) -> ShipEnv:
+        """Merge a dict into the environment: `shp.env |= {'DEBUG': '1'}`.
+
+        Every value is checked before any is set, so a bad value changes nothing.
+
+        Raises:
+            TypeError: If the right-hand side isn't a dict, or a value has an
+                       unsupported type.
+        """
+        if not isinstance(other, dict):
+            return NotImplemented
+        for key, value in other.items():
+            self[key] = value
+        return self
+
+    def pop(self, key: str, *default: Any) -> Any:
+        """Remove an environment variable and return its value.
+
+        Like dict.pop(): if the variable doesn't exist, the default is returned
+        when given, otherwise KeyError is raised.
+        """
+        import os
+
+        return os.environ.pop(key, *default)
+
+    def append(self, key: str, *entries: Any) -> None:
+        """Add entries to the end of a list variable such as PATH.
+
+        Entries the list already has are skipped, comparing them regardless of type
+        (and of a trailing slash), so '/usr/bin' is already in a PATH whose entries
+        are Path objects. An unset variable starts out as an empty list.
+
+        Example:
+            shp.env.append('PATH', '/opt/tools/bin', Path.home() / 'bin')
+        """
+        raise NotImplementedError("append() only works in ShipShell REPL")
+
+    def prepend(self, key: str, *entries: Any) -> None:
+        """Add entries to the front of a list variable such as PATH.
+
+        Like append(), but an entry the list already has is moved to the front
+        rather than skipped, so it takes precedence.
+
+        Example:
+            shp.env.prepend('PATH', Path.home() / '.local' / 'bin')
+        """
+        raise NotImplementedError("prepend() only works in ShipShell REPL")
+
+    def sync_os(self, enabled: bool = True, import_existing: bool = False) -> None:
+        """Mirror shell variable changes into os.environ.
+
+        Once enabled, every set or unset of a shell variable (including those
+        made by builtins like cd) is copied into os.environ as a string.
+
+        Child processes are unaffected either way: they always receive the
+        shell environment directly when they are executed.
+
+        Args:
+            enabled: Whether to mirror changes from now on.
+            import_existing: Copy variables already in os.environ that differ
+                from the shell into the shell environment first.
+        """
+        raise NotImplementedError("sync_os() only works in ShipShell REPL")
+
+    def os_sync_enabled(self) -> bool:
+        """Check if shell variable changes are mirrored into os.environ."""
+        raise NotImplementedError("os_sync_enabled() only works in ShipShell REPL")
+
+    def expand(self, template: str) -> str:
+        """Substitute shell variables into a template string.
+
+        Supports `$VAR`, `${VAR}` and `${VAR:-default}` (default used when VAR is
+        unset or empty). Values are converted as for child processes, unset
+        variables expand to nothing, and `$$` is a literal `$`.
+
+        Args:
+            template: Text containing variable references.
+
+        Returns:
+            The template with every reference replaced.
+
+        Raises:
+            ValueError: If a `${` is unterminated or names no variable.
+
+        Example:
+            env.expand("deploying to ${TARGET:-staging} as $USER")
+        """
+        raise NotImplementedError("expand() only works in ShipShell REPL")
+
+
+def prog(name: str | PathLike[str]) -> ShipProgram:
+    """Create a program reference by name.
+
+    A name containing `/` is run as that file; other names are searched for in
+    PATH. A path-like name (e.g. pathlib.Path) is always run as a file, so
+    prog(Path('tool')) runs ./tool.
+    """
     return ShipProgram(name)
 
 
-def cmd(prog: ShipProgram, *args: str) -> ShipRunnable:
-    """Create a runnable command with the given program and arguments."""
+def cmd(prog: ShipProgram | str | PathLike[str], *args: str) -> ShipRunnable:
+    """Create a runnable command with the given program (or name, as for prog()) and arguments."""
     raise NotImplementedError("cmd() only works in ShipShell REPL")
 
 
-def pipe(cmd1: ShipRunnable, cmd2: ShipRunnable, *cmds: ShipRunnable) -> ShipRunnable:
-    """Create a pipeline of commands."""
+def pipe(*cmds: ShipRunnable) -> ShipRunnable:
+    """Create a pipeline of commands.
+
+    A single command is returned unchanged, since a one-stage pipeline is
+    just that command. Calling pipe() with no commands raises ValueError.
+    """
     raise NotImplementedError("pipe() only works in ShipShell REPL")
 
 
@@ -319,19 +1162,30 @@ def shexec(runnable: ShipRunnable) -> ShipResult:
     return runnable()
 
 
-def capture(runnable: ShipRunnable) -> CapturedResult:
+def capture(
+    runnable: ShipRunnable, merge_stderr: bool = False, pty: bool = False
+) -> CapturedResult:
     """Execute a runnable and capture its stdout and stderr.
 
     This function executes the command and returns a CapturedResult object
     with file descriptors for both stdout and stderr. The streams are captured
-    independently and can be read separately.
+    independently and can be read separately. As when running the command
+    directly, $? is set to its exit code.
 
     Args:
         runnable: The ShipRunnable to execute (command, pipeline, etc.)
+        merge_stderr: Send stderr to the same stream as stdout, preserving the order of
+            writes like a terminal would. Read it with `result.combined`.
+        pty: Run with stdout on a pseudo-terminal, so tools that check for one (ls columns,
+            colours, line buffering) behave as they would interactively. With merge_stderr,
+            stderr goes to the terminal too.
 
     Returns:
         A CapturedResult containing exit_code and file descriptors for stdout/stderr.
 
+    Raises:
+        KeyboardInterrupt: If Ctrl-C interrupts the command (its output is discarded).
+
     Examples:
         # Capture both streams
         result = capture(prog('ls')('/'))
@@ -346,10 +1200,121 @@ def capture(runnable: ShipRunnable) -> CapturedResult:
         # Capture with environment overlay
         result = capture(prog('sh')('-c', 'echo $VAR').with_env(VAR='value'))
         print(result.read_stdout())
+
+        # Capture ls laid out in columns, as on the terminal
+        result = capture(prog('ls')(), pty=True)
     """
     raise NotImplementedError("capture() only works in ShipShell REPL")
 
 
+def capture_all(runnable: ShipRunnable) -> list[CapturedResult]:
+    """Execute a pipeline and capture the stdout and stderr of every stage.
+
+    Each stage's output is still passed on to the next stage, so the pipeline
+    behaves as usual while every intermediate result is kept for inspection.
+    A runnable that isn't a pipeline yields a single result.
+
+    Args:
+        runnable: The ShipRunnable to execute
+
+    Returns:
+        One CapturedResult per stage, in pipeline order.
+
+    Raises:
+        KeyboardInterrupt: If Ctrl-C interrupts the pipeline (all output is discarded).
+
+    Example:
+        first, second = capture_all(prog('echo')('hello world') | prog('tr')('a-z', 'A-Z'))
+        print(first.read_stdout())   # hello world
+        print(second.read_stdout())  # HELLO WORLD
+    """
+    raise NotImplementedError("capture_all() only works in ShipShell REPL")
+
+
+def watch(runnable: ShipRunnable, interval: float = 2.0, count: int | None = None) -> ShipResult:
+    """Re-run a runnable every `interval` seconds, like watch(1).
+
+    The screen is cleared before each run when stdout is a terminal. Watching
+    stops after `count` runs (if given) or when Ctrl-C is pressed, which returns
+    to the prompt instead of exiting the shell.
+
+    Args:
+        runnable: The ShipRunnable to run repeatedly
+        interval: Seconds to wait between runs
+        count: Maximum number of runs (None runs until Ctrl-C)
+
+    Returns:
+        The ShipResult of the last run.
+
+    Example:
+        watch(prog('df')('-h'), interval=5)
+    """
+    raise NotImplementedError("watch() only works in ShipShell REPL")
+
+
+def quote(s: str) -> str:
+    """Quote a string so a POSIX shell would read it as a single literal word.
+
+    Strings made only of safe characters are returned unchanged; anything
+    else is wrapped in single quotes (like shlex.quote).
+
+    Example:
+        quote('my file.txt')  # "'my file.txt'"
+    """
+    import shlex
+
+    return shlex.quote(s)
+
+
+def sh(command_line: str) -> ShipRunnable:
+    """Build a command from a shell-style command line.
+
+    Words are split like a POSIX shell would: whitespace separates words,
+    single quotes are literal, double quotes allow backslash escapes of
+    `"`, `\\`, `$` and backtick, a backslash outside quotes makes the next
+    character literal, adjacent parts join (`foo"bar"baz` is `foobarbaz`),
+    and empty quotes are an empty argument.
+
+    Not supported: globbing and `$VAR`/`~` expansion (kept literally), and
+    shell operators (`|`, `>`, `;`, `&`, ...) - compose runnables for those.
+
+    Args:
+        command_line: The program followed by its arguments.
+
+    Returns:
+        A ShipRunnable for the command (run it by calling it).
+
+    Raises:
+        ValueError: If the line is empty, has an unterminated quote or trailing
+            backslash, or uses a shell operator.
+
+    Examples:
+        sh("git commit -m 'first commit'")()
+        sh(r"ls my\\ dir") | sh("wc -l")
+    """
+    raise NotImplementedError("sh() only works in ShipShell REPL")
+
+
+def from_dict(d: dict[str, Any]) -> ShipRunnable:
+    """Rebuild a runnable from the description returned by ShipRunnable.to_dict().
+
+    Args:
+        d: A runnable description, e.g. loaded back from JSON.
+
+    Returns:
+        A ShipRunnable with the same structure as the one that was described.
+
+    Raises:
+        ValueError: If a "type" is unknown or a required key is missing or has
+            the wrong type.
+
+    Example:
+        saved = json.dumps((prog('ls')('-l') | prog('wc')('-l')).to_dict())
+        from_dict(json.loads(saved))()
+    """
+    raise NotImplementedError("from_dict() only works in ShipShell REPL")
+
+
 def get_stdout(runnable: ShipRunnable) -> str:
     """Execute a runnable and return its stdout as a string.
 
@@ -403,6 +1368,19 @@ def get_stderr(runnable: ShipRunnable) -> str:
     raise NotImplementedError("get_stderr() only works in ShipShell REPL")
 
 
+def sub_out(runnable: ShipRunnable) -> str:
+    """Command substitution, like $(...) in a POSIX shell.
+
+    Runs the runnable immediately and returns its stdout with trailing
+    newlines removed. Because it runs when called, the output is fixed when
+    the surrounding command is built, not when it is executed.
+
+    Example:
+        prog('echo')('Today is', sub_out(prog('date')('+%A')))()
+    """
+    raise NotImplementedError("sub_out() only works in ShipShell REPL")
+
+
 def get_env(key: str) -> Any:
     """Get an environment variable value."""
     import os
@@ -417,5 +1395,214 @@ def set_env(key: str, value: Any) -> None:
     os.environ[key] = str(value)
 
 
+def get_option(name: str) -> bool:
+    """Get the value of a shell option.
+
+    Supported options:
+        noclobber: `>` refuses to overwrite existing files (`>>` still appends)
+        pager: builtin output taller than the terminal (dirs, jobs) is shown through
+            $PAGER (default less); PAGER=cat turns it off
+        hashall: remember where commands were found in PATH (on by default; see hash, rehash)
+        errexit: sourced scripts and -c code stop at the first statement that leaves
+            last_exit() non-zero (the REPL itself keeps going)
+        ttyerrors: "command not found" and similar errors are also shown on the
+            terminal when stderr is captured or redirected (they still go to stderr)
+        debug: print each command, and the spec it runs as, to stderr before running it
+        dryrun: print each command to stderr instead of running it - nothing is started,
+            no directory is changed and no redirect file is created; it counts as succeeding.
+            setopt() still runs, so it can turn dryrun off again
+
+    Raises:
+        ValueError: If the option name is unknown.
+    """
+    raise NotImplementedError("get_option() only works in ShipShell REPL")
+
+
+def set_option(name: str, value: bool) -> None:
+    """Set the value of a shell option.
+
+    Args:
+        name: The option name (see get_option() for the supported options).
+        value: True to enable the option, False to disable it.
+
+    Raises:
+        ValueError: If the option name is unknown.
+
+    Examples:
+        set_option('noclobber', True)
+    """
+    raise NotImplementedError("set_option() only works in ShipShell REPL")
+
+
+def get_pipe_size() -> int | None:
+    """Get the buffer size set with set_pipe_size().
+
+    Returns:
+        The size in bytes, or None if pipes keep the system default.
+    """
+    raise NotImplementedError("get_pipe_size() only works in ShipShell REPL")
+
+
+def set_pipe_size(size: int | None) -> None:
+    """Set the buffer size of the pipes between pipeline stages.
+
+    A larger buffer (the Linux default is 64KB) lets a fast producer run further
+    ahead of its consumer, which can speed up data-heavy pipelines. Only Linux can
+    resize pipes, so this has no effect elsewhere, and sizes above the system limit
+    (/proc/sys/fs/pipe-max-size) are reduced to it.
+
+    Args:
+        size: Buffer size in bytes, or None to go back to the system default.
+
+    Raises:
+        ValueError: If size is 0.
+
+    Examples:
+        set_pipe_size(1024 * 1024)
+        (prog('cat')('big.log') | prog('gzip')()) > 'big.log.gz'
+    """
+    raise NotImplementedError("set_pipe_size() only works in ShipShell REPL")
+
+
+def jobs() -> list[dict[str, Any]]:
+    """List background jobs started with ShipRunnable.background().
+
+    Foreground commands stopped by a signal (e.g. SIGSTOP) are listed as jobs too.
+    Each job is a dict with 'id', 'pid', 'state' ('Running', 'Stopped', 'Done' or 'Exit N')
+    and 'command'. Finished jobs are included once and then removed from the job table.
+
+    Example:
+        prog('sleep')('30').background()
+        jobs()  # [{'id': 1, 'pid': 4242, 'state': 'Running', 'command': 'sleep 30'}]
+    """
+    raise NotImplementedError("jobs() only works in ShipShell REPL")
+
+
+def last_exit() -> int:
+    """Get the exit status of the most recently executed command.
+
+    Equivalent to env['?'] but without the awkward string key.
+
+    Examples:
+        prog('false')()
+        last_exit()  # 1
+    """
+    raise NotImplementedError("last_exit() only works in ShipShell REPL")
+
+
+def pid() -> int:
+    """Get the shell's process ID (equivalent to env['$'])."""
+    import os
+
+    return os.getpid()
+
+
+def ppid() -> int:
+    """Get the shell's parent process ID (equivalent to env['PPID'])."""
+    import os
+
+    return os.getppid()
+
+
+def dirstack() -> list[Path]:
+    """Get the directory stack saved by pushd, in the order dirs() lists it.
+
+    The current directory isn't included, and popd() returns to the last entry.
+    The list is a copy - the stack is read-only and only changes through pushd
+    and popd.
+
+    Examples:
+        pushd('/tmp')
+        pushd('/var')
+        dirstack()  # [PosixPath('/home/me'), PosixPath('/tmp')]
+    """
+    raise NotImplementedError("dirstack() only works in ShipShell REPL")
+
+
+def reload() -> None:
+    """Re-run the shell's Python modules (core, shp.builtins) as fresh modules.
+
+    For working on the shell's Python side: if $SHIP_SOURCE_DIR names a directory
+    (python/shell in a checkout), the sources are read from there, so edits take
+    effect without restarting. Otherwise the copies built into the shell are re-run.
+
+    Names already imported from the old modules keep pointing at them, so import
+    them again afterwards.
+
+    Raises:
+        OSError: If a source file can't be read from $SHIP_SOURCE_DIR.
+
+    Example:
+        env['SHIP_SOURCE_DIR'] = '/path/to/ShipShell/python/shell'
+        reload()
+        from shp.builtins import *
+    """
+    raise NotImplementedError("reload() only works in ShipShell REPL")
+
+
+def terminal_size() -> tuple[int, int]:
+    """Get the size of the terminal.
+
+    When stdout is a terminal, the shell also keeps $COLUMNS and $LINES set to
+    its size, updating them when the window is resized.
+
+    Returns:
+        (columns, rows). Without a terminal, $COLUMNS and $LINES are used if set,
+        falling back to 80x24.
+
+    Example:
+        cols, rows = terminal_size()
+    """
+    raise NotImplementedError("terminal_size() only works in ShipShell REPL")
+
+
+def add_path(dir: str | Path) -> None:
+    """Put a directory at the front of sys.path so modules in it can be imported.
+
+    The directory is made absolute (after ~ expansion); if it's already on
+    sys.path it moves to the front. At startup, the directories listed in
+    $SHIP_PYTHONPATH (colon-separated) are added the same way.
+
+    Args:
+        dir: Directory containing Python modules or packages.
+
+    Example:
+        add_path("~/.config/ship/lib")
+        import my_helpers
+    """
+    raise NotImplementedError("add_path() only works in ShipShell REPL")
+
+
+def mktemp(dir: str | Path | None = None, suffix: str = "", cleanup: bool = True) -> Path:
+    """Create a new, empty temporary file and return its path.
+
+    The file is created with mode 0600 under a unique name (shp.XXXXXXXXXX).
+
+    Args:
+        dir: Directory to create the file in (defaults to $TMPDIR, then /tmp).
+        suffix: Text appended to the generated name, e.g. '.json'.
+        cleanup: Remove the file automatically when the shell exits.
+
+    Example:
+        scratch = mktemp(suffix='.log')
+        (prog('make')() > str(scratch))()
+    """
+    raise NotImplementedError("mktemp() only works in ShipShell REPL")
+
+
+def mktemp_dir(dir: str | Path | None = None, suffix: str = "", cleanup: bool = True) -> Path:
+    """Create a new, empty temporary directory and return its path.
+
+    The directory is created with mode 0700. With cleanup enabled it is
+    removed along with everything inside it when the shell exits.
+
+    Args:
+        dir: Directory to create it in (defaults to $TMPDIR, then /tmp).
+        suffix: Text appended to the generated name.
+        cleanup: Remove the directory automatically when the shell exits.
+    """
+    raise NotImplementedError("mktemp_dir() only works in ShipShell REPL")
+
+
 # Global environment variable dictionary (stub implementation)
 env = ShipEnv()
@@ -10,7 +10,7 @@ from pathlib import Path
 from shp import prog, ShipRunnable
 
 # Control what gets exported with "from ... import *"
-__all__ = ["cd", "pwd", "pushd", "popd", "dirs", "exit", "quit", "which"]
+__all__ = ["cd", "pwd", "pushd", "popd", "dirs", "exit", "quit", "which", "setopt", "readonly"]
 
 
 # Builtin command wrappers using prog() for composability
@@ -63,7 +63,8 @@ def which(*programs: str, show_all: bool = False, silent: bool = False) -> ShipR
     Locate a program file in the user's path.
 
     Args:
-        *programs: One or more program names to search for
+        *programs: One or more program names to search for - a glob pattern (*, ?,
+                   [...]) lists every matching executable in PATH
         show_all: If True, list all instances found (instead of just the first)
         silent: If True, silent mode - no output, just return exit code
 
@@ -76,6 +77,7 @@ def which(*programs: str, show_all: bool = False, silent: bool = False) -> ShipR
         which("ls", "cat", "grep")           # Find multiple programs
         which("python3", show_all=True)      # Find all instances of python3
         which("grep", silent=True)           # Silent mode, check if exists
+        which("py*")                         # Every executable starting with py
     """
     args = []
     if show_all:
@@ -85,3 +87,45 @@ def which(*programs: str, show_all: bool = False, silent: bool = False) -> ShipR
     args.extend(programs)
 
     return prog("which")(*args)
+
+
+def setopt(*names: str, enabled: bool = True) -> ShipRunnable:
+    """
+    Enable or disable shell options (the `set -o` / `set +o` builtin).
+
+    Named setopt to avoid shadowing Python's set type.
+
+    Args:
+        *names: Option names to change - with no names, lists all options
+        enabled: If True, enable the options (set -o); if False, disable them (set +o)
+
+    Examples:
+        setopt()                              # List options and their states
+        setopt("pipefail")                    # Enable pipefail
+        setopt("noclobber", enabled=False)    # Disable noclobber
+    """
+    if not names:
+        return prog("set")("-o")
+
+    flag = "-o" if enabled else "+o"
+    args = []
+    for name in names:
+        args.extend([flag, name])
+
+    return prog("set")(*args)
+
+
+def readonly(*names: str) -> ShipRunnable:
+    """
+    Mark shell variables readonly so they can no longer be set or unset.
+
+    Args:
+        *names: Variable names, or NAME=VALUE to assign before locking - with no
+                names, lists all readonly variables
+
+    Examples:
+        readonly()                    # List readonly variables
+        readonly("EDITOR")            # Lock the current value of EDITOR
+        readonly("MODE=production")   # Assign and lock
+    """
+    return prog("readonly")(*names)
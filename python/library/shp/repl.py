@@ -19,6 +19,12 @@ __all__ = [
     "get_continuation",
     "set_right_prompt",
     "get_right_prompt",
+    "set_autorun",
+    "get_autorun",
+    "set_history_control",
+    "get_history_control",
+    "register_completions",
+    "subshell",
     "on",
     "off",
     "list_hooks",
@@ -62,12 +68,16 @@ class REPLHook(IntEnum):
 def set_prompt(value: str) -> None:
     """Set the primary prompt string.
 
+    ANSI color codes can be used directly; they don't count toward the prompt's
+    width, so bash-style `\\[`/`\\]` markers aren't needed (they're removed).
+
     Args:
         value: The new prompt string to display.
 
     Examples:
         set_prompt(">>> ")
         set_prompt("🚀 ")
+        set_prompt("\\x1b[32mship\\x1b[0m> ")
     """
     raise NotImplementedError("set_prompt() only works in ShipShell REPL")
 
@@ -148,7 +158,143 @@ def get_right_prompt() -> str:
     raise NotImplementedError("get_right_prompt() only works in ShipShell REPL")
 
 
-def on(hook: REPLHook, callback: Callable) -> int:
+def set_autorun(enabled: bool) -> None:
+    """Enable or disable auto-running of bare ShipRunnable expressions.
+
+    When enabled (the default), a REPL line that evaluates to a ShipRunnable
+    is executed immediately. When disabled, the runnable's repr is printed
+    instead, which is handy when building commands interactively.
+
+    Args:
+        enabled: Whether bare ShipRunnable expressions should be executed.
+
+    Examples:
+        set_autorun(False)
+        prog('ls')('-la')  # Prints the runnable instead of running it
+    """
+    raise NotImplementedError("set_autorun() only works in ShipShell REPL")
+
+
+def get_autorun() -> bool:
+    """Check whether bare ShipRunnable expressions are auto-run.
+
+    Returns:
+        True if bare ShipRunnable expressions are executed, False otherwise.
+    """
+    raise NotImplementedError("get_autorun() only works in ShipShell REPL")
+
+
+def set_history_control(
+    ignoredups: bool | None = None, ignorespace: bool | None = None
+) -> None:
+    """Choose which statements are kept out of history (like bash's HISTCONTROL).
+
+    Both are off by default. History is what fc() edits; Up-arrow recall never
+    repeats consecutive duplicates either way.
+
+    Args:
+        ignoredups: Don't record a statement identical to the previous one.
+        ignorespace: Don't record statements typed with leading whitespace. The
+                     whitespace is dropped before the statement runs.
+        Settings left as None are unchanged.
+
+    Examples:
+        set_history_control(ignoredups=True, ignorespace=True)
+        set_history_control(ignorespace=False)
+    """
+    raise NotImplementedError("set_history_control() only works in ShipShell REPL")
+
+
+def get_history_control() -> dict[str, bool]:
+    """Get the history control settings.
+
+    Returns:
+        A dict with the "ignoredups" and "ignorespace" settings.
+
+    Examples:
+        get_history_control()  # {'ignoredups': False, 'ignorespace': False}
+    """
+    raise NotImplementedError("get_history_control() only works in ShipShell REPL")
+
+
+def register_completions(command: str, options: list[str]) -> None:
+    """Register the subcommands and flags offered when tab-completing a command.
+
+    Once registered, pressing Tab while typing an argument of the command
+    offers every option that starts with the partially typed word. The
+    command may be written bare (`git(...)`) or via prog()/cmd().
+    Registering a command again replaces its options.
+
+    Args:
+        command: The program name, e.g. 'git'.
+        options: Subcommands and flags to offer.
+
+    Examples:
+        register_completions('git', ['checkout', 'commit', '--help', '--version'])
+        # prog('git')('che<Tab>  ->  prog('git')('checkout
+        # prog('git')('--<Tab>   ->  offers --help, --version
+    """
+    raise NotImplementedError("register_completions() only works in ShipShell REPL")
+
+
+def complete(line: str, cursor: int) -> list[str]:
+    """Get the completions Tab would offer, without a terminal.
+
+    Useful for testing registered completions or building custom UIs.
+    Words containing a '/' that aren't arguments of a registered command
+    complete file names, with a trailing '/' on directories.
+
+    Args:
+        line: The input line.
+        cursor: Cursor position in characters; positions past the end complete at the end.
+
+    Returns:
+        The suggested replacements for the word before the cursor.
+
+    Examples:
+        register_completions('git', ['checkout', 'commit'])
+        complete("git('ch", 7)  # ['checkout']
+        complete('ls /tm', 6)  # ['/tmp/']
+    """
+    raise NotImplementedError("complete() only works in ShipShell REPL")
+
+
+def edit_last() -> int:
+    """Edit the previous statement in an editor, then run what was saved.
+
+    The editor is $FCEDIT or $EDITOR, falling back to vi and then nano. Saving an
+    empty file cancels. The fc builtin does the same from the prompt.
+
+    Returns:
+        The exit status of the last command run, or 1 if there was no previous
+        statement, no editor could be found, or the edited code raised.
+
+    Examples:
+        edit_last()
+    """
+    raise NotImplementedError("edit_last() only works in ShipShell REPL")
+
+
+def subshell() -> None:
+    """Start a nested REPL, e.g. to inspect variables partway through a function.
+
+    The nested REPL has its own input buffer but shares the namespace, history,
+    prompts and hooks. Press Ctrl+D to return to the code that called it
+    (exit() still exits the whole shell).
+
+    Raises:
+        RuntimeError: If called outside the interactive REPL, or from a REPL hook.
+
+    Examples:
+        def deploy():
+            build()
+            subshell()  # Look around before continuing
+            upload()
+    """
+    raise NotImplementedError("subshell() only works in ShipShell REPL")
+
+
+def on(hook: REPLHook, callback: Callable, *, priority: int = 0) -> int:
     """Register a callback for a REPL hook.
 
     The callback will be invoked at the specified hook point. Each hook type
@@ -157,6 +303,8 @@ def on(hook: REPLHook, callback: Callable) -> int:
     Args:
         hook: The hook point to register the callback for.
         callback: The function to call when the hook fires.
+        priority: Hooks with a higher priority fire first. Hooks with the
+            same priority fire in registration order.
 
     Returns:
         A unique ID for this hook registration, which can be used to
@@ -173,6 +321,9 @@ def on(hook: REPLHook, callback: Callable) -> int:
         def on_continuation(prev_prompt, buffer):
             print(f"Continuing from {prev_prompt}, buffer: {buffer}")
         id3 = on(REPLHook.BeforeContinuation, on_continuation)
+
+        # Logging hook that fires before every other BeforeExecute hook
+        id4 = on(REPLHook.BeforeExecute, log_command, priority=100)
     """
     raise NotImplementedError("on() only works in ShipShell REPL")
 
@@ -204,7 +355,7 @@ def off(hook: REPLHook, id: int) -> bool:
 def list_hooks(hook: REPLHook) -> list[int]:
     """List all registered hook IDs for a specific hook type.
 
-    Returns the IDs in registration order.
+    Returns the IDs in firing order (by priority, then registration order).
 
     Args:
         hook: The hook type to list IDs for.
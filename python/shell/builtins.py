@@ -10,16 +10,38 @@ from pathlib import Path
 from shp import prog, ShipRunnable
 
 # Control what gets exported with "from ... import *"
-__all__ = ["cd", "pwd", "pushd", "popd", "dirs", "exit", "quit", "which"]
+__all__ = [
+    "cd",
+    "pwd",
+    "pushd",
+    "popd",
+    "dirs",
+    "exit",
+    "quit",
+    "which",
+    "setopt",
+    "readonly",
+    "declare",
+    "disown",
+    "fg",
+    "fc",
+    "rehash",
+    "hashcmd",
+]
 
 
 # Builtin command wrappers using prog() for composability
 def cd(path: str | Path | None = None) -> ShipRunnable:
-    """Change directory. No args = HOME, '-' = OLDPWD, path = specific directory."""
+    """Change directory. No args = HOME, '-' = OLDPWD, path = specific directory.
+
+    A Path is always taken literally, so cd(Path('-')) enters a directory named '-'.
+    """
     if path is None:
         return prog("cd")()
+    elif isinstance(path, Path):
+        return prog("cd")("--", str(path))
     else:
-        return prog("cd")(str(path))
+        return prog("cd")(path)
 
 
 def pwd(physical: bool = False) -> ShipRunnable:
@@ -63,7 +85,8 @@ def which(*programs: str, show_all: bool = False, silent: bool = False) -> ShipR
     Locate a program file in the user's path.
 
     Args:
-        *programs: One or more program names to search for
+        *programs: One or more program names to search for - a glob pattern (*, ?,
+                   [...]) lists every matching executable in PATH
         show_all: If True, list all instances found (instead of just the first)
         silent: If True, silent mode - no output, just return exit code
 
@@ -76,6 +99,7 @@ def which(*programs: str, show_all: bool = False, silent: bool = False) -> ShipR
         which("ls", "cat", "grep")           # Find multiple programs
         which("python3", show_all=True)      # Find all instances of python3
         which("grep", silent=True)           # Silent mode, check if exists
+        which("py*")                         # Every executable starting with py
     """
     args = []
     if show_all:
@@ -85,3 +109,146 @@ def which(*programs: str, show_all: bool = False, silent: bool = False) -> ShipR
     args.extend(programs)
 
     return prog("which")(*args)
+
+
+def setopt(*names: str, enabled: bool = True) -> ShipRunnable:
+    """
+    Enable or disable shell options (the `set -o` / `set +o` builtin).
+
+    Named setopt to avoid shadowing Python's set type.
+
+    Args:
+        *names: Option names to change - with no names, lists all options
+        enabled: If True, enable the options (set -o); if False, disable them (set +o)
+
+    Examples:
+        setopt()                              # List options and their states
+        setopt("pipefail")                    # Enable pipefail
+        setopt("noclobber", enabled=False)    # Disable noclobber
+    """
+    if not names:
+        return prog("set")("-o")
+
+    flag = "-o" if enabled else "+o"
+    args = []
+    for name in names:
+        args.extend([flag, name])
+
+    return prog("set")(*args)
+
+
+def readonly(*names: str) -> ShipRunnable:
+    """
+    Mark shell variables readonly so they can no longer be set or unset.
+
+    Args:
+        *names: Variable names, or NAME=VALUE to assign before locking - with no
+                names, lists all readonly variables
+
+    Examples:
+        readonly()                    # List readonly variables
+        readonly("EDITOR")            # Lock the current value of EDITOR
+        readonly("MODE=production")   # Assign and lock
+    """
+    return prog("readonly")(*names)
+
+
+def declare(*args: str) -> ShipRunnable:
+    """
+    Set shell variables with an explicit type, or list variables with their types.
+
+    Args:
+        *args: Optional flags followed by NAME=VALUE assignments (or names to list):
+               "-i" stores an integer, "-a" a list split on ':', "-l" a lowercased
+               string, and "-p" lists the named variables. Without a flag the type
+               is detected as for any assignment. With no names, lists all variables
+               as `[type] NAME=value`.
+
+    Examples:
+        declare()                       # List variables with their types
+        declare("-i", "RETRIES=3")      # Always an integer
+        declare("-a", "DIRS=src")       # A one-item list
+        declare("-l", "MODE=Release")   # The string "release"
+    """
+    return prog("declare")(*args)
+
+
+def fc() -> ShipRunnable:
+    """
+    Edit the previous statement in $FCEDIT or $EDITOR (falling back to vi, then nano),
+    then run the saved result.
+
+    Examples:
+        fc()()
+    """
+    return prog("fc")()
+
+
+def disown(*jobs: str | int) -> ShipRunnable:
+    """
+    Remove background jobs from the job table without killing them.
+
+    Disowned jobs keep running but are no longer listed by jobs(), nor sent SIGHUP on exit.
+
+    Args:
+        *jobs: Job specs like "%1" (or job ids), or "-a" for every job
+
+    Examples:
+        disown("%1")()                  # Stop tracking job 1
+        disown("-a")()                  # Stop tracking all jobs
+    """
+    return prog("disown")(*(str(job) for job in jobs))
+
+
+def fg(job: str | int | None = None) -> ShipRunnable:
+    """
+    Continue a stopped or background job in the foreground and wait for it.
+
+    A command suspended with Ctrl-Z becomes a stopped job that fg resumes.
+
+    Args:
+        job: Job spec like "%1" (or a job id); defaults to the job with the highest id
+
+    Examples:
+        fg()()                          # Resume the latest job
+        fg("%2")()                      # Resume job 2
+    """
+    if job is None:
+        return prog("fg")()
+    return prog("fg")(str(job))
+
+
+def rehash() -> ShipRunnable:
+    """
+    Forget where commands were found in PATH, so each is searched for again on its next run.
+
+    Needed after installing a program that shadows one already run (PATH changes rehash
+    automatically).
+
+    Examples:
+        rehash()()
+    """
+    return prog("rehash")()
+
+
+def hashcmd(*programs: str, reset: bool = False) -> ShipRunnable:
+    """
+    Remember where programs are found in PATH, or list those remembered (the hash builtin).
+
+    Named hashcmd to avoid shadowing Python's hash function.
+
+    With no arguments, prints each remembered program as name=path. Fails if a named
+    program isn't in PATH, so hashcmd("git")() works as a portable "is it installed?" check.
+
+    Args:
+        *programs: Program names to search PATH for now and remember
+        reset: If True, forget every remembered program first (like rehash)
+
+    Examples:
+        hashcmd()()                   # List remembered programs
+        hashcmd("git", "make")()      # Remember git and make, failing if either is missing
+        hashcmd(reset=True)()         # Forget everything
+    """
+    args = ["-r"] if reset else []
+    args.extend(programs)
+    return prog("hash")(*args)
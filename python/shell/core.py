@@ -5,10 +5,12 @@ This module contains fundamental shell features implemented in Python,
 as opposed to Rust builtins or ergonomic wrappers.
 """
 
+import ast
 import io
 import keyword
 import os
 import sys
+import traceback
 from pathlib import Path
 from typing import IO
 
@@ -22,19 +24,27 @@ except ImportError:
     )
     sys.exit(1)
 
-__all__ = ["source", "wire_path_programs"]
+__all__ = ["source", "run_code", "wire_path_programs"]
 
 
-def source(file: str | Path | IO[str], scope: str | None = None) -> None:
+def source(file: str | Path | IO[str], scope: str | None = None) -> bool:
     """
     Execute Python code from a file or file-like object in the REPL's namespace.
 
     This is a Python-specific feature, not a shell builtin.
 
+    Errors in the sourced code don't propagate - they are reported as a
+    `file:line: message` diagnostic on stderr and the REPL keeps running.
+    With the errexit option on, the file stops at the first statement that
+    leaves $? non-zero.
+
     Args:
         file: Path to a Python file, or a file-like object with a read() method
         scope: Optional module to run the code in
 
+    Returns:
+        True if the code ran without errors, False otherwise (including errexit stopping it)
+
     Example:
         source('~/.shipshellrc')
         source(Path('/etc/shipshell/config.py'))
@@ -42,11 +52,70 @@ def source(file: str | Path | IO[str], scope: str | None = None) -> None:
     if isinstance(file, (str, Path)):
         # Resolve to absolute path so cd() calls in the file don't break relative paths
         abs_path = Path(file).expanduser().resolve()
+        filename = str(abs_path)
         with open(abs_path) as f:
-            exec(f.read(), __main__.__dict__)
+            code = f.read()
     else:
         # File-like object
-        exec(file.read(), __main__.__dict__)
+        filename = getattr(file, "name", "<stream>")
+        code = file.read()
+
+    try:
+        return run_code(code, filename)
+    except SyntaxError as e:
+        print(f"{filename}:{e.lineno}: SyntaxError: {e.msg}", file=sys.stderr)
+        return False
+    except Exception as e:
+        print(f"{filename}:{_error_line(e, filename)}: {type(e).__name__}: {e}", file=sys.stderr)
+        return False
+
+
+def run_code(code: str, filename: str) -> bool:
+    """
+    Execute Python code in the REPL's namespace one top-level statement at a time.
+
+    With the errexit option on, $? is checked after each statement and the code
+    stops at the first one that leaves it non-zero (like `set -e`).
+
+    Args:
+        code: The Python source to run
+        filename: Name used in tracebacks and error messages
+
+    Returns:
+        True if every statement ran, False if errexit stopped the code early
+    """
+    import shp
+
+    # errexit judges the commands this code runs, not whatever ran before it
+    if shp.get_option("errexit"):
+        shp.env["?"] = 0
+
+    # Compiling with the real filename makes tracebacks point into the sourced file
+    tree = ast.parse(code, filename)
+    for statement in tree.body:
+        module = ast.Module(body=[statement], type_ignores=[])
+        try:
+            exec(compile(module, filename, "exec"), __main__.__dict__)
+        except BaseException as e:
+            # Tracebacks should start in the code being run, not here
+            if e.__traceback__ is not None:
+                e.__traceback__ = e.__traceback__.tb_next
+            raise
+        if shp.get_option("errexit") and shp.last_exit() != 0:
+            return False
+    return True
+
+
+def _error_line(error: BaseException, filename: str) -> int | str:
+    """Find the line in `filename` where an exception was raised ('?' if not in the traceback)."""
+    frames = [
+        frame
+        for frame in traceback.extract_tb(error.__traceback__)
+        if frame.filename == filename
+    ]
+    if not frames or frames[-1].lineno is None:
+        return "?"
+    return frames[-1].lineno
 
 
 def wire_path_programs(module: str | None = None) -> None:
@@ -86,7 +155,17 @@ def wire_path_programs(module: str | None = None) -> None:
         "exit",
         "quit",
         "which",
+        "set",
+        "readonly",
+        "declare",
+        "typeset",
         "source",
+        "jobs",
+        "disown",
+        "fg",
+        "fc",
+        "rehash",
+        "hash",
     }
 
     # Get PATH from environment
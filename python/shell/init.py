@@ -6,6 +6,32 @@ shell environment.
 """
 
 
+# Python shares stdout and stderr with the commands the shell runs, so its output has to reach
+# them right away (as with python -u): buffered output would come out of order with theirs, or
+# be lost when the shell exits with output still buffered
+def write_through_stdio():
+    import io
+    import sys
+
+    for name in ("stdout", "stderr"):
+        stream = getattr(sys, name)
+        if stream is None:
+            continue
+        raw = io.FileIO(stream.fileno(), "w", closefd=False)
+        unbuffered = io.TextIOWrapper(
+            raw, encoding=stream.encoding, errors=stream.errors, write_through=True
+        )
+        setattr(sys, name, unbuffered)
+
+
+write_through_stdio()
+del write_through_stdio
+
+
+# Set by the shell from the command line: False for --norc, a path for --rcfile, None otherwise
+_rc_file = globals().pop("_ship_rc_file", None)
+
+
 # Load user init file if it exists (after imports so init file has access to everything)
 def load_user_init_file():
     from core import source
@@ -13,8 +39,26 @@ def load_user_init_file():
     from pathlib import Path
     import sys
 
-    config_init = Path.home() / ".config" / "ship" / "init.py"
-    home_init = Path.home() / "init.py"
+    # --rcfile replaces the usual init file, and must exist
+    if _rc_file is not None:
+        rc_path = Path(_rc_file).expanduser()
+        if not rc_path.is_file():
+            print(f"ship_shell: {_rc_file}: No such file", file=sys.stderr)
+            return
+        try:
+            source(rc_path)
+        except Exception as e:
+            print(f"Error loading init file: {e}", file=sys.stderr)
+        return
+
+    # Without a home directory there's no init file to look for
+    try:
+        home = Path.home()
+    except RuntimeError:
+        return
+
+    config_init = home / ".config" / "ship" / "init.py"
+    home_init = home / "init.py"
 
     try:
         if config_init.exists():
@@ -26,8 +70,33 @@ def load_user_init_file():
         print(f"Error loading init file: {e}", file=sys.stderr)
 
 
-# Load RC file now that everything is imported
-load_user_init_file()
+# Load the file named by $ENV if it exists (POSIX shells source it for interactive sessions)
+def load_env_file():
+    from core import source
+
+    from pathlib import Path
+    import shp
+    import sys
+
+    env_file = shp.env.get("ENV")
+    if env_file is None or env_file == "":
+        return
+
+    try:
+        env_path = Path(env_file).expanduser()
+        if env_path.is_file():
+            source(env_path)
+    except Exception as e:
+        # Log the error but continue - don't crash the shell on bad ENV file
+        print(f"Error loading ENV file {env_file}: {e}", file=sys.stderr)
+
+
+# Load RC file now that everything is imported (--norc skips both)
+if _rc_file is not False:
+    load_user_init_file()
+    load_env_file()
 
 # Remove things we don't want exposed in the global namespace
 del load_user_init_file
+del load_env_file
+del _rc_file
@@ -3,8 +3,77 @@ mod repl;
 mod shell;
 
 use anyhow::Result;
+use py_bindings::InitFile;
+use std::io::IsTerminal;
+
+const USAGE: &str = "usage: ship_shell [--norc | --rcfile FILE] [-c CODE | SCRIPT] [ARG...]";
+
+/// How the shell was asked to run
+enum Mode {
+    /// No arguments: start the REPL (or run stdin as a script when it isn't a terminal)
+    Interactive,
+    /// `-c CODE [ARG...]`: run the code as if it were entered at the prompt
+    Command { code: String, args: Vec<String> },
+    /// `SCRIPT [ARG...]`: run a Python file
+    Script { path: String, args: Vec<String> },
+}
+
+/// Work out the init file and mode from the command line arguments (without the program name)
+/// Startup options (--norc, --rcfile FILE) come before the mode, as in bash
+fn parse_args(mut args: Vec<String>) -> Result<(InitFile, Mode), String> {
+    let mut init_file = InitFile::Default;
+    while let Some(option) = args.first() {
+        match option.as_str() {
+            "--norc" => init_file = InitFile::Skip,
+            "--rcfile" => {
+                let Some(path) = args.get(1) else {
+                    return Err("--rcfile: option requires an argument".to_string());
+                };
+                init_file = InitFile::Path(path.clone());
+                args.remove(0);
+            }
+            _ => break,
+        }
+        args.remove(0);
+    }
+
+    Ok((init_file, parse_mode(args)?))
+}
+
+/// Work out the mode from the arguments after any startup options
+fn parse_mode(mut args: Vec<String>) -> Result<Mode, String> {
+    if args.is_empty() {
+        return Ok(Mode::Interactive);
+    }
+
+    let first = args.remove(0);
+    match first.as_str() {
+        "-c" => {
+            if args.is_empty() {
+                return Err("-c: option requires an argument".to_string());
+            }
+            let code = args.remove(0);
+            Ok(Mode::Command { code, args })
+        }
+        "-h" | "--help" => {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        option if option.starts_with('-') => Err(format!("{}: invalid option", option)),
+        _ => Ok(Mode::Script { path: first, args }),
+    }
+}
 
 fn main() -> Result<()> {
+    let (init_file, mode) = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("ship_shell: {}", message);
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
     // Stage 1: Initialize Python runtime (bare interpreter)
     py_bindings::initialize_runtime()?;
 
@@ -12,8 +81,97 @@ fn main() -> Result<()> {
     shell::initialize_environment();
 
     // Stage 2: Configure Python environment and register REPL dependencies
-    py_bindings::configure_repl()?;
+    py_bindings::configure_repl(init_file)?;
+
+    // Run the REPL, or the code/script given on the command line
+    let result = match mode {
+        // A script piped into the shell runs as a whole - the line editor needs a terminal
+        Mode::Interactive if !std::io::stdin().is_terminal() => Ok(py_bindings::run_stdin()),
+        Mode::Interactive => repl::run().map(|()| 0),
+        Mode::Command { code, args } => Ok(py_bindings::run_command(&code, &args)),
+        Mode::Script { path, args } => Ok(py_bindings::run_script(&path, &args)),
+    };
+
+    // Hang up background jobs and remove scratch files created with mktemp()
+    shell::shutdown();
+
+    std::process::exit(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_mode_without_arguments_is_interactive() {
+        assert!(matches!(parse_mode(args(&[])), Ok(Mode::Interactive)));
+    }
+
+    #[test]
+    fn parse_mode_command() {
+        let mode = parse_mode(args(&["-c", "print(1)", "a", "b"]));
+        assert!(
+            matches!(mode, Ok(Mode::Command { code, args }) if code == "print(1)" && args == ["a", "b"])
+        );
+        assert!(parse_mode(args(&["-c"])).is_err());
+    }
+
+    #[test]
+    fn parse_mode_script() {
+        let mode = parse_mode(args(&["run.py", "-c", "x"]));
+        assert!(
+            matches!(mode, Ok(Mode::Script { path, args }) if path == "run.py" && args == ["-c", "x"])
+        );
+    }
+
+    #[test]
+    fn parse_mode_rejects_unknown_options() {
+        assert_eq!(
+            parse_mode(args(&["-x"])).err(),
+            Some("-x: invalid option".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_args_startup_options() {
+        assert!(matches!(
+            parse_args(args(&[])),
+            Ok((InitFile::Default, Mode::Interactive))
+        ));
+        assert!(matches!(
+            parse_args(args(&["--norc", "-c", "x"])),
+            Ok((InitFile::Skip, Mode::Command { .. }))
+        ));
+        assert!(matches!(
+            parse_args(args(&["--rcfile", "rc.py", "run.py"])),
+            Ok((InitFile::Path(path), Mode::Script { .. })) if path == "rc.py"
+        ));
+        // The last startup option wins
+        assert!(matches!(
+            parse_args(args(&["--rcfile", "rc.py", "--norc"])),
+            Ok((InitFile::Skip, Mode::Interactive))
+        ));
+    }
+
+    #[test]
+    fn parse_args_stops_at_the_mode() {
+        // Options after the script belong to the script
+        let parsed = parse_args(args(&["run.py", "--norc"]));
+        assert!(matches!(
+            parsed,
+            Ok((InitFile::Default, Mode::Script { args, .. })) if args == ["--norc"]
+        ));
+    }
 
-    // Run the REPL
-    repl::run()
+    #[test]
+    fn parse_args_rcfile_requires_a_path() {
+        assert_eq!(
+            parse_args(args(&["--rcfile"])).err(),
+            Some("--rcfile: option requires an argument".to_string())
+        );
+    }
 }
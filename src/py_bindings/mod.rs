@@ -4,6 +4,7 @@ pub mod shell;
 use anyhow::Result;
 use pyo3::prelude::*;
 use std::ffi::CString;
+use std::path::{Path, PathBuf};
 
 // Embed Python modules at compile time
 const CORE: &str = include_str!("../../python/shell/core.py");
@@ -11,12 +12,27 @@ const SHP_BUILTINS: &str = include_str!("../../python/shell/builtins.py");
 const SHP_SHELL_MARKER: &str = include_str!("../../python/shell/shell_marker.py");
 const PYTHON_INIT: &str = include_str!("../../python/shell/init.py");
 
+/// Embedded modules as (module name, package, source file under python/shell, embedded source)
+/// Note: We DON'T register the Python shp stub - the Rust native module is already registered
+/// The shp/__init__.py file is only for external IDE/script support
+const EMBEDDED_MODULES: [(&str, Option<&str>, &str, &str); 3] = [
+    ("core", None, "core.py", CORE),
+    ("shp.builtins", Some("shp"), "builtins.py", SHP_BUILTINS),
+    (
+        "shp.shell_marker",
+        Some("shp"),
+        "shell_marker.py",
+        SHP_SHELL_MARKER,
+    ),
+];
+
 /// Register embedded Python modules in sys.modules
-fn register_embedded_modules(py: Python) -> PyResult<()> {
+/// With a `source_dir`, each module is read from that directory instead (see reload)
+fn register_embedded_modules(py: Python, source_dir: Option<&Path>) -> PyResult<()> {
     let sys_modules = py.import("sys")?.getattr("modules")?;
 
-    // Helper closure to register a module
-    let register = |name: &str, code: &str, package: Option<&str>| -> PyResult<()> {
+    // Helper closure to run a module's source as a fresh module, named `origin` in errors
+    let load = |name: &str, code: &str, package: Option<&str>, origin: &str| -> PyResult<_> {
         let module = PyModule::new(py, name)?;
 
         // Set __package__ for proper relative imports
@@ -24,22 +40,56 @@ fn register_embedded_modules(py: Python) -> PyResult<()> {
             module.setattr("__package__", pkg)?;
         }
 
-        let code_cstr = CString::new(code).unwrap();
+        let code_cstr = CString::new(code).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}: {}", origin, e))
+        })?;
         py.run(code_cstr.as_c_str(), Some(&module.dict()), None)?;
-        sys_modules.set_item(name, module)?;
-        Ok(())
+        Ok(module)
     };
 
+    // Read and run every source before registering any, so a file that is missing or fails
+    // leaves the modules as they were
+    let mut modules = Vec::new();
+    for (name, package, file, embedded) in EMBEDDED_MODULES {
+        let (source, origin) = match source_dir {
+            Some(dir) => {
+                let path = dir.join(file);
+                let source = std::fs::read_to_string(&path).map_err(|e| {
+                    PyErr::new::<pyo3::exceptions::PyOSError, _>(format!(
+                        "{}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+                (source, path.display().to_string())
+            }
+            None => (embedded.to_string(), file.to_string()),
+        };
+        modules.push((name, load(name, &source, package, &origin)?));
+    }
+
     // Register all embedded modules
-    // Note: We DON'T register the Python shp stub - the Rust native module is already registered
-    // The shp/__init__.py file is only for external IDE/script support
-    register("core", CORE, None)?;
-    register("shp.builtins", SHP_BUILTINS, Some("shp"))?;
-    register("shp.shell_marker", SHP_SHELL_MARKER, Some("shp"))?;
+    for (name, module) in modules {
+        sys_modules.set_item(name, module)?;
+    }
 
     Ok(())
 }
 
+/// Re-run the shell's Python modules (core, shp.builtins, shp.shell_marker) as fresh modules
+/// If $SHIP_SOURCE_DIR names a directory (python/shell in a checkout), the sources are read from
+/// there, so edits to them take effect without restarting; otherwise the copies embedded at
+/// compile time are re-run
+/// Names already imported from the old modules (e.g. `from shp.builtins import *`) keep
+/// pointing at them until imported again
+#[pyfunction]
+fn reload(py: Python) -> PyResult<()> {
+    let source_dir = crate::shell::get_var("SHIP_SOURCE_DIR")
+        .map(|value| PathBuf::from(value.to_string_repr()))
+        .filter(|dir| !dir.as_os_str().is_empty());
+    register_embedded_modules(py, source_dir.as_deref())
+}
+
 /// Stage 1: Initialize Python runtime (bare interpreter)
 /// Call this BEFORE shell::initialize_environment()
 pub fn initialize_runtime() -> Result<()> {
@@ -52,12 +102,50 @@ pub fn initialize_runtime() -> Result<()> {
     Ok(())
 }
 
+/// Which files the shell sources at startup
+pub enum InitFile {
+    /// The user's init file (~/.config/ship/init.py or ~/init.py), then the file named by $ENV
+    Default,
+    /// Neither (--norc)
+    Skip,
+    /// This file instead of the user's init file (--rcfile)
+    Path(String),
+}
+
 /// Stage 2: Configure Python environment and register REPL dependencies
 /// Call this AFTER shell::initialize_environment()
-pub fn configure_repl() -> Result<()> {
+pub fn configure_repl(init_file: InitFile) -> Result<()> {
+    // Register os.environ mirroring before the init script can enable it
+    crate::shell::set_os_sync_hook(Box::new(|key: &str, value: Option<&str>| {
+        Python::attach(|py| {
+            let result = py
+                .import("os")
+                .and_then(|os| os.getattr("environ"))
+                .and_then(|environ| match value {
+                    Some(value) => environ.set_item(key, value),
+                    None => environ.call_method1("pop", (key, py.None())).map(|_| ()),
+                });
+            if let Err(e) = result {
+                e.print(py);
+            }
+        })
+    }));
+
     // Register embedded Python modules and run initialization script
     Python::attach(|py| {
-        register_embedded_modules(py)?;
+        register_embedded_modules(py, None)?;
+
+        // Make user modules importable before the init script (and the user's init file) runs
+        shell::add_ship_pythonpath(py)?;
+
+        // Tell the init script which files to source: False for none, or a path to use instead
+        // of the user's init file
+        let rc_file = match init_file {
+            InitFile::Default => py.None(),
+            InitFile::Skip => false.into_pyobject(py)?.to_owned().into_any().unbind(),
+            InitFile::Path(path) => path.into_pyobject(py)?.into_any().unbind(),
+        };
+        py.import("__main__")?.setattr("_ship_rc_file", rc_file)?;
 
         // Initialize Python environment (can now import ship_shell_marker and shp.ergo)
         let init_cstr = CString::new(PYTHON_INIT).unwrap();
@@ -90,6 +178,110 @@ pub fn configure_repl() -> Result<()> {
     Ok(())
 }
 
+/// Exit status once non-interactive code finished cleanly: the status of the last command
+fn last_exit_status() -> i32 {
+    match crate::shell::get_last_exit() {
+        crate::shell::EnvValue::Integer(code) => code as i32,
+        _ => 0,
+    }
+}
+
+/// Exit status for an exception that escaped non-interactive code
+/// SystemExit carries its own status (e.g. sys.exit(3)); anything else is reported and fails
+fn exception_status(py: Python, err: PyErr) -> i32 {
+    if !err.is_instance_of::<pyo3::exceptions::PySystemExit>(py) {
+        err.print(py);
+        return 1;
+    }
+
+    match err.value(py).getattr("code") {
+        Ok(code) if code.is_none() => 0,
+        Ok(code) => code.extract::<i32>().unwrap_or_else(|_| {
+            // Like Python, a non-integer status is printed and exits with 1
+            eprintln!("{}", code);
+            1
+        }),
+        Err(_) => 1,
+    }
+}
+
+/// Point sys.argv at the non-interactive program and its arguments
+fn set_argv(py: Python, program: &str, args: &[String]) -> PyResult<()> {
+    let argv: Vec<&str> = std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .collect();
+    py.import("sys")?.setattr("argv", argv)
+}
+
+/// Run code given with -c as if it had been entered at the prompt
+/// Returns the shell's exit status
+pub fn run_command(code: &str, args: &[String]) -> i32 {
+    Python::attach(|py| {
+        let result = set_argv(py, "-c", args)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| shell::execute_script_code(py, code));
+
+        match result {
+            Ok(()) => last_exit_status(),
+            Err(e) => match e.downcast::<PyErr>() {
+                Ok(err) => exception_status(py, err),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            },
+        }
+    })
+}
+
+/// Run a Python script file in the shell's namespace
+/// Returns the shell's exit status
+pub fn run_script(path: &str, args: &[String]) -> i32 {
+    if !std::path::Path::new(path).is_file() {
+        eprintln!("{}: No such file", path);
+        return 127;
+    }
+
+    Python::attach(|py| {
+        let file = pyo3::types::PyString::new(py, path).into_any();
+        run_source(py, path, args, file)
+    })
+}
+
+/// Run a script piped to the shell's stdin, all at once
+/// Returns the shell's exit status
+pub fn run_stdin() -> i32 {
+    Python::attach(
+        |py| match py.import("sys").and_then(|sys| sys.getattr("stdin")) {
+            Ok(stdin) => run_source(py, "-", &[], stdin),
+            Err(err) => exception_status(py, err),
+        },
+    )
+}
+
+/// Run a file (anything core.source() accepts) with sys.argv set to the program and args
+fn run_source(py: Python, program: &str, args: &[String], file: Bound<PyAny>) -> i32 {
+    let result = set_argv(py, program, args).and_then(|()| {
+        py.import("core")?
+            .getattr("source")?
+            .call1((file,))?
+            .extract::<bool>()
+    });
+
+    match result {
+        Ok(true) => last_exit_status(),
+        // errexit stopped the script at a failed command, whose status becomes the shell's
+        Ok(false)
+            if crate::shell::get_option("errexit") == Some(true) && last_exit_status() != 0 =>
+        {
+            last_exit_status()
+        }
+        // source() has already reported the error
+        Ok(false) => 1,
+        Err(err) => exception_status(py, err),
+    }
+}
+
 /// The main Python module 'shp'
 #[pymodule]
 pub mod shp {
@@ -106,8 +298,18 @@ pub mod shp {
         m.add_class::<shell::ShipRunnable>()?;
         m.add_class::<shell::ShipResult>()?;
         m.add_class::<shell::CapturedResult>()?;
+        m.add_class::<shell::ShipProcess>()?;
         m.add_class::<shell::ShipEnv>()?;
 
+        // Add shell exceptions
+        m.add("ShipError", m.py().get_type::<shell::ShipError>())?;
+        m.add(
+            "CommandNotFound",
+            m.py().get_type::<shell::CommandNotFound>(),
+        )?;
+        m.add("CommandError", m.py().get_type::<shell::CommandError>())?;
+        m.add("RedirectError", m.py().get_type::<shell::RedirectError>())?;
+
         // Add shell functions
         m.add_function(wrap_pyfunction!(shell::prog, m)?)?;
         m.add_function(wrap_pyfunction!(shell::cmd, m)?)?;
@@ -115,10 +317,30 @@ pub mod shp {
         m.add_function(wrap_pyfunction!(shell::sub, m)?)?;
         m.add_function(wrap_pyfunction!(shell::shexec, m)?)?;
         m.add_function(wrap_pyfunction!(shell::capture, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::capture_all, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::watch, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::quote, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::sh, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::from_dict, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::terminal_size, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::add_path, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::mktemp, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::mktemp_dir, m)?)?;
         m.add_function(wrap_pyfunction!(shell::get_stdout, m)?)?;
         m.add_function(wrap_pyfunction!(shell::get_stderr, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::sub_out, m)?)?;
         m.add_function(wrap_pyfunction!(shell::get_env, m)?)?;
         m.add_function(wrap_pyfunction!(shell::set_env, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::get_option, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::set_option, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::get_pipe_size, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::set_pipe_size, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::jobs, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::last_exit, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::pid, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::ppid, m)?)?;
+        m.add_function(wrap_pyfunction!(shell::dirstack, m)?)?;
+        m.add_function(wrap_pyfunction!(reload, m)?)?;
 
         // Add repl submodule
         let repl_module = PyModule::new(m.py(), "repl")?;
@@ -128,6 +350,14 @@ pub mod shp {
         repl_module.add_function(wrap_pyfunction!(repl::get_continuation, &repl_module)?)?;
         repl_module.add_function(wrap_pyfunction!(repl::set_right_prompt, &repl_module)?)?;
         repl_module.add_function(wrap_pyfunction!(repl::get_right_prompt, &repl_module)?)?;
+        repl_module.add_function(wrap_pyfunction!(repl::set_autorun, &repl_module)?)?;
+        repl_module.add_function(wrap_pyfunction!(repl::get_autorun, &repl_module)?)?;
+        repl_module.add_function(wrap_pyfunction!(repl::set_history_control, &repl_module)?)?;
+        repl_module.add_function(wrap_pyfunction!(repl::get_history_control, &repl_module)?)?;
+        repl_module.add_function(wrap_pyfunction!(repl::register_completions, &repl_module)?)?;
+        repl_module.add_function(wrap_pyfunction!(repl::complete, &repl_module)?)?;
+        repl_module.add_function(wrap_pyfunction!(repl::edit_last, &repl_module)?)?;
+        repl_module.add_function(wrap_pyfunction!(repl::subshell, &repl_module)?)?;
         repl_module.add_function(wrap_pyfunction!(repl::on, &repl_module)?)?;
         repl_module.add_function(wrap_pyfunction!(repl::off, &repl_module)?)?;
         repl_module.add_function(wrap_pyfunction!(repl::list_hooks, &repl_module)?)?;
@@ -1,4 +1,5 @@
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 
 /// REPL hook enum - exposed to Python
 #[pyclass(eq, eq_int)]
@@ -49,11 +50,77 @@ pub fn get_right_prompt() -> PyResult<String> {
     Ok(crate::repl::get_right_prompt())
 }
 
+/// Enable or disable auto-running of bare ShipRunnable expressions
+#[pyfunction]
+pub fn set_autorun(enabled: bool) -> PyResult<()> {
+    crate::repl::set_autorun(enabled);
+    Ok(())
+}
+
+/// Check whether bare ShipRunnable expressions are auto-run
+#[pyfunction]
+pub fn get_autorun() -> PyResult<bool> {
+    Ok(crate::repl::get_autorun())
+}
+
+/// Choose which statements are kept out of history, like HISTCONTROL in bash
+/// Settings that aren't given are left unchanged
+#[pyfunction]
+#[pyo3(signature = (ignoredups=None, ignorespace=None))]
+pub fn set_history_control(ignoredups: Option<bool>, ignorespace: Option<bool>) -> PyResult<()> {
+    crate::repl::set_history_control(ignoredups, ignorespace);
+    Ok(())
+}
+
+/// Get the history control settings as a dict of ignoredups and ignorespace
+#[pyfunction]
+pub fn get_history_control(py: Python) -> PyResult<Py<PyDict>> {
+    let (ignore_dups, ignore_space) = crate::repl::get_history_control();
+    let settings = PyDict::new(py);
+    settings.set_item("ignoredups", ignore_dups)?;
+    settings.set_item("ignorespace", ignore_space)?;
+    Ok(settings.unbind())
+}
+
+/// Register the subcommands/flags offered when tab-completing a command's arguments
+#[pyfunction]
+pub fn register_completions(command: String, options: Vec<String>) -> PyResult<()> {
+    crate::repl::register_completions(command, options);
+    Ok(())
+}
+
+/// Get the completions Tab would offer for a line with the cursor at the given position
+#[pyfunction]
+pub fn complete(line: String, cursor: usize) -> PyResult<Vec<String>> {
+    Ok(crate::repl::complete(&line, cursor))
+}
+
+/// Open the previous statement in an editor and run the saved result
+/// Returns the exit status (1 if there was no history, no editor, or the code raised)
+#[pyfunction]
+pub fn edit_last() -> PyResult<i32> {
+    Ok(crate::repl::edit_last())
+}
+
+/// Start a nested REPL, e.g. to poke around in the middle of a script or function
+/// It shares the namespace, history, prompts and hooks, and returns on Ctrl+D
+#[pyfunction]
+pub fn subshell() -> PyResult<()> {
+    if !crate::repl::is_running() {
+        return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+            "subshell() only works in the interactive REPL",
+        ));
+    }
+    crate::repl::run().map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+}
+
 /// Register a callback for a REPL hook
 /// Wraps Python callable in Rust closure and registers with REPL
 /// Returns a unique ID for this hook registration
+/// Higher priority hooks fire first; equal priorities fire in registration order
 #[pyfunction]
-pub fn on(hook: REPLHook, callback: Py<PyAny>) -> PyResult<u64> {
+#[pyo3(signature = (hook, callback, *, priority=0))]
+pub fn on(hook: REPLHook, callback: Py<PyAny>, priority: i32) -> PyResult<u64> {
     let id = match hook {
         REPLHook::BeforePrompt => {
             let rust_hook = Box::new(move || {
@@ -64,7 +131,7 @@ pub fn on(hook: REPLHook, callback: Py<PyAny>) -> PyResult<u64> {
                     }
                 });
             });
-            crate::repl::register_before_prompt_hook(rust_hook)
+            crate::repl::register_before_prompt_hook(rust_hook, priority)
         }
         REPLHook::BeforeContinuation => {
             let rust_hook = Box::new(move |prev_prompt: &str, buffer: &str| {
@@ -75,7 +142,7 @@ pub fn on(hook: REPLHook, callback: Py<PyAny>) -> PyResult<u64> {
                     }
                 });
             });
-            crate::repl::register_before_continuation_hook(rust_hook)
+            crate::repl::register_before_continuation_hook(rust_hook, priority)
         }
         REPLHook::BeforeExecute => {
             let rust_hook = Box::new(move |command: &str| {
@@ -86,7 +153,7 @@ pub fn on(hook: REPLHook, callback: Py<PyAny>) -> PyResult<u64> {
                     }
                 });
             });
-            crate::repl::register_before_execute_hook(rust_hook)
+            crate::repl::register_before_execute_hook(rust_hook, priority)
         }
         REPLHook::AfterExecute => {
             let rust_hook = Box::new(move |command: &str| {
@@ -97,7 +164,7 @@ pub fn on(hook: REPLHook, callback: Py<PyAny>) -> PyResult<u64> {
                     }
                 });
             });
-            crate::repl::register_after_execute_hook(rust_hook)
+            crate::repl::register_after_execute_hook(rust_hook, priority)
         }
     };
     Ok(id)
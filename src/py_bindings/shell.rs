@@ -1,25 +1,66 @@
+use nix::errno::Errno;
 use nix::libc;
-use pyo3::exceptions::PyKeyError;
+use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, Signal, killpg, sigaction};
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyKeyError, PyUnicodeDecodeError};
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
+use pyo3::types::{PyBool, PyBytes, PyDict, PyIterator, PyList, PyString, PyTuple};
 use std::collections::HashMap;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::fs::File;
-use std::io::Read;
-use std::os::unix::io::FromRawFd;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::ops::ControlFlow;
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::path::PathBuf;
 use std::sync::Arc;
-
-use crate::shell::exec::{ShellResult, execute_with_capture};
-use crate::shell::{self, EnvValue, ExecRequest, execute};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::shell::exec::{
+    ShellResult, execute_capture_all, execute_with_capture, execute_with_merged_capture,
+    execute_with_pty_capture, spawn, spawn_background, spawn_detached, spawn_with_stdout_pipe,
+    wait_spawned,
+};
+use crate::shell::{self, EnvError, EnvValue, ExecRequest, execute};
+
+/// Environment errors surface in Python as ValueError
+impl From<EnvError> for PyErr {
+    fn from(error: EnvError) -> Self {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(error.message().to_string())
+    }
+}
 
 /// Execute a line of Python code in REPL mode with auto-run for ShipRunnable
 pub fn execute_repl_code(py: Python, repl_string: &str) -> anyhow::Result<()> {
-    let code = CString::new(repl_string)?;
+    execute_code(py, repl_string, |code| py.run(code, None, None))
+}
+
+/// Execute non-interactive code (-c) like REPL input, except that statements run one at a
+/// time through core.run_code() so the errexit option can stop them
+pub fn execute_script_code(py: Python, script: &str) -> anyhow::Result<()> {
+    execute_code(py, script, |_| {
+        py.import("core")?
+            .getattr("run_code")?
+            .call1((script, "<string>"))?;
+        Ok(())
+    })
+}
+
+/// Evaluate code as an expression (auto-running or printing the result), falling back to
+/// run_statements if it isn't one
+fn execute_code(
+    py: Python,
+    source: &str,
+    run_statements: impl FnOnce(&CStr) -> PyResult<()>,
+) -> anyhow::Result<()> {
+    let code = CString::new(source)?;
 
     // Try to evaluate as an expression first
     match py.eval(code.as_c_str(), None, None) {
         // Successfully evaluated as expression
-        Ok(result) if result.is_instance_of::<ShipRunnable>() => {
+        Ok(result) if result.is_instance_of::<ShipRunnable>() && crate::repl::get_autorun() => {
             // ShipRunnable - auto-run it
             result.call0()?;
         }
@@ -28,10 +69,13 @@ pub fn execute_repl_code(py: Python, repl_string: &str) -> anyhow::Result<()> {
             println!("{}", result.repr()?);
         }
         Ok(_) => {} // None result - do nothing
-        // If eval fails, try running as a statement
-        Err(_) => {
-            py.run(code.as_c_str(), None, None)?;
+        // Not an expression - run it as a statement
+        Err(e) if e.is_instance_of::<pyo3::exceptions::PySyntaxError>(py) => {
+            run_statements(code.as_c_str())?;
         }
+        // The expression itself raised (e.g. KeyboardInterrupt) - running it again as a
+        // statement would repeat its side effects
+        Err(e) => return Err(e.into()),
     }
 
     Ok(())
@@ -39,8 +83,7 @@ pub fn execute_repl_code(py: Python, repl_string: &str) -> anyhow::Result<()> {
 
 /// Convert a Python object to an EnvValue with strict type checking (no coercion)
 fn py_to_env_value(obj: &Bound<PyAny>) -> PyResult<EnvValue> {
-    use pyo3::types::{PyBool, PyFloat, PyInt, PyString};
-    use std::path::PathBuf;
+    use pyo3::types::{PyBool, PyFloat, PyInt};
 
     // Check for None first
     if obj.is_none() {
@@ -118,23 +161,58 @@ fn env_value_to_py(py: Python, value: &EnvValue) -> PyResult<Py<PyAny>> {
 #[pyclass]
 #[derive(Clone)]
 pub struct ShipProgram {
+    #[pyo3(get)]
     name: String,
+    /// Subcommands picked by attribute access, which go before the arguments
+    subcommands: Vec<String>,
 }
 
 impl ShipProgram {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            subcommands: Vec::new(),
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Build the command running this program, and any subcommands, with `args`
+    fn command(&self, args: Vec<String>) -> ShipRunnable {
+        ShipRunnable(Arc::new(Runnable::Command {
+            prog: ShipProgram::new(self.name.clone()),
+            args: self.subcommands.iter().cloned().chain(args).collect(),
+        }))
+    }
 }
 
 #[pymethods]
 impl ShipProgram {
     #[pyo3(signature = (*args))]
     fn __call__(&self, args: Vec<String>) -> PyResult<ShipRunnable> {
-        Ok(ShipRunnable(Arc::new(Runnable::Command {
-            prog: self.clone(),
-            args,
-        })))
+        Ok(self.command(args))
+    }
+
+    /// Treat an unknown attribute as a subcommand, with underscores turned into dashes
+    /// Only called once real attributes (like name) aren't found; names starting with an
+    /// underscore stay missing so Python's checks for special methods work as usual
+    ///
+    /// Usage:
+    ///   prog('git').status('-s')()            # git status -s
+    ///   prog('apt').list_upgradable()()       # apt list-upgradable
+    ///   prog('git').remote.add('origin', url)()
+    fn __getattr__(&self, attr: &str) -> PyResult<ShipProgram> {
+        if attr.starts_with('_') {
+            return Err(PyErr::new::<pyo3::exceptions::PyAttributeError, _>(
+                format!("'ShipProgram' object has no attribute '{}'", attr),
+            ));
+        }
+
+        let mut program = self.clone();
+        program.subcommands.push(attr.replace('_', "-"));
+        Ok(program)
     }
 }
 
@@ -143,7 +221,6 @@ impl ShipProgram {
 pub struct ShipRunnable(Arc<Runnable>);
 
 #[allow(dead_code)]
-#[derive(Clone)]
 enum Runnable {
     Command {
         prog: ShipProgram,
@@ -160,15 +237,53 @@ enum Runnable {
         runnable: ShipRunnable,
         target: RedirectTarget,
     },
+    /// Runs the runnable with extra variables, and with only the essential ones from the shell
+    /// unless `inherit`
     WithEnv {
         runnable: ShipRunnable,
         env_overlay: HashMap<String, EnvValue>,
+        inherit: bool,
+    },
+    /// Feeds fixed data to the runnable's stdin
+    Input {
+        runnable: ShipRunnable,
+        data: Vec<u8>,
+    },
+    /// Reads the runnable's stdin from a file
+    InputRedirect {
+        runnable: ShipRunnable,
+        source: RedirectSource,
+    },
+    /// Runs a Python callable in the shell process once the runnable finishes
+    Then {
+        runnable: ShipRunnable,
+        callback: Py<PyAny>,
+    },
+    /// Re-runs the runnable until it succeeds, sleeping longer between each try
+    Retry {
+        runnable: ShipRunnable,
+        attempts: u32,
+        delay: f64,
+        backoff: f64,
     },
 }
 
 #[derive(Clone)]
 enum RedirectTarget {
-    FilePath { path: String, append: bool },
+    FilePath {
+        path: String,
+        append: bool,
+        clobber: bool,
+        mode: Option<u32>,
+    },
+    FileDescriptor {
+        fd: i32,
+    },
+}
+
+#[derive(Clone)]
+enum RedirectSource {
+    FilePath { path: String },
     FileDescriptor { fd: i32 },
 }
 
@@ -179,9 +294,83 @@ pub struct ShipResult {
     pub exit_code: u8,
 }
 
-impl From<&ShipRunnable> for ExecRequest {
-    fn from(runnable: &ShipRunnable) -> Self {
-        match runnable.0.as_ref() {
+impl ShipResult {
+    /// Exit code to compare against: another result's, or a plain int (bools excluded, since
+    /// a result's truth value is the opposite of its exit code)
+    fn other_exit_code(other: &Bound<PyAny>) -> Option<i64> {
+        if let Ok(result) = other.cast::<ShipResult>() {
+            return Some(result.borrow().exit_code as i64);
+        }
+        if other.is_instance_of::<PyBool>() {
+            return None;
+        }
+        other.extract::<i64>().ok()
+    }
+}
+
+#[pymethods]
+impl ShipResult {
+    /// Compare by exit code with an int or another result, e.g. `prog('make')() == 0`
+    fn __eq__(&self, py: Python, other: &Bound<PyAny>) -> PyResult<Py<PyAny>> {
+        match Self::other_exit_code(other) {
+            Some(code) => Ok(PyBool::new(py, self.exit_code as i64 == code)
+                .to_owned()
+                .into_any()
+                .unbind()),
+            None => Ok(py.NotImplemented()),
+        }
+    }
+
+    fn __ne__(&self, py: Python, other: &Bound<PyAny>) -> PyResult<Py<PyAny>> {
+        match Self::other_exit_code(other) {
+            Some(code) => Ok(PyBool::new(py, self.exit_code as i64 != code)
+                .to_owned()
+                .into_any()
+                .unbind()),
+            None => Ok(py.NotImplemented()),
+        }
+    }
+
+    /// Hash like the exit code, so results that compare equal hash alike
+    fn __hash__(&self) -> u64 {
+        self.exit_code as u64
+    }
+
+    fn __int__(&self) -> i64 {
+        self.exit_code as i64
+    }
+
+    /// True when the command succeeded (exit code 0), as in `if` in other shells
+    /// Note this is the opposite of bool(int(result))
+    fn __bool__(&self) -> bool {
+        self.exit_code == 0
+    }
+
+    /// Raise if the command failed, otherwise return the result for chaining
+    /// Exit code 127 (the shell's "command not found") raises CommandNotFound, any other failure
+    /// raises CommandError
+    fn raise_for_status(slf: PyRef<Self>) -> PyResult<PyRef<Self>> {
+        match slf.exit_code {
+            0 => Ok(slf),
+            127 => Err(CommandNotFound::new_err(
+                "command not found (exit code 127)",
+            )),
+            code => Err(CommandError::new_err(format!(
+                "command failed with exit code {}",
+                code
+            ))),
+        }
+    }
+}
+
+/// The request to run for a runnable
+/// then() and retry() only take effect when the runnable is called directly, so a runnable using
+/// them is refused here rather than run without its callback or retries
+impl TryFrom<&ShipRunnable> for ExecRequest {
+    type Error = PyErr;
+
+    fn try_from(runnable: &ShipRunnable) -> PyResult<Self> {
+        Ok(match runnable.0.as_ref() {
             Runnable::Command { prog, args } => ExecRequest::Program {
                 name: prog.name().to_string(),
                 args: args.clone(),
@@ -190,36 +379,84 @@ impl From<&ShipRunnable> for ExecRequest {
                 predecessors,
                 final_cmd,
             } => {
-                let mut stages: Vec<ExecRequest> = predecessors.iter().map(|p| p.into()).collect();
-                stages.push(final_cmd.into());
+                let mut stages = predecessors
+                    .iter()
+                    .map(ExecRequest::try_from)
+                    .collect::<PyResult<Vec<_>>>()?;
+                stages.push(final_cmd.try_into()?);
                 ExecRequest::Pipeline { stages }
             }
             Runnable::Subshell { runnable } => ExecRequest::Subshell {
-                request: Box::new(runnable.into()),
+                request: Box::new(runnable.try_into()?),
             },
             Runnable::Redirect { runnable, target } => {
                 let shell_target = match target {
-                    RedirectTarget::FilePath { path, append } => shell::RedirectTarget::FilePath {
+                    RedirectTarget::FilePath {
+                        path,
+                        append,
+                        clobber,
+                        mode,
+                    } => shell::RedirectTarget::FilePath {
                         path: path.clone(),
                         append: *append,
+                        clobber: *clobber,
+                        mode: *mode,
                     },
                     RedirectTarget::FileDescriptor { fd } => {
                         shell::RedirectTarget::FileDescriptor { fd: *fd }
                     }
                 };
                 ExecRequest::Redirect {
-                    request: Box::new(runnable.into()),
+                    request: Box::new(runnable.try_into()?),
                     target: shell_target,
                 }
             }
             Runnable::WithEnv {
                 runnable,
                 env_overlay,
+                inherit,
             } => ExecRequest::WithEnv {
-                request: Box::new(runnable.into()),
+                request: Box::new(runnable.try_into()?),
                 env_overlay: env_overlay.clone(),
+                inherit: *inherit,
+            },
+            Runnable::Input { runnable, data } => ExecRequest::Input {
+                request: Box::new(runnable.try_into()?),
+                data: data.clone(),
+            },
+            Runnable::InputRedirect { runnable, source } => ExecRequest::InputRedirect {
+                request: Box::new(runnable.try_into()?),
+                source: match source {
+                    RedirectSource::FilePath { path } => {
+                        shell::RedirectSource::FilePath { path: path.clone() }
+                    }
+                    RedirectSource::FileDescriptor { fd } => {
+                        shell::RedirectSource::FileDescriptor { fd: *fd }
+                    }
+                },
             },
+            Runnable::Then { .. } | Runnable::Retry { .. } => {
+                return Err(direct_call_only(runnable));
+            }
+        })
+    }
+}
+
+impl ShipRunnable {
+    /// The equivalent shell command line
+    /// then() and retry() change how a runnable is run rather than what runs, so they aren't shown
+    fn command_line(&self) -> PyResult<String> {
+        let mut runnable = self;
+        while let Runnable::Then {
+            runnable: inner, ..
+        }
+        | Runnable::Retry {
+            runnable: inner, ..
+        } = runnable.0.as_ref()
+        {
+            runnable = inner;
         }
+        Ok(ExecRequest::try_from(runnable)?.to_string())
     }
 }
 
@@ -241,10 +478,31 @@ impl ShipRunnable {
                 ));
             }
 
+            // Then or Retry on either side - error (they apply to the whole runnable)
+            (Then { .. } | Retry { .. }, _) => return Err(not_final(self)),
+            (_, Then { .. } | Retry { .. }) => return Err(not_final(other)),
+
+            // Input on the right - error (its stdin is already the fed data or file)
+            (_, Input { .. }) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                    "Cannot pipe to a command with input() - it already has its stdin",
+                ));
+            }
+            (_, InputRedirect { .. }) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                    "Cannot pipe to a command with redirected input - it already has its stdin",
+                ));
+            }
+
             // Atomic | Atomic -> Pipeline([lhs], rhs)
-            // (Command, Subshell, and WithEnv are all atomic units)
+            // (Command, Subshell, and WithEnv are all atomic units, as are Input and
+            // InputRedirect on the left)
             (
-                Command { .. } | Subshell { .. } | WithEnv { .. },
+                Command { .. }
+                | Subshell { .. }
+                | WithEnv { .. }
+                | Input { .. }
+                | InputRedirect { .. },
                 Command { .. } | Subshell { .. } | WithEnv { .. },
             ) => Arc::new(Pipeline {
                 predecessors: vec![self.clone()],
@@ -269,7 +527,11 @@ impl ShipRunnable {
 
             // Atomic | Pipeline -> prepend to pipeline
             (
-                Command { .. } | Subshell { .. } | WithEnv { .. },
+                Command { .. }
+                | Subshell { .. }
+                | WithEnv { .. }
+                | Input { .. }
+                | InputRedirect { .. },
                 Pipeline {
                     predecessors,
                     final_cmd,
@@ -307,34 +569,216 @@ impl ShipRunnable {
         Ok(ShipRunnable(result_inner))
     }
 
-    fn __call__(&self) -> PyResult<ShipResult> {
-        let result = execute(&self.into());
+    /// Return a new command with one more argument appended
+    fn arg(&self, value: String) -> PyResult<ShipRunnable> {
+        self.args(vec![value])
+    }
+
+    /// Return a new command with more arguments appended
+    /// Only plain commands can be extended - appending to a pipeline would be ambiguous
+    fn args(&self, values: Vec<String>) -> PyResult<ShipRunnable> {
+        match self.0.as_ref() {
+            Runnable::Command { prog, args } => {
+                let mut new_args = args.clone();
+                new_args.extend(values);
+                Ok(ShipRunnable(Arc::new(Runnable::Command {
+                    prog: prog.clone(),
+                    args: new_args,
+                })))
+            }
+            _ => Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                "Arguments can only be appended to a plain command",
+            )),
+        }
+    }
+
+    /// Return a new runnable that reads `data` (str or bytes) as its stdin, like subprocess's
+    /// input=. Applied to a pipeline, the data goes to its first stage
+    ///
+    /// Usage:
+    ///   get_stdout(prog('sort')().input('b\na\n'))
+    fn input(&self, data: &Bound<PyAny>) -> PyResult<ShipRunnable> {
+        if let Runnable::Then { .. } | Runnable::Retry { .. } = self.0.as_ref() {
+            return Err(not_final(self));
+        }
+        let data = if let Ok(text) = data.cast::<PyString>() {
+            text.to_str()?.as_bytes().to_vec()
+        } else if let Ok(bytes) = data.cast::<PyBytes>() {
+            bytes.as_bytes().to_vec()
+        } else {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                "input() data must be str or bytes, not {}",
+                data.get_type().name()?
+            )));
+        };
+        Ok(ShipRunnable(Arc::new(Runnable::Input {
+            runnable: self.clone(),
+            data,
+        })))
+    }
+
+    /// Take stdin from another runnable: b.stdin_from(a) is the same as a | b
+    fn stdin_from(&self, source: &ShipRunnable) -> PyResult<ShipRunnable> {
+        source.__or__(self)
+    }
+
+    /// Run and capture stdout and stderr, the same as capture(runnable)
+    ///
+    /// Usage:
+    ///   prog('ls')('-l').capture().read_stdout()
+    ///   (prog('make')() | prog('tail')('-5')).capture(merge_stderr=True).combined
+    ///   prog('ls')().capture(pty=True).read_stdout()  # columns, as on the terminal
+    #[pyo3(signature = (merge_stderr=false, pty=false))]
+    fn capture(&self, merge_stderr: bool, pty: bool) -> PyResult<CapturedResult> {
+        capture(self, merge_stderr, pty)
+    }
+
+    /// Run and return stdout split on whitespace into a flat list of fields
+    fn fields(&self, py: Python) -> PyResult<Vec<String>> {
+        let output = get_stdout(py, self)?;
+        Ok(output.split_whitespace().map(String::from).collect())
+    }
+
+    /// Run and return stdout as rows of fields: one row per line, split on `sep`
+    /// Without a separator each line is split on runs of whitespace
+    #[pyo3(signature = (sep=None))]
+    fn table(&self, py: Python, sep: Option<&str>) -> PyResult<Vec<Vec<String>>> {
+        if sep == Some("") {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "empty separator",
+            ));
+        }
+
+        let output = get_stdout(py, self)?;
+        Ok(output
+            .lines()
+            .map(|line| match sep {
+                Some(sep) => line.split(sep).map(String::from).collect(),
+                None => line.split_whitespace().map(String::from).collect(),
+            })
+            .collect())
+    }
+
+    /// Render as the equivalent shell command line
+    fn __str__(&self) -> PyResult<String> {
+        self.command_line()
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("<ShipRunnable: {}>", self.command_line()?))
+    }
+
+    fn __call__(&self, py: Python) -> PyResult<ShipResult> {
+        if let Runnable::Retry {
+            runnable,
+            attempts,
+            delay,
+            backoff,
+        } = self.0.as_ref()
+        {
+            return run_with_retries(py, runnable, *attempts, *delay, *backoff);
+        }
+        if let Runnable::Then { runnable, callback } = self.0.as_ref() {
+            let result = runnable.__call__(py)?;
+            callback.call1(py, (result.clone(),))?;
+
+            // Commands run by the callback mustn't leave their status in $?
+            shell::set_last_exit(result.exit_code);
+            return Ok(result);
+        }
+
+        let result = execute(&self.try_into()?);
         Ok(ShipResult {
             exit_code: result.exit_code(),
         })
     }
 
+    /// Call a Python callable with the ShipResult after this runnable runs
+    /// The original result is returned; an exception from the callable propagates
+    ///
+    /// Usage:
+    ///   prog('make')('build').then(lambda r: print('ok' if r.exit_code == 0 else 'failed'))()
+    fn then(&self, callback: Py<PyAny>) -> ShipRunnable {
+        ShipRunnable(Arc::new(Runnable::Then {
+            runnable: self.clone(),
+            callback,
+        }))
+    }
+
+    /// Return a runnable that re-runs this one until it succeeds, at most `attempts` times
+    /// Waits `delay` seconds before the first retry, multiplied by `backoff` after each one;
+    /// Ctrl-C stops retrying. Calling it returns the last try's ShipResult
+    ///
+    /// Usage:
+    ///   prog('curl')('-fsS', url).retry(attempts=3, delay=1.0, backoff=2.0)()
+    #[pyo3(signature = (attempts=3, delay=1.0, backoff=2.0))]
+    fn retry(&self, attempts: u32, delay: f64, backoff: f64) -> PyResult<ShipRunnable> {
+        if attempts == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "attempts must be at least 1",
+            ));
+        }
+        if !delay.is_finite() || delay < 0.0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "delay must be a non-negative number of seconds",
+            ));
+        }
+        if !backoff.is_finite() || backoff <= 0.0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "backoff must be a positive number",
+            ));
+        }
+
+        Ok(ShipRunnable(Arc::new(Runnable::Retry {
+            runnable: self.clone(),
+            attempts,
+            delay,
+            backoff,
+        })))
+    }
+
+    /// Run in the background without waiting, like `cmd &`
+    /// Returns the job id (see jobs())
+    fn background(&self) -> PyResult<usize> {
+        Ok(spawn_background(&self.try_into()?))
+    }
+
+    /// Start running without waiting and return a handle to poll, wait for or signal it
+    /// Like background() it runs in its own process group, but it isn't a job - the handle is
+    /// the only way to get its exit code
+    fn spawn(&self) -> PyResult<ShipProcess> {
+        let request = ExecRequest::try_from(self)?;
+        Ok(ShipProcess {
+            pid: spawn(&request).as_raw(),
+            command: request.to_string(),
+            exit_code: None,
+        })
+    }
+
+    /// Run detached from the shell, like `nohup cmd &`
+    /// The command ignores SIGHUP and isn't tracked as a job; returns its PID
+    fn nohup(&self) -> PyResult<i32> {
+        Ok(spawn_detached(&self.try_into()?).as_raw())
+    }
+
     fn __gt__(&self, target: Bound<PyAny>) -> PyResult<ShipRunnable> {
+        if let Runnable::Then { .. } | Runnable::Retry { .. } = self.0.as_ref() {
+            return Err(not_final(self));
+        }
+
         let redirect_target = if let Ok(path) = target.extract::<String>() {
             // String path - truncate mode
             RedirectTarget::FilePath {
                 path,
                 append: false,
+                clobber: false,
+                mode: None,
             }
         } else if target.hasattr("fileno")? {
             // File-like object - get file descriptor
-            let fileno_method = target.getattr("fileno")?;
-            let fd: i32 = fileno_method.call0()?.extract()?;
-
-            // Duplicate the file descriptor for cross-fork safety
-            let dup_fd = unsafe { libc::dup(fd) };
-            if dup_fd == -1 {
-                return Err(PyErr::new::<pyo3::exceptions::PyOSError, _>(
-                    "Failed to duplicate file descriptor",
-                ));
+            RedirectTarget::FileDescriptor {
+                fd: dup_target_fd(&target)?,
             }
-
-            RedirectTarget::FileDescriptor { fd: dup_fd }
         } else {
             return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
                 "Redirect target must be a string path or file-like object with fileno()",
@@ -347,24 +791,50 @@ impl ShipRunnable {
         })))
     }
 
+    /// Redirect stdin from a file: a string path or a file-like object with fileno()
+    /// Stacks with output redirects, but Python chains `a < b > c` as comparisons, so the input
+    /// redirect needs parentheses: (prog('sort')() < 'in.txt') > 'out.txt'
+    fn __lt__(&self, source: Bound<PyAny>) -> PyResult<ShipRunnable> {
+        if let Runnable::Then { .. } | Runnable::Retry { .. } = self.0.as_ref() {
+            return Err(not_final(self));
+        }
+
+        let redirect_source = if let Ok(path) = source.extract::<String>() {
+            RedirectSource::FilePath { path }
+        } else if source.hasattr("fileno")? {
+            RedirectSource::FileDescriptor {
+                fd: dup_target_fd(&source)?,
+            }
+        } else {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                "Redirect source must be a string path or file-like object with fileno()",
+            ));
+        };
+
+        Ok(ShipRunnable(Arc::new(Runnable::InputRedirect {
+            runnable: self.clone(),
+            source: redirect_source,
+        })))
+    }
+
     fn __rshift__(&self, target: Bound<PyAny>) -> PyResult<ShipRunnable> {
+        if let Runnable::Then { .. } | Runnable::Retry { .. } = self.0.as_ref() {
+            return Err(not_final(self));
+        }
+
         let redirect_target = if let Ok(path) = target.extract::<String>() {
             // String path - append mode
-            RedirectTarget::FilePath { path, append: true }
+            RedirectTarget::FilePath {
+                path,
+                append: true,
+                clobber: false,
+                mode: None,
+            }
         } else if target.hasattr("fileno")? {
             // File-like object - get file descriptor
-            let fileno_method = target.getattr("fileno")?;
-            let fd: i32 = fileno_method.call0()?.extract()?;
-
-            // Duplicate the file descriptor for cross-fork safety
-            let dup_fd = unsafe { libc::dup(fd) };
-            if dup_fd == -1 {
-                return Err(PyErr::new::<pyo3::exceptions::PyOSError, _>(
-                    "Failed to duplicate file descriptor",
-                ));
+            RedirectTarget::FileDescriptor {
+                fd: dup_target_fd(&target)?,
             }
-
-            RedirectTarget::FileDescriptor { fd: dup_fd }
         } else {
             return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
                 "Redirect target must be a string path or file-like object with fileno()",
@@ -377,6 +847,146 @@ impl ShipRunnable {
         })))
     }
 
+    /// Force a file redirect to truncate the target even when noclobber is set
+    ///
+    /// Usage:
+    ///   (prog('echo')('Hello') > 'out.txt').clobber()()
+    fn clobber(&self) -> PyResult<ShipRunnable> {
+        match self.0.as_ref() {
+            Runnable::Redirect {
+                runnable,
+                target:
+                    RedirectTarget::FilePath {
+                        path, append, mode, ..
+                    },
+            } => Ok(ShipRunnable(Arc::new(Runnable::Redirect {
+                runnable: runnable.clone(),
+                target: RedirectTarget::FilePath {
+                    path: path.clone(),
+                    append: *append,
+                    clobber: true,
+                    mode: *mode,
+                },
+            }))),
+            _ => Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                "clobber() can only be applied to a file redirect",
+            )),
+        }
+    }
+
+    /// Set the permission bits used if the redirect creates its file, e.g. 0o600
+    /// Existing files keep their permissions, and the process umask still applies
+    fn create_mode(&self, mode: u32) -> PyResult<ShipRunnable> {
+        if mode > 0o7777 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid file mode {:#o}",
+                mode
+            )));
+        }
+        match self.0.as_ref() {
+            Runnable::Redirect {
+                runnable,
+                target:
+                    RedirectTarget::FilePath {
+                        path,
+                        append,
+                        clobber,
+                        ..
+                    },
+            } => Ok(ShipRunnable(Arc::new(Runnable::Redirect {
+                runnable: runnable.clone(),
+                target: RedirectTarget::FilePath {
+                    path: path.clone(),
+                    append: *append,
+                    clobber: *clobber,
+                    mode: Some(mode),
+                },
+            }))),
+            _ => Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                "create_mode() can only be applied to a file redirect",
+            )),
+        }
+    }
+
+    /// Run this runnable and feed each line of its stdout to a Python callable
+    ///
+    /// Usage:
+    ///   prog('seq')('10').pipe_to(lambda line: print(int(line) * 2))
+    ///   prog('cat')('log.txt').pipe_to(handler, abort_on_error=True)
+    #[pyo3(signature = (callback, abort_on_error=false))]
+    fn pipe_to(
+        &self,
+        py: Python,
+        callback: Py<PyAny>,
+        abort_on_error: bool,
+    ) -> PyResult<ShipResult> {
+        for_each_stdout_line(py, self, |line| {
+            if let Err(e) = callback.call1(py, (line_text(line).as_ref(),)) {
+                if abort_on_error {
+                    return ControlFlow::Break(e);
+                }
+                eprintln!("Error in pipe_to callback:");
+                e.print(py);
+            }
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Run this runnable and write each stdout line, transformed by a Python callable, to stdout
+    /// A None return drops the line; a string replaces it (and is written with a newline)
+    ///
+    /// Usage:
+    ///   prog('cat')('names.txt').map_lines(str.upper)
+    ///   prog('ls')().map_lines(lambda line: line if line.endswith('.rs') else None)
+    fn map_lines(&self, py: Python, callback: Py<PyAny>) -> PyResult<ShipResult> {
+        let mut stdout = std::io::stdout();
+        let result = for_each_stdout_line(py, self, |line| {
+            let mapped = callback
+                .call1(py, (line_text(line).as_ref(),))
+                .and_then(|mapped| {
+                    mapped.extract::<Option<String>>(py).map_err(|_| {
+                        PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                            "map_lines() callback must return a str or None",
+                        )
+                    })
+                });
+            match mapped {
+                Ok(Some(mapped)) => {
+                    writeln!(stdout, "{}", mapped).ok();
+                }
+                Ok(None) => {}
+                Err(e) => return ControlFlow::Break(e),
+            }
+            ControlFlow::Continue(())
+        });
+        stdout.flush().ok();
+        result
+    }
+
+    /// Run this runnable with its stdout going to stdout as usual, also passing each line to a
+    /// Python callable (e.g. to drive a progress bar)
+    /// If the callable raises, the error is reported once and the output keeps flowing
+    ///
+    /// Usage:
+    ///   prog('make')('all').on_output(lambda line: bar.update())
+    fn on_output(&self, py: Python, callback: Py<PyAny>) -> PyResult<ShipResult> {
+        let mut stdout = std::io::stdout();
+        let mut callback_failed = false;
+
+        for_each_stdout_line(py, self, |line| {
+            // Pass the output through untouched, flushing so it shows up as it's produced
+            stdout.write_all(line).ok();
+            stdout.flush().ok();
+
+            if !callback_failed && let Err(e) = callback.call1(py, (line_text(line).as_ref(),)) {
+                eprintln!("Error in on_output callback:");
+                e.print(py);
+                callback_failed = true;
+            }
+            ControlFlow::Continue(())
+        })
+    }
+
     /// Apply environment overlay to this runnable
     ///
     /// Usage:
@@ -387,6 +997,9 @@ impl ShipRunnable {
         let kwargs = kwargs.ok_or_else(|| {
             PyErr::new::<pyo3::exceptions::PyTypeError, _>("with_env() requires keyword arguments")
         })?;
+        if let Runnable::Then { .. } | Runnable::Retry { .. } = self.0.as_ref() {
+            return Err(not_final(self));
+        }
 
         // Convert **kwargs to HashMap<String, EnvValue>
         let mut overlay = HashMap::new();
@@ -401,6 +1014,7 @@ impl ShipRunnable {
         if let Runnable::WithEnv {
             runnable,
             env_overlay: existing,
+            inherit,
         } = self.0.as_ref()
         {
             let mut merged = existing.clone();
@@ -408,56 +1022,548 @@ impl ShipRunnable {
             Ok(ShipRunnable(Arc::new(Runnable::WithEnv {
                 runnable: runnable.clone(),
                 env_overlay: merged,
+                inherit: *inherit,
             })))
         } else {
             // Wrap this runnable in WithEnv
             Ok(ShipRunnable(Arc::new(Runnable::WithEnv {
                 runnable: self.clone(),
                 env_overlay: overlay,
+                inherit: true,
             })))
         }
     }
-}
-
-#[pyfunction]
-#[pyo3(signature = (name))]
-pub fn prog(name: String) -> PyResult<ShipProgram> {
-    // TODO: Resolve the program from the shell environment
-    Ok(ShipProgram { name })
-}
 
-#[pyfunction]
-#[pyo3(signature = (prog, *args))]
-pub fn cmd(prog: ShipProgram, args: Vec<String>) -> PyResult<ShipRunnable> {
-    // PyO3 automatically converts:
-    // - cmd to String (calls __str__ if needed)
-    // - each arg to String (calls __str__ if needed)
-    Ok(ShipRunnable(Arc::new(Runnable::Command { prog, args })))
-}
+    /// Choose whether this runnable gets the shell's variables
+    /// With False it only gets PATH, HOME and PWD, plus anything set with with_env()
+    ///
+    /// Usage:
+    ///   prog('make')('test').env_inherit(False).with_env(CC='clang')()
+    fn env_inherit(&self, inherit: bool) -> PyResult<ShipRunnable> {
+        match self.0.as_ref() {
+            Runnable::Then { .. } | Runnable::Retry { .. } => Err(not_final(self)),
+            Runnable::WithEnv {
+                runnable,
+                env_overlay,
+                ..
+            } => Ok(ShipRunnable(Arc::new(Runnable::WithEnv {
+                runnable: runnable.clone(),
+                env_overlay: env_overlay.clone(),
+                inherit,
+            }))),
+            _ => Ok(ShipRunnable(Arc::new(Runnable::WithEnv {
+                runnable: self.clone(),
+                env_overlay: HashMap::new(),
+                inherit,
+            }))),
+        }
+    }
 
-#[pyfunction]
-#[pyo3(signature = (cmd1, cmd2, *cmds))]
-pub fn pipe(
-    cmd1: ShipRunnable,
-    cmd2: ShipRunnable,
-    cmds: Vec<ShipRunnable>,
-) -> PyResult<ShipRunnable> {
-    let mut result = cmd1.__or__(&cmd2)?;
-    for cmd in cmds {
-        result = result.__or__(&cmd)?;
+    /// Describe this runnable as plain nested dicts and lists, rebuildable with from_dict()
+    ///
+    /// Each level has a "type" tag: command (program, args), pipeline (stages), subshell
+    /// (runnable), redirect (runnable, target), input_redirect (runnable, source), with_env
+    /// (runnable, env, and inherit when false) or input (runnable, data - a str, or bytes if it
+    /// isn't valid UTF-8)
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyDict>> {
+        let dict = PyDict::new(py);
+        match self.0.as_ref() {
+            Runnable::Command { prog, args } => {
+                dict.set_item("type", "command")?;
+                dict.set_item("program", prog.name())?;
+                dict.set_item("args", args)?;
+            }
+            Runnable::Pipeline {
+                predecessors,
+                final_cmd,
+            } => {
+                let stages = predecessors
+                    .iter()
+                    .chain(std::iter::once(final_cmd))
+                    .map(|stage| stage.to_dict(py))
+                    .collect::<PyResult<Vec<_>>>()?;
+                dict.set_item("type", "pipeline")?;
+                dict.set_item("stages", stages)?;
+            }
+            Runnable::Subshell { runnable } => {
+                dict.set_item("type", "subshell")?;
+                dict.set_item("runnable", runnable.to_dict(py)?)?;
+            }
+            Runnable::Redirect { runnable, target } => {
+                let RedirectTarget::FilePath {
+                    path,
+                    append,
+                    clobber,
+                    mode,
+                } = target
+                else {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                        "to_dict(): a redirect to an open file can't be serialized",
+                    ));
+                };
+                let target = PyDict::new(py);
+                target.set_item("path", path)?;
+                target.set_item("append", append)?;
+                target.set_item("clobber", clobber)?;
+                target.set_item("mode", mode)?;
+                dict.set_item("type", "redirect")?;
+                dict.set_item("runnable", runnable.to_dict(py)?)?;
+                dict.set_item("target", target)?;
+            }
+            Runnable::WithEnv {
+                runnable,
+                env_overlay,
+                inherit,
+            } => {
+                let env = PyDict::new(py);
+                for (key, value) in env_overlay {
+                    env.set_item(key, env_value_to_py(py, value)?)?;
+                }
+                dict.set_item("type", "with_env")?;
+                dict.set_item("runnable", runnable.to_dict(py)?)?;
+                dict.set_item("env", env)?;
+                // Only noted when off, the unusual case
+                if !inherit {
+                    dict.set_item("inherit", false)?;
+                }
+            }
+            Runnable::InputRedirect { runnable, source } => {
+                let RedirectSource::FilePath { path } = source else {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                        "to_dict(): a redirect from an open file can't be serialized",
+                    ));
+                };
+                let source = PyDict::new(py);
+                source.set_item("path", path)?;
+                dict.set_item("type", "input_redirect")?;
+                dict.set_item("runnable", runnable.to_dict(py)?)?;
+                dict.set_item("source", source)?;
+            }
+            Runnable::Input { runnable, data } => {
+                dict.set_item("type", "input")?;
+                dict.set_item("runnable", runnable.to_dict(py)?)?;
+                match std::str::from_utf8(data) {
+                    Ok(text) => dict.set_item("data", text)?,
+                    Err(_) => dict.set_item("data", PyBytes::new(py, data))?,
+                }
+            }
+            Runnable::Then { .. } => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "to_dict(): a then() callback can't be serialized",
+                ));
+            }
+            Runnable::Retry { .. } => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "to_dict(): retry() can't be serialized",
+                ));
+            }
+        }
+        Ok(dict.unbind())
     }
 
-    Ok(result)
+    /// Describe what will run as nested dicts, like to_dict() but for inspection rather than
+    /// rebuilding, so every runnable can be described
+    ///
+    /// Commands also get "builtin" (whether the shell runs it itself rather than a program from
+    /// PATH), redirects to or from an open file give its "fd" instead of a path, and then() and
+    /// retry() appear as "then" (runnable) and "retry" (runnable, attempts, delay, backoff)
+    fn describe(&self, py: Python) -> PyResult<Py<PyDict>> {
+        let dict = PyDict::new(py);
+        match self.0.as_ref() {
+            Runnable::Command { prog, args } => {
+                let program = prog.name();
+                dict.set_item("type", "command")?;
+                dict.set_item("program", program)?;
+                dict.set_item("args", args)?;
+                dict.set_item("builtin", shell::builtins::get_builtin(program).is_some())?;
+            }
+            Runnable::Pipeline {
+                predecessors,
+                final_cmd,
+            } => {
+                let stages = predecessors
+                    .iter()
+                    .chain(std::iter::once(final_cmd))
+                    .map(|stage| stage.describe(py))
+                    .collect::<PyResult<Vec<_>>>()?;
+                dict.set_item("type", "pipeline")?;
+                dict.set_item("stages", stages)?;
+            }
+            Runnable::Subshell { runnable } => {
+                dict.set_item("type", "subshell")?;
+                dict.set_item("runnable", runnable.describe(py)?)?;
+            }
+            Runnable::Redirect { runnable, target } => {
+                let target_dict = PyDict::new(py);
+                match target {
+                    RedirectTarget::FilePath {
+                        path,
+                        append,
+                        clobber,
+                        mode,
+                    } => {
+                        target_dict.set_item("path", path)?;
+                        target_dict.set_item("append", append)?;
+                        target_dict.set_item("clobber", clobber)?;
+                        target_dict.set_item("mode", mode)?;
+                    }
+                    RedirectTarget::FileDescriptor { fd } => target_dict.set_item("fd", fd)?,
+                }
+                dict.set_item("type", "redirect")?;
+                dict.set_item("runnable", runnable.describe(py)?)?;
+                dict.set_item("target", target_dict)?;
+            }
+            Runnable::WithEnv {
+                runnable,
+                env_overlay,
+                inherit,
+            } => {
+                let env = PyDict::new(py);
+                for (key, value) in env_overlay {
+                    env.set_item(key, env_value_to_py(py, value)?)?;
+                }
+                dict.set_item("type", "with_env")?;
+                dict.set_item("runnable", runnable.describe(py)?)?;
+                dict.set_item("env", env)?;
+                dict.set_item("inherit", inherit)?;
+            }
+            Runnable::InputRedirect { runnable, source } => {
+                let source_dict = PyDict::new(py);
+                match source {
+                    RedirectSource::FilePath { path } => source_dict.set_item("path", path)?,
+                    RedirectSource::FileDescriptor { fd } => source_dict.set_item("fd", fd)?,
+                }
+                dict.set_item("type", "input_redirect")?;
+                dict.set_item("runnable", runnable.describe(py)?)?;
+                dict.set_item("source", source_dict)?;
+            }
+            Runnable::Input { runnable, data } => {
+                dict.set_item("type", "input")?;
+                dict.set_item("runnable", runnable.describe(py)?)?;
+                match std::str::from_utf8(data) {
+                    Ok(text) => dict.set_item("data", text)?,
+                    Err(_) => dict.set_item("data", PyBytes::new(py, data))?,
+                }
+            }
+            Runnable::Then { runnable, .. } => {
+                dict.set_item("type", "then")?;
+                dict.set_item("runnable", runnable.describe(py)?)?;
+            }
+            Runnable::Retry {
+                runnable,
+                attempts,
+                delay,
+                backoff,
+            } => {
+                dict.set_item("type", "retry")?;
+                dict.set_item("runnable", runnable.describe(py)?)?;
+                dict.set_item("attempts", attempts)?;
+                dict.set_item("delay", delay)?;
+                dict.set_item("backoff", backoff)?;
+            }
+        }
+        Ok(dict.unbind())
+    }
+
+    /// Inspect the environment overlay applied by with_env()
+    ///
+    /// Returns the merged overlay as a dict, or an empty dict if this
+    /// runnable isn't wrapped in with_env()
+    fn env(&self, py: Python) -> PyResult<Py<PyDict>> {
+        let dict = PyDict::new(py);
+        if let Runnable::WithEnv { env_overlay, .. } = self.0.as_ref() {
+            for (key, value) in env_overlay {
+                dict.set_item(key, env_value_to_py(py, value)?)?;
+            }
+        }
+        Ok(dict.into())
+    }
+}
+
+/// Rebuild a runnable from the description produced by ShipRunnable.to_dict()
+#[pyfunction]
+pub fn from_dict(d: &Bound<PyAny>) -> PyResult<ShipRunnable> {
+    // Missing keys and wrong types are reported as ValueError naming the offending key
+    fn field<'py, T: FromPyObjectOwned<'py>>(d: &Bound<'py, PyAny>, key: &str) -> PyResult<T> {
+        d.get_item(key)
+            .and_then(|value| value.extract::<T>().map_err(Into::into))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "from_dict(): invalid '{}': {}",
+                    key, e
+                ))
+            })
+    }
+
+    let runnable = match field::<String>(d, "type")?.as_str() {
+        "command" => Runnable::Command {
+            prog: ShipProgram::new(field(d, "program")?),
+            args: field(d, "args")?,
+        },
+        "pipeline" => {
+            let stages = field::<Vec<Bound<PyAny>>>(d, "stages")?
+                .iter()
+                .map(from_dict)
+                .collect::<PyResult<Vec<_>>>()?;
+            if stages.len() < 2 {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "from_dict(): a pipeline needs at least two stages",
+                ));
+            }
+            // Join the stages like `|` does, so nested pipelines are flattened and
+            // stages that can't be piped are rejected
+            return pipe(stages);
+        }
+        "subshell" => Runnable::Subshell {
+            runnable: from_dict(&field(d, "runnable")?)?,
+        },
+        "redirect" => {
+            let target: Bound<PyAny> = field(d, "target")?;
+            Runnable::Redirect {
+                runnable: from_dict(&field(d, "runnable")?)?,
+                target: RedirectTarget::FilePath {
+                    path: field(&target, "path")?,
+                    append: field(&target, "append")?,
+                    clobber: field(&target, "clobber")?,
+                    mode: field(&target, "mode")?,
+                },
+            }
+        }
+        "input_redirect" => {
+            let source: Bound<PyAny> = field(d, "source")?;
+            Runnable::InputRedirect {
+                runnable: from_dict(&field(d, "runnable")?)?,
+                source: RedirectSource::FilePath {
+                    path: field(&source, "path")?,
+                },
+            }
+        }
+        "with_env" => {
+            let mut env_overlay = HashMap::new();
+            for (key, value) in field::<Bound<PyDict>>(d, "env")?.iter() {
+                env_overlay.insert(key.extract::<String>()?, py_to_env_value(&value)?);
+            }
+            Runnable::WithEnv {
+                runnable: from_dict(&field(d, "runnable")?)?,
+                env_overlay,
+                inherit: match d.get_item("inherit") {
+                    Ok(_) => field(d, "inherit")?,
+                    Err(_) => true,
+                },
+            }
+        }
+        "input" => {
+            // Text was stored as str, anything else as bytes
+            let data = match field::<String>(d, "data") {
+                Ok(text) => text.into_bytes(),
+                Err(_) => field(d, "data")?,
+            };
+            Runnable::Input {
+                runnable: from_dict(&field(d, "runnable")?)?,
+                data,
+            }
+        }
+        other => {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "from_dict(): unknown runnable type '{}'",
+                other
+            )));
+        }
+    };
+
+    Ok(ShipRunnable(Arc::new(runnable)))
+}
+
+// Exceptions for shell failures, so scripts can catch them specifically
+create_exception!(
+    shp,
+    ShipError,
+    pyo3::exceptions::PyException,
+    "Base class for shell errors"
+);
+create_exception!(
+    shp,
+    CommandNotFound,
+    ShipError,
+    "A command could not be found"
+);
+create_exception!(
+    shp,
+    CommandError,
+    ShipError,
+    "A command exited with a non-zero status"
+);
+create_exception!(
+    shp,
+    RedirectError,
+    ShipError,
+    "Output could not be redirected"
+);
+
+/// Duplicate a file-like redirect target's descriptor for cross-fork safety
+fn dup_target_fd(target: &Bound<PyAny>) -> PyResult<i32> {
+    let fd: i32 = target
+        .call_method0("fileno")
+        .and_then(|fd| fd.extract())
+        .map_err(|e| {
+            RedirectError::new_err(format!("Redirect target has no file descriptor: {}", e))
+        })?;
+
+    let dup_fd = unsafe { libc::dup(fd) };
+    if dup_fd == -1 {
+        return Err(RedirectError::new_err(format!(
+            "Failed to duplicate file descriptor {}: {}",
+            fd,
+            std::io::Error::last_os_error()
+        )));
+    }
+    Ok(dup_fd)
+}
+
+/// Error for composing a runnable after then() or retry(), which would separate it from its
+/// callback or retries
+fn not_final(runnable: &ShipRunnable) -> PyErr {
+    let message = match runnable.0.as_ref() {
+        Runnable::Retry { .. } => {
+            "Cannot extend a runnable with retry() - retry() must be the final operation"
+        }
+        _ => "Cannot extend a runnable with a then() callback - then() must be the final operation",
+    };
+    PyErr::new::<pyo3::exceptions::PyTypeError, _>(message)
+}
+
+/// Error for running a then() or retry() runnable other than by calling it, e.g. capturing it
+/// or running it in the background, which would lose its callback or retries
+fn direct_call_only(runnable: &ShipRunnable) -> PyErr {
+    let message = match runnable.0.as_ref() {
+        Runnable::Retry { .. } => {
+            "retry() only applies when the runnable is called - it can't be captured, streamed \
+             or run in the background"
+        }
+        _ => {
+            "A then() callback only runs when the runnable is called - it can't be captured, \
+             streamed or run in the background"
+        }
+    };
+    PyErr::new::<pyo3::exceptions::PyTypeError, _>(message)
+}
+
+/// Get a program name from a str or path-like object (e.g. pathlib.Path)
+/// A path is always taken literally rather than searched for in PATH - pathlib drops the
+/// leading `./` of Path('./tool'), so it's put back for names without a `/`
+fn program_name(name: &Bound<PyAny>) -> PyResult<String> {
+    if let Ok(name) = name.extract::<String>() {
+        return Ok(name);
+    }
+    if name.hasattr("__fspath__")? {
+        let path = name.py().import("os")?.call_method1("fspath", (name,))?;
+        if let Ok(path) = path.extract::<String>() {
+            return Ok(if path.contains('/') {
+                path
+            } else {
+                format!("./{}", path)
+            });
+        }
+    }
+    Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+        "program name must be a str or path-like object, not {}",
+        name.get_type().name()?
+    )))
+}
+
+#[pyfunction]
+#[pyo3(signature = (name))]
+pub fn prog(name: &Bound<PyAny>) -> PyResult<ShipProgram> {
+    // TODO: Resolve the program from the shell environment
+    Ok(ShipProgram::new(program_name(name)?))
+}
+
+#[pyfunction]
+#[pyo3(signature = (prog, *args))]
+pub fn cmd(prog: &Bound<PyAny>, args: Vec<String>) -> PyResult<ShipRunnable> {
+    // The program may be given as a ShipProgram or by name
+    let prog = match prog.cast::<ShipProgram>() {
+        Ok(prog) => prog.borrow().clone(),
+        Err(_) => ShipProgram::new(program_name(prog)?),
+    };
+    Ok(prog.command(args))
+}
+
+/// Build a pipeline from one or more runnables
+/// A single runnable is returned unchanged, since a one-stage pipeline is just that command
+#[pyfunction]
+#[pyo3(signature = (*cmds))]
+pub fn pipe(cmds: Vec<ShipRunnable>) -> PyResult<ShipRunnable> {
+    let mut cmds = cmds.into_iter();
+    let mut result = cmds.next().ok_or_else(|| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>("pipe() requires at least one command")
+    })?;
+    for cmd in cmds {
+        result = result.__or__(&cmd)?;
+    }
+
+    Ok(result)
 }
 
 #[pyfunction]
 pub fn sub(runnable: ShipRunnable) -> PyResult<ShipRunnable> {
+    if let Runnable::Then { .. } | Runnable::Retry { .. } = runnable.0.as_ref() {
+        return Err(not_final(&runnable));
+    }
     Ok(ShipRunnable(Arc::new(Runnable::Subshell { runnable })))
 }
 
 #[pyfunction]
-pub fn shexec(runnable: &ShipRunnable) -> PyResult<ShipResult> {
-    runnable.__call__()
+pub fn shexec(py: Python, runnable: &ShipRunnable) -> PyResult<ShipResult> {
+    runnable.__call__(py)
+}
+
+/// Read a captured FD to EOF and close it
+fn read_fd_to_end(fd: i32, stream: &str) -> PyResult<Vec<u8>> {
+    // Convert raw FD to File (takes ownership)
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let mut content = Vec::new();
+
+    file.read_to_end(&mut content).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read {}: {}", stream, e))
+    })?;
+
+    // File is automatically closed when dropped
+    Ok(content)
+}
+
+/// Decode captured output using a Python-style error mode ('replace' or 'strict')
+fn decode_output(py: Python, content: Vec<u8>, errors: &str) -> PyResult<String> {
+    match errors {
+        "replace" => Ok(String::from_utf8_lossy(&content).into_owned()),
+        "strict" => String::from_utf8(content).map_err(|e| {
+            match PyUnicodeDecodeError::new_utf8(py, e.as_bytes(), e.utf8_error()) {
+                Ok(err) => PyErr::from_value(err.into_any()),
+                Err(err) => err,
+            }
+        }),
+        _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Unknown error mode '{}' (expected 'replace' or 'strict')",
+            errors
+        ))),
+    }
+}
+
+/// Split a line on runs of whitespace, at most `maxsplit` times (like Python's str.split)
+/// The last field keeps any whitespace inside it
+fn split_fields(line: &str, maxsplit: Option<usize>) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        if maxsplit == Some(fields.len()) {
+            fields.push(rest);
+            break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    fields
 }
 
 /// Result of capturing command output with file descriptors
@@ -467,44 +1573,155 @@ pub struct CapturedResult {
     exit_code: u8,
     stdout_fd: Option<i32>,
     stderr_fd: Option<i32>,
+    /// Interleaved stdout and stderr, only present for capture(..., merge_stderr=True)
+    combined_fd: Option<i32>,
+    /// Output already read by __str__ or __repr__, handed out by the next read instead
+    stdout: Option<Vec<u8>>,
+    stderr: Option<Vec<u8>>,
+}
+
+/// How much of each stream __repr__ shows
+const PREVIEW_CHARS: usize = 40;
+
+/// Read a stream into memory (the first time only) so it can be looked at more than once
+fn buffer_stream(
+    fd: &mut Option<i32>,
+    buffered: &mut Option<Vec<u8>>,
+    stream: &str,
+) -> PyResult<()> {
+    if let Some(fd) = fd.take() {
+        *buffered = Some(read_fd_to_end(fd, stream)?);
+    }
+    Ok(())
+}
+
+/// Take all of a stream, from memory if it was already read
+fn take_stream(
+    fd: &mut Option<i32>,
+    buffered: &mut Option<Vec<u8>>,
+    stream: &str,
+) -> PyResult<Vec<u8>> {
+    if let Some(content) = buffered.take() {
+        return Ok(content);
+    }
+    let fd = fd.take().ok_or_else(|| {
+        PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{} already consumed", stream))
+    })?;
+    read_fd_to_end(fd, stream)
+}
+
+/// Shorten buffered output for __repr__, or note that it was already consumed
+fn preview(buffered: &Option<Vec<u8>>) -> String {
+    let Some(content) = buffered else {
+        return "<consumed>".to_string();
+    };
+    let text = String::from_utf8_lossy(content);
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{:?}...", &text[..end]),
+        None => format!("{:?}", text),
+    }
 }
 
 #[pymethods]
 impl CapturedResult {
     /// Read all stdout, close FD, return as string. Can only call once.
-    fn read_stdout(&mut self) -> PyResult<String> {
-        let fd = self.stdout_fd.take().ok_or_else(|| {
-            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("stdout already consumed")
-        })?;
+    /// errors='replace' substitutes invalid UTF-8, errors='strict' raises UnicodeDecodeError
+    #[pyo3(signature = (errors="replace"))]
+    fn read_stdout(&mut self, py: Python, errors: &str) -> PyResult<String> {
+        let content = take_stream(&mut self.stdout_fd, &mut self.stdout, "stdout")?;
+        decode_output(py, content, errors)
+    }
 
-        // Convert raw FD to File (takes ownership)
-        let mut file = unsafe { File::from_raw_fd(fd) };
-        let mut content = String::new();
+    /// Read all stderr, close FD, return as string. Can only call once.
+    /// errors='replace' substitutes invalid UTF-8, errors='strict' raises UnicodeDecodeError
+    #[pyo3(signature = (errors="replace"))]
+    fn read_stderr(&mut self, py: Python, errors: &str) -> PyResult<String> {
+        let content = take_stream(&mut self.stderr_fd, &mut self.stderr, "stderr")?;
+        decode_output(py, content, errors)
+    }
 
-        file.read_to_string(&mut content).map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read stdout: {}", e))
-        })?;
+    /// Read all stdout, close FD, return the raw bytes. Can only call once.
+    fn read_stdout_bytes(&mut self, py: Python) -> PyResult<Py<PyBytes>> {
+        let content = take_stream(&mut self.stdout_fd, &mut self.stdout, "stdout")?;
+        Ok(PyBytes::new(py, &content).unbind())
+    }
 
-        // File is automatically closed when dropped
-        Ok(content)
+    /// Read all stderr, close FD, return the raw bytes. Can only call once.
+    fn read_stderr_bytes(&mut self, py: Python) -> PyResult<Py<PyBytes>> {
+        let content = take_stream(&mut self.stderr_fd, &mut self.stderr, "stderr")?;
+        Ok(PyBytes::new(py, &content).unbind())
     }
 
-    /// Read all stderr, close FD, return as string. Can only call once.
-    fn read_stderr(&mut self) -> PyResult<String> {
-        let fd = self.stderr_fd.take().ok_or_else(|| {
-            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("stderr already consumed")
-        })?;
+    /// The stdout text (invalid UTF-8 replaced), which stays readable - str() can be called
+    /// again, and the next read_stdout() still returns it all
+    fn __str__(&mut self) -> PyResult<String> {
+        buffer_stream(&mut self.stdout_fd, &mut self.stdout, "stdout")?;
+        match &self.stdout {
+            Some(content) => Ok(String::from_utf8_lossy(content).into_owned()),
+            None => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "stdout already consumed",
+            )),
+        }
+    }
 
-        // Convert raw FD to File (takes ownership)
-        let mut file = unsafe { File::from_raw_fd(fd) };
-        let mut content = String::new();
+    /// The exit code with the start of stdout and stderr, leaving both readable like __str__
+    fn __repr__(&mut self) -> String {
+        // A stream that can't be read shows as consumed rather than failing the repr
+        buffer_stream(&mut self.stdout_fd, &mut self.stdout, "stdout").ok();
+        buffer_stream(&mut self.stderr_fd, &mut self.stderr, "stderr").ok();
+        format!(
+            "<CapturedResult: exit_code={} stdout={} stderr={}>",
+            self.exit_code,
+            preview(&self.stdout),
+            preview(&self.stderr)
+        )
+    }
 
-        file.read_to_string(&mut content).map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read stderr: {}", e))
-        })?;
+    /// Read stdout as whitespace-separated columns, like `ps` or `df` output. Can only call once.
+    /// With header=True the first line names the columns and each row is a dict keyed by those
+    /// names (fields past the last name are dropped); otherwise rows are keyed by position.
+    /// Each row is split at most `maxsplit` times so the last column keeps its spaces (e.g.
+    /// ps's COMMAND) - with a header this defaults to one less than the number of names
+    #[pyo3(signature = (header=true, maxsplit=None))]
+    fn columns(
+        &mut self,
+        py: Python,
+        header: bool,
+        maxsplit: Option<usize>,
+    ) -> PyResult<Vec<Py<PyDict>>> {
+        let output = self.read_stdout(py, "replace")?;
+        let mut lines: Vec<&str> = output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
 
-        // File is automatically closed when dropped
-        Ok(content)
+        let names: Option<Vec<&str>> = match header {
+            true if !lines.is_empty() => Some(lines.remove(0).split_whitespace().collect()),
+            _ => None,
+        };
+        let maxsplit =
+            maxsplit.or_else(|| names.as_ref().map(|names| names.len().saturating_sub(1)));
+
+        lines
+            .into_iter()
+            .map(|line| {
+                let row = PyDict::new(py);
+                let fields = split_fields(line, maxsplit);
+                match &names {
+                    Some(names) => {
+                        for (name, field) in names.iter().zip(fields) {
+                            row.set_item(name, field)?;
+                        }
+                    }
+                    None => {
+                        for (i, field) in fields.into_iter().enumerate() {
+                            row.set_item(i, field)?;
+                        }
+                    }
+                }
+                Ok(row.unbind())
+            })
+            .collect()
     }
 
     /// Get raw stdout FD for manual streaming. YOU MUST CLOSE IT!
@@ -523,27 +1740,381 @@ impl CapturedResult {
         })
     }
 
-    fn __del__(&mut self) {
-        // Safety: close unclosed FDs to prevent FD leaks
-        if let Some(fd) = self.stdout_fd {
+    /// Read stdout and stderr as written, interleaved, close FD, return as string.
+    /// Only available with merge_stderr=True, and can only be read once.
+    #[getter]
+    fn combined(&mut self, py: Python) -> PyResult<String> {
+        let fd = self.combined_fd.take().ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "combined output not captured (use merge_stderr=True) or already consumed",
+            )
+        })?;
+        decode_output(py, read_fd_to_end(fd, "combined output")?, "replace")
+    }
+
+    /// Close any streams that haven't been read or taken. Safe to call more than once.
+    fn close(&mut self) {
+        for fd in [
+            self.stdout_fd.take(),
+            self.stderr_fd.take(),
+            self.combined_fd.take(),
+        ]
+        .into_iter()
+        .flatten()
+        {
             unsafe {
                 libc::close(fd);
             }
         }
-        if let Some(fd) = self.stderr_fd {
-            unsafe {
-                libc::close(fd);
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    /// Close unread streams on leaving the with block; exceptions propagate
+    fn __exit__(
+        &mut self,
+        _exc_type: Py<PyAny>,
+        _exc_value: Py<PyAny>,
+        _traceback: Py<PyAny>,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
+
+impl Drop for CapturedResult {
+    fn drop(&mut self) {
+        // Unread streams are closed when Python frees the result, so discarded captures don't
+        // leak FDs (FDs taken through stdout_fd/stderr_fd belong to the caller)
+        self.close();
+    }
+}
+
+/// A command started with ShipRunnable.spawn()
+#[pyclass]
+pub struct ShipProcess {
+    #[pyo3(get)]
+    pid: i32,
+    #[pyo3(get)]
+    command: String,
+    /// Set once the process has finished and been reaped
+    #[pyo3(get)]
+    exit_code: Option<u8>,
+}
+
+impl ShipProcess {
+    /// Check on the process, blocking until it finishes unless `flags` has WNOHANG
+    fn wait_with(&mut self, py: Python, flags: Option<WaitPidFlag>) -> PyResult<Option<u8>> {
+        while self.exit_code.is_none() {
+            match shell::jobs::wait_process(Pid::from_raw(self.pid), flags) {
+                Ok(WaitStatus::Exited(_pid, code)) => self.exit_code = Some(code as u8),
+                Ok(WaitStatus::Signaled(_pid, signal, _core_dump)) => {
+                    self.exit_code = Some(shell::jobs::signal_exit_code(signal as i32));
+                }
+                // Ctrl-C interrupts a blocking wait as KeyboardInterrupt
+                Err(Errno::EINTR) => py.check_signals()?,
+                Err(e) => {
+                    return Err(PyErr::new::<pyo3::exceptions::PyChildProcessError, _>(
+                        format!("Failed to wait for process {}: {}", self.pid, e),
+                    ));
+                }
+                // Still running (or stopped): only a blocking wait tries again
+                Ok(_) if flags.is_some() => break,
+                Ok(_) => {}
+            }
+        }
+        Ok(self.exit_code)
+    }
+}
+
+#[pymethods]
+impl ShipProcess {
+    /// Return the exit code if the process has finished, or None if it's still running
+    fn poll(&mut self, py: Python) -> PyResult<Option<u8>> {
+        self.wait_with(py, Some(WaitPidFlag::WNOHANG))
+    }
+
+    /// Wait for the process to finish and return its exit code (128 + N if killed by signal N)
+    fn wait(&mut self, py: Python) -> PyResult<u8> {
+        Ok(self.wait_with(py, None)?.unwrap_or_default())
+    }
+
+    /// Send a signal (SIGTERM by default) to the process's group, reaching every pipeline stage
+    /// Does nothing once the process has been reaped, as its PID may have been reused
+    #[pyo3(signature = (sig=libc::SIGTERM))]
+    fn kill(&self, sig: i32) -> PyResult<()> {
+        if self.exit_code.is_some() {
+            return Ok(());
+        }
+        let signal = Signal::try_from(sig).map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid signal {}", sig))
+        })?;
+        killpg(Pid::from_raw(self.pid), signal).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyOSError, _>(format!(
+                "Failed to signal process {}: {}",
+                self.pid, e
+            ))
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        let state = match self.exit_code {
+            Some(code) => format!("exit_code={}", code),
+            None => "running".to_string(),
+        };
+        format!(
+            "<ShipProcess: pid={} {} {:?}>",
+            self.pid, state, self.command
+        )
+    }
+}
+
+/// Set by the SIGINT handler installed by SigintGuard
+static SIGINT_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigint(_signal: libc::c_int) {
+    SIGINT_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+/// Catches Ctrl-C while alive so it interrupts the running command instead of the shell
+/// The command still receives the signal from the terminal; the previous handling is
+/// restored on drop
+struct SigintGuard {
+    previous: SigAction,
+}
+
+impl SigintGuard {
+    fn install() -> PyResult<Self> {
+        SIGINT_RECEIVED.store(false, Ordering::SeqCst);
+        // SA_RESTART keeps the waitpid() for the running command from failing with EINTR
+        let action = SigAction::new(
+            SigHandler::Handler(handle_sigint),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+        let previous = unsafe { sigaction(Signal::SIGINT, &action) }.map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyOSError, _>(format!("Failed to handle SIGINT: {}", e))
+        })?;
+        Ok(SigintGuard { previous })
+    }
+
+    /// Whether Ctrl-C was pressed since the guard was installed
+    fn interrupted(&self) -> bool {
+        SIGINT_RECEIVED.load(Ordering::SeqCst)
+    }
+
+    /// Sleep for `duration` unless Ctrl-C is pressed, returning whether it slept the whole time
+    /// Sleeps in short slices so Ctrl-C is noticed promptly
+    fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.interrupted() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return true;
             }
+            std::thread::sleep(remaining.min(Duration::from_millis(50)));
         }
+        false
     }
 }
 
+impl Drop for SigintGuard {
+    fn drop(&mut self) {
+        unsafe { sigaction(Signal::SIGINT, &self.previous) }.ok();
+    }
+}
+
+/// Close the read ends of a captured result that won't be handed to Python
+fn close_captured(result: &ShellResult) {
+    if let ShellResult::Captured {
+        stdout_fd,
+        stderr_fd,
+        ..
+    } = result
+    {
+        unsafe {
+            libc::close(*stdout_fd);
+            libc::close(*stderr_fd);
+        }
+    }
+}
+
+/// Run a runnable with its stdout on a pipe, handing each line (with its newline, if any) to
+/// `on_line` until the output ends or `on_line` breaks with an error
+/// The GIL is released while waiting for output, so other Python threads keep running while a
+/// slow command works. Returns the exit code, or the error that stopped reading
+fn for_each_stdout_line(
+    py: Python,
+    runnable: &ShipRunnable,
+    mut on_line: impl FnMut(&[u8]) -> ControlFlow<PyErr>,
+) -> PyResult<ShipResult> {
+    let (child, stdout_fd) = spawn_with_stdout_pipe(&runnable.try_into()?);
+    let mut reader = BufReader::new(File::from(stdout_fd));
+    let mut error = None;
+
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match py.detach(|| reader.read_until(b'\n', &mut line)) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                error = Some(PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                    "Failed to read stdout: {}",
+                    e
+                )));
+                break;
+            }
+        }
+        if let ControlFlow::Break(e) = on_line(&line) {
+            error = Some(e);
+            break;
+        }
+    }
+
+    // Close the read end before waiting so an aborted producer gets SIGPIPE
+    drop(reader);
+    let result = wait_spawned(child);
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(ShipResult {
+            exit_code: result.exit_code(),
+        }),
+    }
+}
+
+/// A line of output as passed to Python callbacks: without its trailing newline, and with any
+/// invalid UTF-8 replaced
+fn line_text(line: &[u8]) -> std::borrow::Cow<'_, str> {
+    String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(line))
+}
+
+/// Re-run a runnable every `interval` seconds with a cleared screen, like watch(1)
+/// Stops after `count` runs (if given) or on Ctrl-C, returning the last run's result
+#[pyfunction]
+#[pyo3(signature = (runnable, interval=2.0, count=None))]
+pub fn watch(runnable: &ShipRunnable, interval: f64, count: Option<u64>) -> PyResult<ShipResult> {
+    if !interval.is_finite() || interval <= 0.0 {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "interval must be a positive number of seconds",
+        ));
+    }
+    if count == Some(0) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "count must be at least 1",
+        ));
+    }
+
+    let request = ExecRequest::try_from(runnable)?;
+
+    // Catch Ctrl-C ourselves so it ends the watch instead of the shell
+    let guard = SigintGuard::install()?;
+
+    let tick = Duration::from_secs_f64(interval);
+    let mut runs = 0;
+    let mut last_result = ShipResult { exit_code: 0 };
+
+    while !guard.interrupted() {
+        // Clear the screen and move the cursor home, on a terminal only so piped or captured
+        // output is just the runs one after another
+        if shell::builtins::stdout_is_tty() {
+            print!("\x1b[H\x1b[2J");
+        }
+        println!("Every {}s: {}  (Ctrl-C to stop)\n", interval, request);
+        std::io::stdout().flush().ok();
+
+        last_result = ShipResult {
+            exit_code: execute(&request).exit_code(),
+        };
+        runs += 1;
+        if count.is_some_and(|count| runs >= count) {
+            break;
+        }
+
+        guard.sleep(tick);
+    }
+
+    Ok(last_result)
+}
+
+/// Run a runnable until it succeeds, at most `attempts` times, for ShipRunnable.retry()
+/// The pause between tries starts at `delay` seconds and grows by `backoff` each time
+/// Returns the last try's result, including when Ctrl-C stops the retries
+fn run_with_retries(
+    py: Python,
+    runnable: &ShipRunnable,
+    attempts: u32,
+    delay: f64,
+    backoff: f64,
+) -> PyResult<ShipResult> {
+    // Catch Ctrl-C ourselves so it ends the retries instead of the shell
+    let guard = SigintGuard::install()?;
+
+    let mut pause = delay;
+    for attempt in 1.. {
+        let result = runnable.__call__(py)?;
+        if result.exit_code == 0 || attempt >= attempts || guard.interrupted() {
+            return Ok(result);
+        }
+
+        // A pause grown too long for a Duration is as good as forever
+        if !guard.sleep(Duration::try_from_secs_f64(pause).unwrap_or(Duration::MAX)) {
+            return Ok(result);
+        }
+        pause *= backoff;
+    }
+    unreachable!()
+}
+
+/// Create a read FD that is immediately at EOF
+fn empty_stream() -> PyResult<i32> {
+    let (read, write) = nix::unistd::pipe().map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyOSError, _>(format!("Failed to create pipe: {}", e))
+    })?;
+    drop(write);
+    Ok(read.into_raw_fd())
+}
+
 /// Execute a runnable and capture its stdout and stderr
+/// With merge_stderr=True both streams go to one pipe, read through `combined`, and the
+/// separate streams are empty
+/// With pty=True stdout is a pseudo-terminal, so tools that check for one (ls columns, colours,
+/// line buffering) behave as they would interactively; merged stderr goes to it too
+/// Like running it directly, this sets $? to the exit code
 #[pyfunction]
-pub fn capture(runnable: &ShipRunnable) -> PyResult<CapturedResult> {
-    let result = execute_with_capture(&runnable.into());
+#[pyo3(signature = (runnable, merge_stderr=false, pty=false))]
+pub fn capture(runnable: &ShipRunnable, merge_stderr: bool, pty: bool) -> PyResult<CapturedResult> {
+    let request = ExecRequest::try_from(runnable)?;
+    // On Ctrl-C the command is interrupted and KeyboardInterrupt is raised with nothing left open
+    let guard = SigintGuard::install()?;
+    let result = if pty {
+        execute_with_pty_capture(&request, merge_stderr)
+    } else if merge_stderr {
+        execute_with_merged_capture(&request)
+    } else {
+        execute_with_capture(&request)
+    };
+    if guard.interrupted() {
+        close_captured(&result);
+        return Err(PyErr::new::<pyo3::exceptions::PyKeyboardInterrupt, _>(()));
+    }
 
     match result {
+        ShellResult::Captured {
+            exit_code,
+            stdout_fd,
+            stderr_fd,
+        } if merge_stderr => Ok(CapturedResult {
+            exit_code,
+            stdout_fd: Some(empty_stream()?),
+            stderr_fd: Some(stderr_fd),
+            combined_fd: Some(stdout_fd),
+            stdout: None,
+            stderr: None,
+        }),
         ShellResult::Captured {
             exit_code,
             stdout_fd,
@@ -552,6 +2123,9 @@ pub fn capture(runnable: &ShipRunnable) -> PyResult<CapturedResult> {
             exit_code,
             stdout_fd: Some(stdout_fd),
             stderr_fd: Some(stderr_fd),
+            combined_fd: None,
+            stdout: None,
+            stderr: None,
         }),
         ShellResult::ExitOnly { .. } => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
             "Expected captured result but got exit-only result",
@@ -559,18 +2133,60 @@ pub fn capture(runnable: &ShipRunnable) -> PyResult<CapturedResult> {
     }
 }
 
+/// Execute a pipeline and capture the stdout and stderr of every stage
+/// Returns one CapturedResult per stage, in pipeline order
+#[pyfunction]
+pub fn capture_all(runnable: &ShipRunnable) -> PyResult<Vec<CapturedResult>> {
+    let request = ExecRequest::try_from(runnable)?;
+    let guard = SigintGuard::install()?;
+    let results = execute_capture_all(&request);
+    if guard.interrupted() {
+        results.iter().for_each(close_captured);
+        return Err(PyErr::new::<pyo3::exceptions::PyKeyboardInterrupt, _>(()));
+    }
+
+    results
+        .into_iter()
+        .map(|result| match result {
+            ShellResult::Captured {
+                exit_code,
+                stdout_fd,
+                stderr_fd,
+            } => Ok(CapturedResult {
+                exit_code,
+                stdout_fd: Some(stdout_fd),
+                stderr_fd: Some(stderr_fd),
+                combined_fd: None,
+                stdout: None,
+                stderr: None,
+            }),
+            ShellResult::ExitOnly { .. } => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Expected captured result but got exit-only result",
+            )),
+        })
+        .collect()
+}
+
 /// Convenience function: execute and return just stdout as a string
 #[pyfunction]
-pub fn get_stdout(runnable: &ShipRunnable) -> PyResult<String> {
-    let mut result = capture(runnable)?;
-    result.read_stdout()
+pub fn get_stdout(py: Python, runnable: &ShipRunnable) -> PyResult<String> {
+    let mut result = capture(runnable, false, false)?;
+    result.read_stdout(py, "replace")
+}
+
+/// Command substitution, like $(...): execute now and return stdout without trailing newlines
+/// Runs when called, so the output is fixed at argument-build time
+#[pyfunction]
+pub fn sub_out(py: Python, runnable: &ShipRunnable) -> PyResult<String> {
+    let output = get_stdout(py, runnable)?;
+    Ok(output.trim_end_matches('\n').to_string())
 }
 
 /// Convenience function: execute and return just stderr as a string
 #[pyfunction]
-pub fn get_stderr(runnable: &ShipRunnable) -> PyResult<String> {
-    let mut result = capture(runnable)?;
-    result.read_stderr()
+pub fn get_stderr(py: Python, runnable: &ShipRunnable) -> PyResult<String> {
+    let mut result = capture(runnable, false, false)?;
+    result.read_stderr(py, "replace")
 }
 
 /// Get an environment variable
@@ -586,10 +2202,211 @@ pub fn get_env(py: Python, key: String) -> PyResult<Py<PyAny>> {
 #[pyfunction]
 pub fn set_env(key: String, value: Bound<PyAny>) -> PyResult<()> {
     let env_value = py_to_env_value(&value)?;
-    shell::set_var(key, env_value);
+    shell::set_var(key, env_value)?;
+    Ok(())
+}
+
+/// Get the terminal size as (columns, rows)
+/// Without a terminal, falls back to $COLUMNS and $LINES, then 80x24
+#[pyfunction]
+pub fn terminal_size() -> (u16, u16) {
+    shell::terminal::refresh_terminal_size();
+    shell::terminal::terminal_size().unwrap_or_else(|| {
+        let dimension = |key: &str, default: u16| match shell::get_var(key) {
+            Some(EnvValue::Integer(n)) if n > 0 => u16::try_from(n).unwrap_or(default),
+            _ => default,
+        };
+        (dimension("COLUMNS", 80), dimension("LINES", 24))
+    })
+}
+
+/// Put a directory at the front of sys.path so modules in it can be imported
+/// The directory is made absolute (after ~ expansion) so a later cd doesn't change what it
+/// refers to; if it was already on sys.path it moves to the front
+#[pyfunction]
+pub fn add_path(py: Python, dir: PathBuf) -> PyResult<()> {
+    let os_path = py.import("os")?.getattr("path")?;
+    let dir = os_path.call_method1("expanduser", (dir,))?;
+    let dir: String = os_path.call_method1("abspath", (dir,))?.extract()?;
+
+    let sys_path = py.import("sys")?.getattr("path")?;
+    while sys_path.contains(&dir)? {
+        sys_path.call_method1("remove", (&dir,))?;
+    }
+    sys_path.call_method1("insert", (0, &dir))?;
+
+    // The import system caches directory listings, which may predate new modules
+    py.import("importlib")?.call_method0("invalidate_caches")?;
     Ok(())
 }
 
+/// Add the directories listed in $SHIP_PYTHONPATH to sys.path, keeping their order
+pub fn add_ship_pythonpath(py: Python) -> PyResult<()> {
+    let Some(value) = shell::get_var("SHIP_PYTHONPATH") else {
+        return Ok(());
+    };
+    let value = value.to_string_repr();
+    for dir in value.split(':').filter(|dir| !dir.is_empty()).rev() {
+        add_path(py, PathBuf::from(dir))?;
+    }
+    Ok(())
+}
+
+/// Create a temporary file and return its path
+#[pyfunction]
+#[pyo3(signature = (dir=None, suffix="", cleanup=true))]
+pub fn mktemp(
+    py: Python,
+    dir: Option<PathBuf>,
+    suffix: &str,
+    cleanup: bool,
+) -> PyResult<Py<PyAny>> {
+    make_temp_path(py, dir, suffix, false, cleanup)
+}
+
+/// Create a temporary directory and return its path
+#[pyfunction]
+#[pyo3(signature = (dir=None, suffix="", cleanup=true))]
+pub fn mktemp_dir(
+    py: Python,
+    dir: Option<PathBuf>,
+    suffix: &str,
+    cleanup: bool,
+) -> PyResult<Py<PyAny>> {
+    make_temp_path(py, dir, suffix, true, cleanup)
+}
+
+/// Shared implementation of mktemp() and mktemp_dir()
+fn make_temp_path(
+    py: Python,
+    dir: Option<PathBuf>,
+    suffix: &str,
+    directory: bool,
+    cleanup: bool,
+) -> PyResult<Py<PyAny>> {
+    let path = shell::make_temp(dir.as_deref(), suffix, directory, cleanup).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to create temp path: {}", e))
+    })?;
+    env_value_to_py(py, &EnvValue::FilePath(path))
+}
+
+/// Build a command from a shell-style command line, e.g. sh("git commit -m 'first commit'")
+/// Words are split like a POSIX shell (quotes and backslashes) with no expansion - see
+/// shell::split_words for what is and isn't supported
+#[pyfunction]
+pub fn sh(command_line: &str) -> PyResult<ShipRunnable> {
+    let mut words = shell::split_words(command_line)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("sh(): {}", e)))?
+        .into_iter();
+    let name = words.next().ok_or_else(|| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>("sh() requires a command")
+    })?;
+
+    Ok(ShipRunnable(Arc::new(Runnable::Command {
+        prog: ShipProgram::new(name),
+        args: words.collect(),
+    })))
+}
+
+/// Quote a string so a POSIX shell would read it as a single literal word
+#[pyfunction]
+pub fn quote(s: &str) -> String {
+    shell::shell_quote(s)
+}
+
+/// Get the value of a shell option
+#[pyfunction]
+pub fn get_option(name: String) -> PyResult<bool> {
+    shell::get_option(&name).ok_or_else(|| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unknown shell option '{}'", name))
+    })
+}
+
+/// Set the value of a shell option
+#[pyfunction]
+pub fn set_option(name: String, value: bool) -> PyResult<()> {
+    if shell::set_option(&name, value) {
+        Ok(())
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Unknown shell option '{}'",
+            name
+        )))
+    }
+}
+
+/// Get the buffer size set for the pipes between pipeline stages, or None for the default
+#[pyfunction]
+pub fn get_pipe_size() -> Option<usize> {
+    shell::get_pipe_size()
+}
+
+/// Set the buffer size in bytes for the pipes between pipeline stages, or None for the default
+/// Linux only (a no-op elsewhere); sizes above /proc/sys/fs/pipe-max-size are reduced to it
+#[pyfunction]
+pub fn set_pipe_size(size: Option<usize>) -> PyResult<()> {
+    if size == Some(0) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "pipe size must be positive",
+        ));
+    }
+    shell::set_pipe_size(size);
+    Ok(())
+}
+
+/// List background jobs as dicts with id, pid, state and command
+/// Finished jobs are included once and then removed from the job table
+#[pyfunction]
+pub fn jobs(py: Python) -> PyResult<Py<PyList>> {
+    let list = PyList::empty(py);
+    for job in shell::jobs::report_jobs() {
+        let dict = PyDict::new(py);
+        dict.set_item("id", job.id)?;
+        dict.set_item("pid", job.pid.as_raw())?;
+        dict.set_item("state", job.state.describe())?;
+        dict.set_item("command", job.command)?;
+        list.append(dict)?;
+    }
+    Ok(list.unbind())
+}
+
+/// Get the exit status of the most recently executed command ($?)
+#[pyfunction]
+pub fn last_exit() -> PyResult<i64> {
+    shell_integer("?", shell::get_last_exit())
+}
+
+/// Get the shell's process ID ($$)
+#[pyfunction]
+pub fn pid() -> PyResult<i64> {
+    shell_integer("$", shell::get_pid())
+}
+
+/// Get the shell's parent process ID ($PPID)
+#[pyfunction]
+pub fn ppid() -> PyResult<i64> {
+    shell_integer("PPID", shell::get_ppid())
+}
+
+/// Unwrap a shell-managed variable, which the environment only lets hold an integer
+fn shell_integer(name: &str, value: EnvValue) -> PyResult<i64> {
+    match value {
+        EnvValue::Integer(i) => Ok(i),
+        other => Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+            "{}: expected an integer, found {:?}",
+            name, other
+        ))),
+    }
+}
+
+/// Get the directory stack saved by pushd, in the order dirs lists it (without the current
+/// directory) - popd returns to the last entry
+/// Returns a new list each call: the stack only changes through pushd and popd
+#[pyfunction]
+pub fn dirstack(py: Python) -> PyResult<Py<PyAny>> {
+    env_value_to_py(py, &shell::get_dir_stack())
+}
+
 /// Dictionary-like access to environment variables
 #[pyclass]
 pub struct ShipEnv;
@@ -605,12 +2422,12 @@ impl ShipEnv {
 
     fn __setitem__(&self, key: String, value: Bound<PyAny>) -> PyResult<()> {
         let env_value = py_to_env_value(&value)?;
-        shell::set_var(key, env_value);
+        shell::set_var(key, env_value)?;
         Ok(())
     }
 
     fn __delitem__(&self, key: String) -> PyResult<()> {
-        match shell::unset_var(&key) {
+        match shell::unset_var(&key)? {
             Some(_) => Ok(()),
             None => Err(PyKeyError::new_err(format!("Key '{}' not found", key))),
         }
@@ -624,6 +2441,13 @@ impl ShipEnv {
         Ok(shell::var_count())
     }
 
+    /// Iterate over variable names, like a dict
+    /// Iterates over a snapshot, so the environment can be changed inside the loop
+    fn __iter__(&self, py: Python) -> PyResult<Py<PyIterator>> {
+        let keys = shell::all_var_keys();
+        Ok(PyList::new(py, &keys)?.try_iter()?.into())
+    }
+
     fn keys(&self, py: Python) -> PyResult<Py<PyList>> {
         let keys = shell::all_var_keys();
         Ok(PyList::new(py, &keys)?.into())
@@ -655,4 +2479,265 @@ impl ShipEnv {
             },
         }
     }
+
+    /// Get a value nested inside a variable: the variable's name, then list indexes
+    /// Returns the default if the variable is unset or a step doesn't lead anywhere: an index out
+    /// of range, indexing a value that isn't a list, or a key that isn't an index (environment
+    /// values can be lists but not dicts, so a string key never matches)
+    ///
+    /// Usage:
+    ///   shp.env.get_in(['PATH', 0])  # first PATH entry
+    #[pyo3(signature = (path, default=None))]
+    fn get_in(
+        &self,
+        py: Python,
+        path: Vec<Bound<PyAny>>,
+        default: Option<Bound<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let Some((key, steps)) = path.split_first() else {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "get_in(): path must start with a variable name",
+            ));
+        };
+        let mut leaf = shell::get_var(&key.extract::<String>()?);
+        for step in steps {
+            leaf = match step.extract::<i64>() {
+                Ok(index) => leaf.and_then(|value| value.item(index).cloned()),
+                Err(_) => None,
+            };
+        }
+        match leaf {
+            Some(value) => env_value_to_py(py, &value),
+            None => Ok(default.map_or_else(|| py.None(), Bound::unbind)),
+        }
+    }
+
+    /// Remove all variables except readonly ones
+    fn clear(&self) {
+        shell::clear_vars();
+    }
+
+    /// Merge a dict into the environment: shp.env |= {'DEBUG': '1', 'PORT': 8080}
+    /// Every value is converted before any is set, so a bad value changes nothing
+    fn __ior__(&self, other: &Bound<PyAny>) -> PyResult<()> {
+        let dict = other.cast::<PyDict>().map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                "unsupported operand type(s) for |=: 'ShipEnv' and '{}'",
+                other
+                    .get_type()
+                    .name()
+                    .map(|n| n.to_string())
+                    .unwrap_or_default()
+            ))
+        })?;
+
+        let values = dict
+            .iter()
+            .map(|(key, value)| Ok((key.extract::<String>()?, py_to_env_value(&value)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        for (key, value) in values {
+            shell::set_var(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Remove a variable and return its value
+    /// Like dict.pop(), a missing key returns the default if one is given and raises KeyError
+    /// otherwise
+    #[pyo3(signature = (key, *default))]
+    fn pop(&self, py: Python, key: String, default: Bound<PyTuple>) -> PyResult<Py<PyAny>> {
+        if default.len() > 1 {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                "pop expected at most 2 arguments, got {}",
+                default.len() + 1
+            )));
+        }
+
+        match shell::unset_var(&key)? {
+            Some(value) => env_value_to_py(py, &value),
+            None => match default.get_item(0) {
+                Ok(d) => Ok(d.unbind()),
+                Err(_) => Err(PyKeyError::new_err(format!("Key '{}' not found", key))),
+            },
+        }
+    }
+
+    /// Add entries to the end of a list variable such as PATH, skipping ones it already has
+    /// Entries match regardless of type, so '/usr/bin' is already in a PATH of Paths
+    ///
+    /// Usage:
+    ///   shp.env.append('PATH', '/opt/tools/bin', Path.home() / 'bin')
+    #[pyo3(signature = (key, *entries))]
+    fn append(&self, key: &str, entries: Bound<PyTuple>) -> PyResult<()> {
+        add_entries(key, &entries, false)
+    }
+
+    /// Add entries to the front of a list variable such as PATH
+    /// Entries it already has are moved to the front rather than repeated
+    ///
+    /// Usage:
+    ///   shp.env.prepend('PATH', Path.home() / '.local' / 'bin')
+    #[pyo3(signature = (key, *entries))]
+    fn prepend(&self, key: &str, entries: Bound<PyTuple>) -> PyResult<()> {
+        add_entries(key, &entries, true)
+    }
+
+    /// The KEY=VALUE strings a command run now would get as its environment, sorted by name
+    /// Lists are joined and None is exported empty, while $?, $$, PPID and OLDPWD are left out
+    fn export_list(&self) -> Vec<String> {
+        shell::export_list()
+    }
+
+    /// Mirror shell variable changes into os.environ
+    ///
+    /// With import_existing, variables already in os.environ that differ from the shell are
+    /// first copied into the shell environment (readonly variables are left alone)
+    #[pyo3(signature = (enabled=true, import_existing=false))]
+    fn sync_os(&self, py: Python, enabled: bool, import_existing: bool) -> PyResult<()> {
+        if enabled && import_existing {
+            let environ = py.import("os")?.getattr("environ")?;
+            for item in environ.call_method0("items")?.try_iter()? {
+                let (key, value): (String, String) = item?.extract()?;
+                let current = shell::get_var(&key).map(|v| v.to_string_repr());
+                if current.as_deref() != Some(value.as_str()) {
+                    shell::set_var(key, EnvValue::parse_from_string(&value)).ok();
+                }
+            }
+        }
+
+        shell::set_os_sync(enabled);
+        Ok(())
+    }
+
+    /// Check if shell variable changes are mirrored into os.environ
+    fn os_sync_enabled(&self) -> bool {
+        shell::os_sync_enabled()
+    }
+
+    /// Substitute shell variables into a template string
+    /// Supports $VAR, ${VAR} and ${VAR:-default}; $$ is a literal $
+    fn expand(&self, template: &str) -> PyResult<String> {
+        expand_template(template)
+    }
+}
+
+/// Convert entries for ShipEnv.append()/prepend() and add them to the list variable `key`
+fn add_entries(key: &str, entries: &Bound<PyTuple>, prepend: bool) -> PyResult<()> {
+    let entries = entries
+        .iter()
+        .map(|entry| py_to_env_value(&entry))
+        .collect::<PyResult<Vec<_>>>()?;
+    shell::add_to_list(key, entries, prepend)?;
+    Ok(())
+}
+
+/// Expand variable references in a template using the shell environment
+///
+/// Unset variables expand to nothing. ${VAR:-default} uses the (itself expanded) default when
+/// VAR is unset or empty. A $ not followed by a name or brace is kept as is
+fn expand_template(template: &str) -> PyResult<String> {
+    let bad_substitution = |reference: &str| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "expand(): bad substitution: {}",
+            reference
+        ))
+    };
+    let is_name_char = |c: char| c == '_' || c.is_ascii_alphanumeric();
+
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            // Find the matching brace, allowing ${...} nested in a default
+            let mut depth = 1;
+            let end = after
+                .char_indices()
+                .find(|&(i, c)| {
+                    match c {
+                        '{' if after[..i].ends_with('$') => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                })
+                .map(|(i, _)| i)
+                .ok_or_else(|| bad_substitution(&format!("${{{}", after)))?;
+            let reference = &after[..end];
+            rest = &after[end + 1..];
+
+            let (name, default) = match reference.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (reference, None),
+            };
+            if name.is_empty() || name.contains([':', '{', '}', '$']) {
+                return Err(bad_substitution(&format!("${{{}}}", reference)));
+            }
+
+            let value = shell::get_var(name)
+                .map(|v| v.to_string_repr())
+                .unwrap_or_default();
+            match default {
+                Some(default) if value.is_empty() => result.push_str(&expand_template(default)?),
+                _ => result.push_str(&value),
+            }
+        } else if rest.starts_with(|c: char| c == '_' || c.is_ascii_alphabetic()) {
+            let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            if let Some(value) = shell::get_var(&rest[..end]) {
+                result.push_str(&value.to_string_repr());
+            }
+            rest = &rest[end..];
+        } else {
+            result.push('$');
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_template_reference_forms() {
+        shell::set_var(
+            "EXPAND_TEST_NAME".to_string(),
+            EnvValue::String("ship".to_string()),
+        )
+        .unwrap();
+        shell::set_var("EXPAND_TEST_EMPTY".to_string(), EnvValue::None).unwrap();
+
+        let expand = |template: &str| expand_template(template).unwrap();
+        assert_eq!(expand("hi $EXPAND_TEST_NAME!"), "hi ship!");
+        assert_eq!(expand("${EXPAND_TEST_NAME}s"), "ships");
+        assert_eq!(expand("[$EXPAND_TEST_UNSET]"), "[]");
+        assert_eq!(expand("${EXPAND_TEST_UNSET:-fallback}"), "fallback");
+        assert_eq!(expand("${EXPAND_TEST_EMPTY:-fallback}"), "fallback");
+        assert_eq!(expand("${EXPAND_TEST_NAME:-fallback}"), "ship");
+        assert_eq!(
+            expand("${EXPAND_TEST_UNSET:-${EXPAND_TEST_NAME}yard}"),
+            "shipyard"
+        );
+    }
+
+    #[test]
+    fn expand_template_literal_dollars() {
+        let expand = |template: &str| expand_template(template).unwrap();
+        assert_eq!(expand("cost: $$5"), "cost: $5");
+        assert_eq!(expand("$1 and $ alone"), "$1 and $ alone");
+        assert_eq!(expand("trailing $"), "trailing $");
+    }
+
+    #[test]
+    fn expand_template_bad_substitutions() {
+        assert!(expand_template("${EXPAND_TEST_NAME").is_err());
+        assert!(expand_template("${}").is_err());
+        assert!(expand_template("${A:B}").is_err());
+    }
 }
@@ -1,8 +1,12 @@
+use crate::shell::{EnvValue, ExecRequest};
 use reedline::{
-    Prompt, PromptEditMode, PromptHistorySearch, PromptHistorySearchStatus, Reedline, Signal,
+    ColumnarMenu, Completer, Emacs, KeyCode, KeyModifiers, MenuBuilder, Prompt, PromptEditMode,
+    PromptHistorySearch, PromptHistorySearchStatus, Reedline, ReedlineEvent, ReedlineMenu, Signal,
+    Span, Suggestion, default_emacs_keybindings,
 };
 use std::borrow::Cow;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{OnceLock, RwLock};
 
 /// REPL state storage
@@ -10,8 +14,21 @@ pub struct REPLState {
     pub primary_prompt: String,
     pub continuation_prompt: String,
     pub right_prompt: String,
+    pub autorun: bool,
+    /// Executed statements, oldest first (bounded by HISTORY_LIMIT)
+    pub history: VecDeque<String>,
+    /// Skip recording a statement identical to the previous one (like HISTCONTROL=ignoredups)
+    pub ignore_dups: bool,
+    /// Keep statements typed with leading whitespace out of history (like ignorespace)
+    pub ignore_space: bool,
 }
 
+/// Number of executed statements kept in history
+const HISTORY_LIMIT: usize = 1000;
+
+/// Set when fc recorded the edited code, which takes the place of the statement that ran fc
+static HISTORY_REPLACED: AtomicBool = AtomicBool::new(false);
+
 /// Global REPL state instance
 static REPL_STATE: OnceLock<RwLock<REPLState>> = OnceLock::new();
 
@@ -22,6 +39,10 @@ fn get_repl_state() -> &'static RwLock<REPLState> {
             primary_prompt: "ship> ".to_string(),
             continuation_prompt: "..... ".to_string(),
             right_prompt: String::new(),
+            autorun: true,
+            history: VecDeque::new(),
+            ignore_dups: false,
+            ignore_space: false,
         })
     })
 }
@@ -68,6 +89,123 @@ pub fn get_right_prompt() -> String {
     state_read.right_prompt.clone()
 }
 
+/// Enable or disable auto-running of bare runnable expressions
+pub fn set_autorun(enabled: bool) {
+    let state = get_repl_state();
+    let mut state_write = state.write().unwrap();
+    state_write.autorun = enabled;
+}
+
+/// Check whether bare runnable expressions are auto-run
+pub fn get_autorun() -> bool {
+    let state = get_repl_state();
+    let state_read = state.read().unwrap();
+    state_read.autorun
+}
+
+/// Set which statements are kept out of history; None leaves a setting unchanged
+pub fn set_history_control(ignore_dups: Option<bool>, ignore_space: Option<bool>) {
+    let state = get_repl_state();
+    let mut state_write = state.write().unwrap();
+    if let Some(ignore_dups) = ignore_dups {
+        state_write.ignore_dups = ignore_dups;
+    }
+    if let Some(ignore_space) = ignore_space {
+        state_write.ignore_space = ignore_space;
+    }
+}
+
+/// Get the history control settings as (ignore_dups, ignore_space)
+pub fn get_history_control() -> (bool, bool) {
+    let state = get_repl_state();
+    let state_read = state.read().unwrap();
+    (state_read.ignore_dups, state_read.ignore_space)
+}
+
+/// Record an executed statement, dropping the oldest once the history is full
+fn record_history(code: &str) {
+    let state = get_repl_state();
+    let mut state_write = state.write().unwrap();
+    if state_write.ignore_dups && state_write.history.back().is_some_and(|last| last == code) {
+        return;
+    }
+    if state_write.history.len() == HISTORY_LIMIT {
+        state_write.history.pop_front();
+    }
+    state_write.history.push_back(code.to_string());
+}
+
+/// Get the most recently executed statement
+fn last_history_entry() -> Option<String> {
+    let state = get_repl_state();
+    let state_read = state.read().unwrap();
+    state_read.history.back().cloned()
+}
+
+/// Open the previous statement in an editor, then run what was saved (like fc in other shells)
+/// Returns the exit status: the last command's status, or 1 if nothing could be run
+pub fn edit_last() -> i32 {
+    let Some(last) = last_history_entry() else {
+        eprintln!("fc: no command in history");
+        return 1;
+    };
+    let Some(mut editor) = crate::shell::builtins::find_editor() else {
+        eprintln!("fc: no editor found (set EDITOR)");
+        return 1;
+    };
+
+    let path = match crate::shell::make_temp(None, ".py", false, true) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("fc: cannot create temporary file: {}", e);
+            return 1;
+        }
+    };
+    if let Err(e) = std::fs::write(&path, format!("{}\n", last)) {
+        eprintln!("fc: {}: {}", path.display(), e);
+        return 1;
+    }
+
+    let name = editor.remove(0);
+    editor.push(path.to_string_lossy().to_string());
+    let status = crate::shell::execute(&ExecRequest::Program { name, args: editor }).exit_code();
+    if status != 0 {
+        eprintln!("fc: editor exited with status {}", status);
+        return status as i32;
+    }
+
+    let code = match std::fs::read_to_string(&path) {
+        Ok(code) => code.trim_end().to_string(),
+        Err(e) => {
+            eprintln!("fc: {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    std::fs::remove_file(&path).ok();
+    if code.trim().is_empty() {
+        return 0;
+    }
+
+    // Show what is about to run, as other shells do
+    println!("{}", code);
+    fire_before_execute_hooks(&code);
+    let result = CODE_EXECUTOR.get().map(|executor| executor(&code));
+    fire_after_execute_hooks(&code);
+    record_history(&code);
+    HISTORY_REPLACED.store(true, Ordering::SeqCst);
+
+    match result {
+        Some(Err(e)) => {
+            eprintln!("Error executing code: {}", e);
+            1
+        }
+        _ => match crate::shell::get_last_exit() {
+            EnvValue::Integer(code) => code as i32,
+            _ => 0,
+        },
+    }
+}
+
 /// Hook types
 pub type BeforePromptHook = Box<dyn Fn() + Send + Sync>;
 pub type BeforeContinuationHook = Box<dyn Fn(&str, &str) + Send + Sync>;
@@ -80,12 +218,13 @@ static BEFORE_CONTINUATION_COUNTER: AtomicU64 = AtomicU64::new(1);
 static BEFORE_EXECUTE_COUNTER: AtomicU64 = AtomicU64::new(1);
 static AFTER_EXECUTE_COUNTER: AtomicU64 = AtomicU64::new(1);
 
-/// Hook storage with IDs (Vec maintains registration order)
+/// Hook storage with IDs and priorities
+/// Each Vec is kept in firing order: higher priority first, then registration order
 struct Hooks {
-    before_prompt: Vec<(u64, BeforePromptHook)>,
-    before_continuation: Vec<(u64, BeforeContinuationHook)>,
-    before_execute: Vec<(u64, BeforeExecuteHook)>,
-    after_execute: Vec<(u64, AfterExecuteHook)>,
+    before_prompt: Vec<(u64, i32, BeforePromptHook)>,
+    before_continuation: Vec<(u64, i32, BeforeContinuationHook)>,
+    before_execute: Vec<(u64, i32, BeforeExecuteHook)>,
+    after_execute: Vec<(u64, i32, AfterExecuteHook)>,
 }
 
 static HOOKS: OnceLock<RwLock<Hooks>> = OnceLock::new();
@@ -101,32 +240,42 @@ fn get_hooks() -> &'static RwLock<Hooks> {
     })
 }
 
+/// Insert a hook after all hooks of higher or equal priority (stable within a priority)
+fn insert_by_priority<H>(hooks: &mut Vec<(u64, i32, H)>, id: u64, priority: i32, hook: H) {
+    let pos = hooks
+        .iter()
+        .position(|(_, existing, _)| *existing < priority)
+        .unwrap_or(hooks.len());
+    hooks.insert(pos, (id, priority, hook));
+}
+
 /// Register hooks - returns unique ID for the hook
-pub fn register_before_prompt_hook(hook: BeforePromptHook) -> u64 {
+/// Hooks with a higher priority fire first; the default priority is 0
+pub fn register_before_prompt_hook(hook: BeforePromptHook, priority: i32) -> u64 {
     let id = BEFORE_PROMPT_COUNTER.fetch_add(1, Ordering::SeqCst);
-    get_hooks().write().unwrap().before_prompt.push((id, hook));
+    let mut hooks = get_hooks().write().unwrap();
+    insert_by_priority(&mut hooks.before_prompt, id, priority, hook);
     id
 }
 
-pub fn register_before_continuation_hook(hook: BeforeContinuationHook) -> u64 {
+pub fn register_before_continuation_hook(hook: BeforeContinuationHook, priority: i32) -> u64 {
     let id = BEFORE_CONTINUATION_COUNTER.fetch_add(1, Ordering::SeqCst);
-    get_hooks()
-        .write()
-        .unwrap()
-        .before_continuation
-        .push((id, hook));
+    let mut hooks = get_hooks().write().unwrap();
+    insert_by_priority(&mut hooks.before_continuation, id, priority, hook);
     id
 }
 
-pub fn register_before_execute_hook(hook: BeforeExecuteHook) -> u64 {
+pub fn register_before_execute_hook(hook: BeforeExecuteHook, priority: i32) -> u64 {
     let id = BEFORE_EXECUTE_COUNTER.fetch_add(1, Ordering::SeqCst);
-    get_hooks().write().unwrap().before_execute.push((id, hook));
+    let mut hooks = get_hooks().write().unwrap();
+    insert_by_priority(&mut hooks.before_execute, id, priority, hook);
     id
 }
 
-pub fn register_after_execute_hook(hook: AfterExecuteHook) -> u64 {
+pub fn register_after_execute_hook(hook: AfterExecuteHook, priority: i32) -> u64 {
     let id = AFTER_EXECUTE_COUNTER.fetch_add(1, Ordering::SeqCst);
-    get_hooks().write().unwrap().after_execute.push((id, hook));
+    let mut hooks = get_hooks().write().unwrap();
+    insert_by_priority(&mut hooks.after_execute, id, priority, hook);
     id
 }
 
@@ -136,7 +285,7 @@ pub fn unregister_before_prompt_hook(id: u64) -> bool {
     if let Some(pos) = hooks
         .before_prompt
         .iter()
-        .position(|(hook_id, _)| *hook_id == id)
+        .position(|(hook_id, _, _)| *hook_id == id)
     {
         let _ = hooks.before_prompt.remove(pos);
         true
@@ -150,7 +299,7 @@ pub fn unregister_before_continuation_hook(id: u64) -> bool {
     if let Some(pos) = hooks
         .before_continuation
         .iter()
-        .position(|(hook_id, _)| *hook_id == id)
+        .position(|(hook_id, _, _)| *hook_id == id)
     {
         let _ = hooks.before_continuation.remove(pos);
         true
@@ -164,7 +313,7 @@ pub fn unregister_before_execute_hook(id: u64) -> bool {
     if let Some(pos) = hooks
         .before_execute
         .iter()
-        .position(|(hook_id, _)| *hook_id == id)
+        .position(|(hook_id, _, _)| *hook_id == id)
     {
         let _ = hooks.before_execute.remove(pos);
         true
@@ -178,7 +327,7 @@ pub fn unregister_after_execute_hook(id: u64) -> bool {
     if let Some(pos) = hooks
         .after_execute
         .iter()
-        .position(|(hook_id, _)| *hook_id == id)
+        .position(|(hook_id, _, _)| *hook_id == id)
     {
         let _ = hooks.after_execute.remove(pos);
         true
@@ -187,14 +336,14 @@ pub fn unregister_after_execute_hook(id: u64) -> bool {
     }
 }
 
-/// List hook IDs in registration order
+/// List hook IDs in firing order
 pub fn list_before_prompt_hook_ids() -> Vec<u64> {
     get_hooks()
         .read()
         .unwrap()
         .before_prompt
         .iter()
-        .map(|(id, _)| *id)
+        .map(|(id, _, _)| *id)
         .collect()
 }
 
@@ -204,7 +353,7 @@ pub fn list_before_continuation_hook_ids() -> Vec<u64> {
         .unwrap()
         .before_continuation
         .iter()
-        .map(|(id, _)| *id)
+        .map(|(id, _, _)| *id)
         .collect()
 }
 
@@ -214,7 +363,7 @@ pub fn list_before_execute_hook_ids() -> Vec<u64> {
         .unwrap()
         .before_execute
         .iter()
-        .map(|(id, _)| *id)
+        .map(|(id, _, _)| *id)
         .collect()
 }
 
@@ -224,37 +373,51 @@ pub fn list_after_execute_hook_ids() -> Vec<u64> {
         .unwrap()
         .after_execute
         .iter()
-        .map(|(id, _)| *id)
+        .map(|(id, _, _)| *id)
         .collect()
 }
 
+/// Set while hooks run - a hook can't start a nested REPL, which would fire the hooks again
+static FIRING_HOOKS: AtomicBool = AtomicBool::new(false);
+
+/// Run `fire` with FIRING_HOOKS set, restoring the previous value afterwards
+fn firing_hooks(fire: impl FnOnce(&Hooks)) {
+    let was_firing = FIRING_HOOKS.swap(true, Ordering::SeqCst);
+    fire(&get_hooks().read().unwrap());
+    FIRING_HOOKS.store(was_firing, Ordering::SeqCst);
+}
+
 /// Fire hooks
 fn fire_before_prompt_hooks() {
-    let hooks = get_hooks().read().unwrap();
-    for (_id, hook) in &hooks.before_prompt {
-        hook();
-    }
+    firing_hooks(|hooks| {
+        for (_id, _priority, hook) in &hooks.before_prompt {
+            hook();
+        }
+    });
 }
 
 fn fire_before_continuation_hooks(prev_prompt: &str, buffer: &str) {
-    let hooks = get_hooks().read().unwrap();
-    for (_id, hook) in &hooks.before_continuation {
-        hook(prev_prompt, buffer);
-    }
+    firing_hooks(|hooks| {
+        for (_id, _priority, hook) in &hooks.before_continuation {
+            hook(prev_prompt, buffer);
+        }
+    });
 }
 
 fn fire_before_execute_hooks(command: &str) {
-    let hooks = get_hooks().read().unwrap();
-    for (_id, hook) in &hooks.before_execute {
-        hook(command);
-    }
+    firing_hooks(|hooks| {
+        for (_id, _priority, hook) in &hooks.before_execute {
+            hook(command);
+        }
+    });
 }
 
 fn fire_after_execute_hooks(command: &str) {
-    let hooks = get_hooks().read().unwrap();
-    for (_id, hook) in &hooks.after_execute {
-        hook(command);
-    }
+    firing_hooks(|hooks| {
+        for (_id, _priority, hook) in &hooks.after_execute {
+            hook(command);
+        }
+    });
 }
 
 /// Custom prompt for ShipShell
@@ -270,20 +433,54 @@ impl ShipPrompt {
     }
 }
 
+/// Prepare a user-supplied prompt for reedline
+///
+/// reedline skips escape sequences itself when measuring the prompt, so the markers readline
+/// needs around them (\x01 and \x02, written `\[` and `\]` in bash prompts) are removed - the
+/// bash spelling would otherwise print and count as text. An escape sequence cut off at the end
+/// is dropped too, since the terminal would swallow the start of the input to complete it
+fn prompt_text(prompt: &str) -> String {
+    let mut text = prompt
+        .replace("\\[", "")
+        .replace("\\]", "")
+        .replace(['\x01', '\x02'], "");
+
+    if let Some(start) = text.rfind('\x1b') {
+        let mut sequence = text[start + 1..].chars();
+        let incomplete = match sequence.next() {
+            None => true,
+            // CSI sequences end with a byte in @..~, OSC strings with BEL (or ESC \)
+            Some('[') => !sequence.any(|c| ('@'..='~').contains(&c)),
+            Some(']') => !sequence.any(|c| c == '\x07'),
+            Some(_) => false,
+        };
+        if incomplete {
+            text.truncate(start);
+        }
+    }
+    text
+}
+
 impl Prompt for ShipPrompt {
     fn render_prompt_left(&self) -> Cow<'_, str> {
         let repl_state = get_repl_state().read().unwrap();
         // Use ANSI reset code to ensure white/default terminal color
         if self.is_continuation {
-            Cow::Owned(format!("\x1b[0m{}", repl_state.continuation_prompt))
+            Cow::Owned(format!(
+                "\x1b[0m{}",
+                prompt_text(&repl_state.continuation_prompt)
+            ))
         } else {
-            Cow::Owned(format!("\x1b[0m{}", repl_state.primary_prompt))
+            Cow::Owned(format!(
+                "\x1b[0m{}",
+                prompt_text(&repl_state.primary_prompt)
+            ))
         }
     }
 
     fn render_prompt_right(&self) -> Cow<'_, str> {
         let repl_state = get_repl_state().read().unwrap();
-        Cow::Owned(format!("\x1b[0m{}", repl_state.right_prompt))
+        Cow::Owned(format!("\x1b[0m{}", prompt_text(&repl_state.right_prompt)))
     }
 
     fn render_prompt_indicator(&self, _mode: PromptEditMode) -> Cow<'_, str> {
@@ -306,6 +503,133 @@ impl Prompt for ShipPrompt {
     }
 }
 
+/// Completion registry: command name -> subcommands/flags offered after it
+static COMPLETIONS: OnceLock<RwLock<HashMap<String, Vec<String>>>> = OnceLock::new();
+
+fn get_completions() -> &'static RwLock<HashMap<String, Vec<String>>> {
+    COMPLETIONS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Register the options offered when completing arguments of a command
+/// Replaces any options previously registered for the command
+pub fn register_completions(command: String, options: Vec<String>) {
+    get_completions().write().unwrap().insert(command, options);
+}
+
+/// Characters that separate words when completing (shell and Python call syntax)
+fn is_word_boundary(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | '[' | ']' | ',' | '\'' | '"')
+}
+
+/// Tab completer for the REPL, driven by the completion registry
+struct ShipCompleter;
+
+impl Completer for ShipCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        let before = &line[..pos];
+
+        // The word being completed starts after the last boundary character
+        let word_start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| is_word_boundary(*c))
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let word = &before[word_start..];
+
+        // The command is the first registered token before the word, which skips
+        // Python syntax such as assignments and prog()/cmd() wrappers
+        // Words that look like paths fall back to completing file names
+        let completions = get_completions().read().unwrap();
+        let values = match before[..word_start]
+            .split(is_word_boundary)
+            .find_map(|token| completions.get(token))
+        {
+            Some(options) => options
+                .iter()
+                .filter(|option| option.starts_with(word))
+                .cloned()
+                .collect(),
+            None if word.contains('/') => complete_path(word),
+            None => return Vec::new(),
+        };
+
+        values
+            .into_iter()
+            .map(|value| Suggestion {
+                value,
+                span: Span::new(word_start, pos),
+                append_whitespace: false,
+                ..Suggestion::default()
+            })
+            .collect()
+    }
+}
+
+/// Complete a partially typed path against the file system
+/// Directories get a trailing slash so completion can continue into them, and hidden
+/// entries are only offered once the name being typed starts with a dot
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Get the completions the Tab key would offer with the cursor at `cursor` (in characters)
+/// A cursor past the end of the line completes at the end
+pub fn complete(line: &str, cursor: usize) -> Vec<String> {
+    let pos = line
+        .char_indices()
+        .nth(cursor)
+        .map(|(i, _)| i)
+        .unwrap_or(line.len());
+
+    ShipCompleter
+        .complete(line, pos)
+        .into_iter()
+        .map(|suggestion| suggestion.value)
+        .collect()
+}
+
+/// Create the line editor with tab completion wired up
+fn create_line_editor() -> Reedline {
+    let completion_menu = ColumnarMenu::default().with_name("completion_menu");
+
+    // Tab opens the completion menu, or advances through it once open
+    let mut keybindings = default_emacs_keybindings();
+    keybindings.add_binding(
+        KeyModifiers::NONE,
+        KeyCode::Tab,
+        ReedlineEvent::UntilFound(vec![
+            ReedlineEvent::Menu("completion_menu".to_string()),
+            ReedlineEvent::MenuNext,
+        ]),
+    );
+
+    Reedline::create()
+        .with_completer(Box::new(ShipCompleter))
+        .with_menu(ReedlineMenu::EngineCompleter(Box::new(completion_menu)))
+        .with_edit_mode(Box::new(Emacs::new(keybindings)))
+}
+
 /// Check if a Python statement is complete
 /// This function is passed in to avoid Python dependency in REPL module
 type StatementChecker = Box<dyn Fn(&str) -> bool + Send + Sync>;
@@ -324,6 +648,56 @@ fn is_complete_statement(code: &str) -> bool {
     }
 }
 
+/// What to do with the input buffer after a line is entered
+#[derive(Debug, PartialEq, Eq)]
+enum BufferAction {
+    /// Run the given code (the buffer with any trailing blank lines normalized)
+    Execute(String),
+    /// Nothing to run - discard the buffer
+    Clear,
+    /// The statement is incomplete - keep reading lines
+    Continue,
+}
+
+/// Decide what to do with the buffer once the latest line has been appended
+///
+/// Python needs a blank line to terminate a compound statement, so a whitespace-only line
+/// counts as blank: indentation left on the last line shouldn't keep the block open
+fn buffer_action(buffer: &str) -> BufferAction {
+    if buffer.trim().is_empty() {
+        return BufferAction::Clear;
+    }
+
+    let ends_with_blank_line = buffer
+        .rsplit('\n')
+        .next()
+        .is_some_and(|line| line.trim().is_empty());
+
+    if ends_with_blank_line {
+        // Terminate the statement with a single newline in place of the blank line(s)
+        // If that's still incomplete (open brackets, triple-quoted strings, ...) keep the
+        // buffer untouched since the blank line may be part of the statement
+        let code = format!("{}\n", buffer.trim_end());
+        if is_complete_statement(&code) {
+            BufferAction::Execute(code)
+        } else {
+            BufferAction::Continue
+        }
+    } else if is_complete_statement(buffer) {
+        BufferAction::Execute(buffer.to_string())
+    } else {
+        BufferAction::Continue
+    }
+}
+
+/// Whether reading a line can be retried after it failed
+///
+/// A signal arriving mid-read interrupts it, but anything else (e.g. the terminal went away)
+/// can't be read past
+fn is_recoverable_read_error(err: &std::io::Error) -> bool {
+    err.kind() == std::io::ErrorKind::Interrupted
+}
+
 /// Executor function type - executes code and sets ? environment variable
 type CodeExecutor = Box<dyn Fn(&str) -> anyhow::Result<()> + Send + Sync>;
 static CODE_EXECUTOR: OnceLock<CodeExecutor> = OnceLock::new();
@@ -332,19 +706,63 @@ pub fn set_code_executor(executor: CodeExecutor) {
     CODE_EXECUTOR.set(executor).ok();
 }
 
+/// How many REPL loops are running - code run at the prompt can start a nested one
+static RUN_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a running REPL loop in RUN_DEPTH for as long as it's alive
+struct RunDepthGuard(usize);
+
+impl RunDepthGuard {
+    fn enter() -> Self {
+        RunDepthGuard(RUN_DEPTH.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+}
+
+impl Drop for RunDepthGuard {
+    fn drop(&mut self) {
+        RUN_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Check if the REPL loop is running (so a nested one can be started)
+pub fn is_running() -> bool {
+    RUN_DEPTH.load(Ordering::SeqCst) > 0
+}
+
 /// Main REPL loop - completely Python-agnostic
+///
+/// Calling this from code run at the prompt starts a nested REPL with its own line editor and
+/// buffer (sharing history, prompts and hooks), which returns on Ctrl+D. Hooks can't start one
 pub fn run() -> anyhow::Result<()> {
+    if FIRING_HOOKS.load(Ordering::SeqCst) {
+        anyhow::bail!("a REPL can't be started from a REPL hook");
+    }
+    let depth = RunDepthGuard::enter();
+
     // Create reedline editor (default: white text, no syntax highlighting)
-    let mut line_editor = Reedline::create();
+    let mut line_editor = create_line_editor();
     let mut buffer = String::new();
     let mut prompt = ShipPrompt::new();
 
-    println!("ShipShell Python REPL");
-    println!("Type 'exit()' or press Ctrl+D to quit");
-    println!();
+    if depth.0 == 1 {
+        println!("ShipShell Python REPL");
+        println!("Type 'exit()' or press Ctrl+D to quit");
+        println!();
+    } else {
+        println!("Nested REPL (depth {}) - press Ctrl+D to return", depth.0);
+    }
 
     let mut prev_prompt = get_primary_prompt();
 
+    // Leading whitespace of a statement kept out of history by ignorespace
+    let mut private_indent: Option<String> = None;
+
+    // The fc builtin edits the history kept here
+    crate::shell::builtins::set_fc_handler(Box::new(edit_last));
+
+    // Ctrl-Z suspends the running command and returns to the prompt
+    crate::shell::jobs::enable_job_control();
+
     loop {
         // Update prompt state
         prompt.is_continuation = !buffer.is_empty();
@@ -353,40 +771,71 @@ pub fn run() -> anyhow::Result<()> {
         if prompt.is_continuation {
             fire_before_continuation_hooks(&prev_prompt, &buffer);
         } else {
+            // Report (and reap) background jobs that finished while the last command ran
+            crate::shell::jobs::notify_finished_jobs();
+            crate::shell::terminal::refresh_terminal_size();
             fire_before_prompt_hooks();
             prev_prompt = get_primary_prompt();
         }
 
+        let ignore_space = get_history_control().1;
+        line_editor = line_editor.with_history_exclusion_prefix(ignore_space.then(|| " ".into()));
+
         let sig = line_editor.read_line(&prompt);
 
+        // The terminal may have been resized while waiting for input
+        crate::shell::terminal::refresh_terminal_size();
+
         match sig {
-            Ok(Signal::Success(line)) => {
+            Ok(Signal::Success(mut line)) => {
+                // The leading whitespace only marks the statement as private, so drop it (from
+                // every line) rather than have Python report an unexpected indent
+                if buffer.is_empty() {
+                    private_indent = None;
+                    let code = line.trim_start();
+                    if ignore_space && code.len() < line.len() {
+                        private_indent = Some(line[..line.len() - code.len()].to_string());
+                        line = code.to_string();
+                    }
+                } else if let Some(indent) = &private_indent
+                    && let Some(code) = line.strip_prefix(indent.as_str())
+                {
+                    line = code.to_string();
+                }
+
                 // Append line to buffer
                 if !buffer.is_empty() {
                     buffer.push('\n');
                 }
                 buffer.push_str(&line);
 
-                // Check if statement is complete
-                if is_complete_statement(&buffer) {
-                    // Skip empty statements
-                    if !buffer.trim().is_empty() {
+                match buffer_action(&buffer) {
+                    BufferAction::Execute(code) => {
                         // Fire before execute hook
-                        fire_before_execute_hooks(&buffer);
+                        fire_before_execute_hooks(&code);
 
                         // Execute code via registered executor
                         if let Some(executor) = CODE_EXECUTOR.get()
-                            && let Err(e) = executor(&buffer)
+                            && let Err(e) = executor(&code)
                         {
                             eprintln!("Error executing code: {}", e);
                         }
 
                         // Fire after execute hook
-                        fire_after_execute_hooks(&buffer);
-                    }
+                        fire_after_execute_hooks(&code);
 
-                    // Clear buffer for next statement
-                    buffer.clear();
+                        // Record once it has run, so fc during execution sees the statement before
+                        if !HISTORY_REPLACED.swap(false, Ordering::SeqCst)
+                            && private_indent.is_none()
+                        {
+                            record_history(&code);
+                        }
+
+                        // Clear buffer for next statement
+                        buffer.clear();
+                    }
+                    BufferAction::Clear => buffer.clear(),
+                    BufferAction::Continue => {}
                 }
             }
             Ok(Signal::CtrlC) => {
@@ -395,9 +844,18 @@ pub fn run() -> anyhow::Result<()> {
                 continue;
             }
             Ok(Signal::CtrlD) => {
-                println!("Exiting...");
+                if depth.0 == 1 {
+                    println!("Exiting...");
+                } else {
+                    println!("Leaving nested REPL");
+                }
                 break;
             }
+            // Start the statement over
+            Err(err) if is_recoverable_read_error(&err) => {
+                buffer.clear();
+                continue;
+            }
             Err(err) => {
                 println!("Error: {:?}", err);
                 break;
@@ -407,3 +865,102 @@ pub fn run() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Columns a prompt takes up on screen: everything but complete CSI and OSC sequences
+    fn display_width(text: &str) -> usize {
+        let mut width = 0;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('\x1b', Some('[')) => {
+                    chars.next();
+                    chars.find(|c| ('@'..='~').contains(c));
+                }
+                ('\x1b', Some(']')) => {
+                    chars.find(|c| *c == '\x07');
+                }
+                _ => width += 1,
+            }
+        }
+        width
+    }
+
+    #[test]
+    fn prompt_text_keeps_color_codes_out_of_the_width() {
+        let colored = "\x1b[1;32mship\x1b[0m> ";
+        assert_eq!(prompt_text(colored), colored);
+        assert_eq!(display_width(&prompt_text(colored)), 6);
+
+        // readline's invisible-text markers, raw or in bash's spelling, are removed
+        for marked in [
+            "\\[\x1b[1;32m\\]ship\\[\x1b[0m\\]> ",
+            "\x01\x1b[1;32m\x02ship\x01\x1b[0m\x02> ",
+        ] {
+            assert_eq!(prompt_text(marked), colored);
+            assert_eq!(display_width(&prompt_text(marked)), 6);
+        }
+    }
+
+    #[test]
+    fn prompt_text_drops_a_cut_off_escape_sequence() {
+        assert_eq!(prompt_text("ship> \x1b[1;3"), "ship> ");
+        assert_eq!(prompt_text("ship> \x1b]0;title"), "ship> ");
+        assert_eq!(prompt_text("ship> \x1b"), "ship> ");
+        // A complete title sequence is kept
+        assert_eq!(prompt_text("\x1b]0;title\x07$ "), "\x1b]0;title\x07$ ");
+        assert_eq!(display_width("\x1b]0;title\x07$ "), 2);
+    }
+
+    #[test]
+    fn ignore_dups_skips_repeated_statements() {
+        let history = || get_repl_state().read().unwrap().history.clone();
+        get_repl_state().write().unwrap().history.clear();
+
+        for code in ["a", "a", "b", "a"] {
+            record_history(code);
+        }
+        assert_eq!(history(), ["a", "a", "b", "a"]);
+
+        set_history_control(Some(true), None);
+        for code in ["a", "a", "b", "b", "a"] {
+            record_history(code);
+        }
+        set_history_control(Some(false), None);
+        assert_eq!(history(), ["a", "a", "b", "a", "b", "a"]);
+        assert_eq!(last_history_entry().as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn only_interrupted_reads_are_retried() {
+        use std::io::{Error, ErrorKind};
+
+        assert!(is_recoverable_read_error(&Error::from(
+            ErrorKind::Interrupted
+        )));
+        assert!(is_recoverable_read_error(&Error::from_raw_os_error(
+            nix::libc::EINTR
+        )));
+        assert!(!is_recoverable_read_error(&Error::from_raw_os_error(
+            nix::libc::EIO
+        )));
+        assert!(!is_recoverable_read_error(&Error::from(
+            ErrorKind::UnexpectedEof
+        )));
+    }
+
+    #[test]
+    fn run_depth_counts_nested_loops() {
+        assert!(!is_running());
+        {
+            let outer = RunDepthGuard::enter();
+            let nested = RunDepthGuard::enter();
+            assert_eq!((outer.0, nested.0), (1, 2));
+            assert!(is_running());
+        }
+        assert!(!is_running());
+    }
+}
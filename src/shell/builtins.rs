@@ -1,10 +1,18 @@
+use nix::unistd::isatty;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
-use super::env::{EnvValue, get_shell_env, get_var};
+use super::env::{EnvValue, get_shell_env, get_var, home_dir, set_var};
+use super::exec::{cached_programs, clear_resolution_cache, rehash_program, resume_in_foreground};
+use super::jobs::{
+    current_job_id, disown_all_jobs, disown_job, parse_job_spec, report_jobs, take_job,
+};
+use super::options::{OPTION_NAMES, get_option, set_option};
+use super::pager::page_output;
 
 /// Get a builtin function by name
 ///
@@ -20,6 +28,15 @@ pub fn get_builtin(name: &str) -> Option<fn(&[String]) -> i32> {
         "exit" => Some(exit_builtin),
         "quit" => Some(quit),
         "which" => Some(which),
+        "set" => Some(set_builtin),
+        "readonly" => Some(readonly),
+        "declare" | "typeset" => Some(declare),
+        "jobs" => Some(jobs),
+        "disown" => Some(disown),
+        "fg" => Some(fg),
+        "fc" => Some(fc),
+        "rehash" => Some(rehash),
+        "hash" => Some(hash),
         _ => None,
     }
 }
@@ -30,68 +47,47 @@ pub fn get_builtin(name: &str) -> Option<fn(&[String]) -> i32> {
 ///   - [] -> change to HOME
 ///   - ["-"] -> change to OLDPWD
 ///   - [path] -> change to path
+///   - ["--", path] -> change to path taken literally (so "-" is a directory name)
 pub fn cd(args: &[String]) -> i32 {
+    // Everything after "--" is an operand, never the OLDPWD shortcut
+    let (literal, args) = match args.split_first() {
+        Some((first, rest)) if first == "--" => (true, rest),
+        _ => (false, args),
+    };
+
     // Determine target directory
     let target = if args.is_empty() {
         // No argument - go to HOME
-        match get_var("HOME") {
-            Some(EnvValue::String(s)) => PathBuf::from(s),
-            Some(EnvValue::FilePath(p)) => p,
-            _ => {
+        match home_dir() {
+            Some(home) => home,
+            None => {
                 eprintln!("cd: HOME not set");
                 return 1;
             }
         }
-    } else if args[0] == "-" {
-        // cd - (change to previous directory)
-        match get_var("OLDPWD") {
-            Some(EnvValue::String(s)) => {
-                println!("{}", s);
-                PathBuf::from(s)
-            }
-            Some(EnvValue::FilePath(p)) => {
-                println!("{}", p.display());
-                p.clone()
-            }
+    } else if !literal && args[0] == "-" {
+        // cd - (change to previous directory), named on a terminal so the user sees where
+        // they went - piped or captured output stays quiet
+        let oldpwd = match get_var("OLDPWD") {
+            Some(EnvValue::String(s)) => PathBuf::from(s),
+            Some(EnvValue::FilePath(p)) => p.clone(),
             _ => {
                 eprintln!("cd: OLDPWD not set");
                 return 1;
             }
+        };
+        if stdout_is_tty() {
+            println!("{}", oldpwd.display());
         }
+        oldpwd
     } else {
-        // Specific path provided
-        let path_str = &args[0];
-
-        // Expand tilde if present
-        if path_str.starts_with('~') {
-            match get_var("HOME") {
-                Some(EnvValue::String(s)) => {
-                    if path_str == "~" {
-                        PathBuf::from(&s)
-                    } else if let Some(stripped) = path_str.strip_prefix("~/") {
-                        PathBuf::from(&s).join(stripped)
-                    } else {
-                        // ~user syntax - just treat as literal for now
-                        PathBuf::from(path_str)
-                    }
-                }
-                Some(EnvValue::FilePath(p)) => {
-                    if path_str == "~" {
-                        p
-                    } else if let Some(stripped) = path_str.strip_prefix("~/") {
-                        p.join(stripped)
-                    } else {
-                        // TODO: Handle ~user syntax
-                        PathBuf::from(path_str)
-                    }
-                }
-                _ => {
-                    eprintln!("cd: HOME not set");
-                    return 1;
-                }
+        // Specific path provided, with tilde expansion
+        match expand_tilde(&args[0]) {
+            Some(path) => path,
+            None => {
+                eprintln!("cd: {}: HOME not set, cannot expand ~", args[0]);
+                return 1;
             }
-        } else {
-            PathBuf::from(path_str)
         }
     };
 
@@ -120,12 +116,29 @@ pub fn cd(args: &[String]) -> i32 {
     };
 
     // Update environment variables
-    let env = get_shell_env();
-    let mut env_write = env.write().unwrap();
-    env_write.set("OLDPWD".to_string(), EnvValue::FilePath(current_dir));
-    env_write.set("PWD".to_string(), EnvValue::FilePath(new_dir));
+    let mut exit_code = 0;
+    for (key, value) in [("OLDPWD", current_dir), ("PWD", new_dir)] {
+        if let Err(e) = set_var(key.to_string(), EnvValue::FilePath(value)) {
+            eprintln!("cd: {}", e.message());
+            exit_code = 1;
+        }
+    }
 
-    0
+    exit_code
+}
+
+/// Expand a leading `~` or `~/` using HOME
+///
+/// Other paths (including `~user`, which isn't supported yet) are returned unchanged.
+/// Returns None if the path needs HOME but it is unset.
+fn expand_tilde(path: &str) -> Option<PathBuf> {
+    if path == "~" {
+        home_dir()
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        home_dir().map(|home| home.join(rest))
+    } else {
+        Some(PathBuf::from(path))
+    }
 }
 
 /// Print the current working directory
@@ -197,11 +210,12 @@ pub fn pushd(args: &[String]) -> i32 {
     // Change to the new directory
     let exit_code = cd(args);
 
-    if exit_code == 0 {
-        // Print the new directory
-        if let Ok(new_dir) = env::current_dir() {
-            println!("{}", new_dir.display());
-        }
+    // Print the new directory on a terminal only, so piped or captured output stays quiet
+    if exit_code == 0
+        && stdout_is_tty()
+        && let Ok(new_dir) = env::current_dir()
+    {
+        println!("{}", display_path(&new_dir));
     }
 
     exit_code
@@ -232,11 +246,12 @@ pub fn popd(args: &[String]) -> i32 {
     let target_str = target.to_string_lossy().to_string();
     let exit_code = cd(&[target_str]);
 
-    if exit_code == 0 {
-        // Print the new directory
-        if let Ok(new_dir) = env::current_dir() {
-            println!("{}", new_dir.display());
-        }
+    // Print the new directory on a terminal only, so piped or captured output stays quiet
+    if exit_code == 0
+        && stdout_is_tty()
+        && let Ok(new_dir) = env::current_dir()
+    {
+        println!("{}", display_path(&new_dir));
     }
 
     exit_code
@@ -260,39 +275,84 @@ pub fn dirs(args: &[String]) -> i32 {
         }
     };
 
-    // Print current directory first
-    println!("{}", current_dir.display());
+    // Current directory first
+    let mut output = format!("{}\n", display_path(&current_dir));
 
-    // Print directory stack
+    // Then the directory stack
     let env = get_shell_env();
     let env_read = env.read().unwrap();
-    for dir in env_read.dir_stack() {
-        println!("{}", dir.display());
+    let stack = env_read.dir_stack().to_vec();
+    drop(env_read); // Release the lock before display_path reads HOME
+    for dir in stack {
+        output.push_str(&format!("{}\n", display_path(&dir)));
     }
 
+    page_output(&output);
     0
 }
 
+/// Check if stdout is a terminal
+/// Builtins use this to drop interactive-only formatting when their output is piped or captured
+pub fn stdout_is_tty() -> bool {
+    isatty(std::io::stdout()).unwrap_or(false)
+}
+
+/// Render a path for display, abbreviating a leading HOME prefix to `~`
+/// The abbreviation is only applied on a terminal - piped output keeps the full path
+fn display_path(path: &Path) -> String {
+    if !stdout_is_tty() {
+        return path.display().to_string();
+    }
+
+    let Some(home) = home_dir() else {
+        return path.display().to_string();
+    };
+
+    // A HOME of "/" would turn every path into ~/...
+    if home == Path::new("/") {
+        return path.display().to_string();
+    }
+
+    match path.strip_prefix(&home) {
+        Ok(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
 /// Exit the shell
 ///
 /// Args:
 ///   - [] -> exit with code 0
-///   - [code] -> exit with specified code
+///   - [code] -> exit with specified code, truncated to 0-255 like other shells (256 -> 0,
+///     -1 -> 255); a non-numeric code exits with 2
 pub fn exit_builtin(args: &[String]) -> i32 {
-    let exit_code = if args.is_empty() {
-        0
-    } else {
-        args[0].parse::<i32>().unwrap_or(1)
+    let exit_code = match args.first().map(|code| exit_status(code)) {
+        None => 0,
+        Some(Ok(code)) => code,
+        Some(Err(message)) => {
+            eprintln!("{}", message);
+            2
+        }
     };
 
+    super::shutdown();
     std::process::exit(exit_code);
 }
 
+/// Parse an exit builtin's code, truncated to 0-255 (see exit_builtin)
+fn exit_status(code: &str) -> Result<i32, String> {
+    match code.trim().parse::<i64>() {
+        Ok(code) => Ok((code & 0xFF) as i32),
+        Err(_) => Err(format!("exit: {}: numeric argument required", code)),
+    }
+}
+
 /// Quit the shell (alias for exit)
 ///
 /// Args:
 ///   - [] -> exit with code 0
-///   - [code] -> exit with specified code
+///   - [code] -> exit with specified code (see exit_builtin)
 pub fn quit(args: &[String]) -> i32 {
     exit_builtin(args)
 }
@@ -302,7 +362,8 @@ pub fn quit(args: &[String]) -> i32 {
 /// Args:
 ///   - [-a] -> list all instances found (instead of just the first)
 ///   - [-s] -> silent mode, no output, just return exit code
-///   - [program ...] -> one or more program names to locate
+///   - [program ...] -> one or more program names to locate, or glob patterns (`*`, `?`,
+///     `[...]`) listing every matching executable in PATH
 ///
 /// Returns:
 ///   - 0 if all programs found
@@ -337,7 +398,12 @@ pub fn which(args: &[String]) -> i32 {
         let is_builtin = get_builtin(program).is_some() || program == "source";
         if is_builtin {
             if !silent {
-                println!("{}: shell built-in command", program);
+                // Piped output stays one name or path per line
+                if stdout_is_tty() {
+                    println!("{}: shell built-in command", program);
+                } else {
+                    println!("{}", program);
+                }
             }
             found_anything = true;
 
@@ -348,7 +414,12 @@ pub fn which(args: &[String]) -> i32 {
         }
 
         // Search in PATH (either not a built-in, or show_all is requested)
-        let paths = find_in_path(program, show_all);
+        // A pattern lists every matching executable, like an ls of each PATH directory
+        let paths = if is_glob_pattern(program) {
+            find_matching_in_path(program)
+        } else {
+            find_in_path(program, show_all)
+        };
 
         if !paths.is_empty() {
             found_anything = true;
@@ -367,21 +438,467 @@ pub fn which(args: &[String]) -> i32 {
     if all_found { 0 } else { 1 }
 }
 
-/// Find a program in PATH
+/// Set or display shell options
 ///
-/// Searches the PATH environment variable for executable files matching the program name.
+/// Args:
+///   - [] or ["-o"] -> list all options and their states
+///   - ["+o"] -> list all options as `set` commands that recreate them
+///   - ["-o", name ...] -> enable the named option(s)
+///   - ["+o", name ...] -> disable the named option(s)
+pub fn set_builtin(args: &[String]) -> i32 {
+    if args.is_empty() || (args.len() == 1 && args[0] == "-o") {
+        for name in OPTION_NAMES {
+            let state = if get_option(name) == Some(true) {
+                "on"
+            } else {
+                "off"
+            };
+            println!("{:<15}\t{}", name, state);
+        }
+        return 0;
+    }
+
+    if args.len() == 1 && args[0] == "+o" {
+        for name in OPTION_NAMES {
+            let flag = if get_option(name) == Some(true) {
+                "-o"
+            } else {
+                "+o"
+            };
+            println!("set {} {}", flag, name);
+        }
+        return 0;
+    }
+
+    let mut exit_code = 0;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let enable = match arg.as_str() {
+            "-o" => true,
+            "+o" => false,
+            _ => {
+                eprintln!("set: {}: invalid option", arg);
+                return 2;
+            }
+        };
+
+        let Some(name) = iter.next() else {
+            eprintln!("set: {}: option name required", arg);
+            return 2;
+        };
+
+        if !set_option(name, enable) {
+            eprintln!("set: {}: invalid option name", name);
+            exit_code = 1;
+        }
+    }
+
+    exit_code
+}
+
+/// Mark variables as readonly, optionally assigning them first
 ///
 /// Args:
-///   - program: The program name to search for
-///   - find_all: If true, returns all instances found; if false, returns only the first
+///   - [] or ["-p"] -> list all readonly variables
+///   - [name ...] -> mark the named variables readonly
+///   - [name=value ...] -> assign the value, then mark readonly
+pub fn readonly(args: &[String]) -> i32 {
+    let env = get_shell_env();
+
+    if args.is_empty() || (args.len() == 1 && args[0] == "-p") {
+        let env_read = env.read().unwrap();
+        let mut keys: Vec<String> = env_read.readonly_keys().cloned().collect();
+        keys.sort();
+        for key in keys {
+            match env_read.get(&key) {
+                Some(value) => println!("readonly {}={}", key, value.to_string_repr()),
+                None => println!("readonly {}", key),
+            }
+        }
+        return 0;
+    }
+
+    let mut exit_code = 0;
+    for arg in args {
+        let (key, value) = match arg.split_once('=') {
+            Some((key, value)) => (key, Some(EnvValue::parse_from_string(value))),
+            None => (arg.as_str(), None),
+        };
+
+        if key.is_empty() {
+            eprintln!("readonly: `{}': not a valid identifier", arg);
+            exit_code = 1;
+            continue;
+        }
+
+        if let Some(value) = value
+            && let Err(e) = set_var(key.to_string(), value)
+        {
+            eprintln!("readonly: {}", e.message());
+            exit_code = 1;
+            continue;
+        }
+
+        env.write().unwrap().mark_readonly(key.to_string());
+    }
+
+    exit_code
+}
+
+/// Type tag shown for a variable by `declare`
+fn type_tag(value: &EnvValue) -> &'static str {
+    match value {
+        EnvValue::String(_) => "str",
+        EnvValue::Integer(_) => "int",
+        EnvValue::Decimal(_) => "float",
+        EnvValue::Bool(_) => "bool",
+        EnvValue::None => "none",
+        EnvValue::List(_) => "list",
+        EnvValue::FilePath(_) => "path",
+    }
+}
+
+/// Set variables with an explicit type, or list variables with their types
+///
+/// Args:
+///   - [] or ["-p"] -> list all variables as `[type] NAME=value`
+///   - ["-p", name ...] -> list the named variables
+///   - [name=value ...] -> assign, detecting the type like any other assignment
+///   - ["-i", name=value ...] -> store an Integer
+///   - ["-a", name=value ...] -> store a List, split on ':'
+///   - ["-l", name=value ...] -> store a lowercased String
+///
+/// With a type flag, a name without a value converts the variable's current value
+pub fn declare(args: &[String]) -> i32 {
+    let mut type_flag = None;
+    let mut print = false;
+    let mut names = args;
+    while let Some((arg, rest)) = names.split_first() {
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            break;
+        };
+        for flag in flags.chars() {
+            match flag {
+                'p' => print = true,
+                'i' | 'a' | 'l' if type_flag.is_none_or(|current| current == flag) => {
+                    type_flag = Some(flag)
+                }
+                _ => {
+                    eprintln!("declare: usage: declare [-p] [-i|-a|-l] [name[=value] ...]");
+                    return 2;
+                }
+            }
+        }
+        names = rest;
+    }
+
+    if names.is_empty() || print {
+        let vars = super::env::all_vars();
+        let mut keys: Vec<&String> = if names.is_empty() {
+            vars.keys().collect()
+        } else {
+            names.iter().collect()
+        };
+        keys.sort();
+
+        let mut exit_code = 0;
+        let mut output = String::new();
+        for key in keys {
+            match vars.get(key) {
+                Some(value) => output.push_str(&format!(
+                    "[{}] {}={}\n",
+                    type_tag(value),
+                    key,
+                    value.to_string_repr()
+                )),
+                None => {
+                    eprintln!("declare: {}: not found", key);
+                    exit_code = 1;
+                }
+            }
+        }
+        page_output(&output);
+        return exit_code;
+    }
+
+    let mut exit_code = 0;
+    for arg in names {
+        let (key, text) = match arg.split_once('=') {
+            Some((key, value)) => (key, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+
+        if key.is_empty() {
+            eprintln!("declare: `{}': not a valid identifier", arg);
+            exit_code = 1;
+            continue;
+        }
+
+        // Without a value only a type change makes sense: convert the current value
+        let text = match (text, type_flag) {
+            (Some(text), _) => text,
+            (None, Some(_)) => get_var(key).map(|v| v.to_string_repr()).unwrap_or_default(),
+            (None, None) => continue,
+        };
+
+        let value = match type_flag {
+            Some('i') if text.is_empty() => EnvValue::Integer(0),
+            Some('i') => match text.trim().parse::<i64>() {
+                Ok(i) => EnvValue::Integer(i),
+                Err(_) => {
+                    eprintln!("declare: {}: not an integer", text);
+                    exit_code = 1;
+                    continue;
+                }
+            },
+            Some('a') if text.is_empty() => EnvValue::List(Vec::new()),
+            Some('a') => EnvValue::List(text.split(':').map(EnvValue::parse_from_string).collect()),
+            Some('l') => EnvValue::String(text.to_lowercase()),
+            _ => EnvValue::parse_from_string(&text),
+        };
+
+        if let Err(e) = set_var(key.to_string(), value) {
+            eprintln!("declare: {}", e.message());
+            exit_code = 1;
+        }
+    }
+
+    exit_code
+}
+
+/// List background jobs
+///
+/// Args:
+///   - [] -> list jobs as `[id] state command`
+///   - ["-l"] -> also include each job's PID
+///
+/// Finished jobs are listed once and then removed from the job table
+pub fn jobs(args: &[String]) -> i32 {
+    let show_pids = match args {
+        [] => false,
+        [flag] if flag == "-l" => true,
+        _ => {
+            eprintln!("jobs: usage: jobs [-l]");
+            return 2;
+        }
+    };
+
+    let mut output = String::new();
+    for job in report_jobs() {
+        if show_pids {
+            output.push_str(&format!(
+                "[{}] {} {}\t{}\n",
+                job.id,
+                job.pid,
+                job.state.describe(),
+                job.command
+            ));
+        } else {
+            output.push_str(&format!(
+                "[{}] {}\t{}\n",
+                job.id,
+                job.state.describe(),
+                job.command
+            ));
+        }
+    }
+
+    page_output(&output);
+    0
+}
+
+/// Remove background jobs from the job table without killing them
+/// A disowned job keeps running but is no longer listed by jobs, nor hung up when the shell exits
+///
+/// Args:
+///   - ["-a"] -> disown every job
+///   - [spec, ...] -> disown each job given as `%N` (or `N`)
+pub fn disown(args: &[String]) -> i32 {
+    match args {
+        [] => {
+            eprintln!("disown: usage: disown [-a] [%job ...]");
+            2
+        }
+        [flag] if flag == "-a" => {
+            disown_all_jobs();
+            0
+        }
+        specs => {
+            let mut exit_code = 0;
+            for spec in specs {
+                let disowned = parse_job_spec(spec).and_then(disown_job);
+                if disowned.is_none() {
+                    eprintln!("disown: {}: no such job", spec);
+                    exit_code = 1;
+                }
+            }
+            exit_code
+        }
+    }
+}
+
+/// Continue a stopped or background job in the foreground and wait for it
+/// The job's command is printed first; Ctrl-Z stops it again
+///
+/// Args:
+///   - [] -> the job with the highest id
+///   - [spec] -> the job given as `%N` (or `N`)
+pub fn fg(args: &[String]) -> i32 {
+    let id = match args {
+        [] => match current_job_id() {
+            Some(id) => id,
+            None => {
+                eprintln!("fg: no current job");
+                return 1;
+            }
+        },
+        [spec] => match parse_job_spec(spec) {
+            Some(id) => id,
+            None => {
+                eprintln!("fg: {}: no such job", spec);
+                return 1;
+            }
+        },
+        _ => {
+            eprintln!("fg: usage: fg [%job]");
+            return 2;
+        }
+    };
+
+    let Some(job) = take_job(id) else {
+        eprintln!("fg: %{}: no such job", id);
+        return 1;
+    };
+    if job.state.is_done() {
+        eprintln!("fg: %{}: job has terminated", id);
+        return 1;
+    }
+
+    println!("{}", job.command);
+    resume_in_foreground(&job) as i32
+}
+
+/// Forget where commands were found, so the next run of each searches PATH again
+/// Needed when a program is added earlier in PATH than the one already found
+///
+/// Args: none
+pub fn rehash(args: &[String]) -> i32 {
+    if !args.is_empty() {
+        eprintln!("rehash: too many arguments");
+        return 1;
+    }
+
+    clear_resolution_cache();
+    0
+}
+
+/// Remember or list where programs were found in PATH
+///
+/// Args:
+///   - [] -> list cached programs as `name=path`
+///   - [-r] -> forget every cached program, like rehash
+///   - [program ...] -> search PATH for each program now and cache where it was found
 ///
 /// Returns:
-///   - Vec of PathBuf containing all matching executable paths (empty if not found)
-fn find_in_path(program: &str, find_all: bool) -> Vec<PathBuf> {
-    let mut results = Vec::new();
+///   - 0 if every program was found
+///   - 1 if any program wasn't found, or hashall is off
+///   - 2 for an unknown option
+pub fn hash(args: &[String]) -> i32 {
+    let mut programs = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-r" => clear_resolution_cache(),
+            opt if opt.starts_with('-') => {
+                eprintln!("hash: {}: invalid option", opt);
+                eprintln!("hash: usage: hash [-r] [name ...]");
+                return 2;
+            }
+            _ => programs.push(arg.as_str()),
+        }
+    }
+
+    if args.is_empty() {
+        for (name, path) in cached_programs() {
+            println!("{}={}", name, path.display());
+        }
+        return 0;
+    }
 
-    // Extract PATH directories, supporting both List and String variants
-    let path_dirs: Vec<String> = match get_var("PATH") {
+    if programs.is_empty() {
+        return 0;
+    }
+    if get_option("hashall") != Some(true) {
+        eprintln!("hash: hashing disabled");
+        return 1;
+    }
+
+    let mut status = 0;
+    for program in programs {
+        // Names with a slash are never searched for, so there's nothing to remember
+        if program.contains('/') {
+            continue;
+        }
+        if rehash_program(program).is_err() {
+            eprintln!("hash: {}: not found", program);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// Handler that edits and re-runs the previous statement
+/// Registered by the REPL, which owns the history
+type FcHandler = Box<dyn Fn() -> i32 + Send + Sync>;
+static FC_HANDLER: OnceLock<FcHandler> = OnceLock::new();
+
+pub fn set_fc_handler(handler: FcHandler) {
+    FC_HANDLER.set(handler).ok();
+}
+
+/// Edit the previous statement in an editor and run the result
+///
+/// Args:
+///   - [] -> edit the last statement with $FCEDIT, $EDITOR, vi or nano (first one set/found)
+pub fn fc(args: &[String]) -> i32 {
+    if !args.is_empty() {
+        eprintln!("fc: usage: fc");
+        return 2;
+    }
+
+    match FC_HANDLER.get() {
+        Some(handler) => handler(),
+        None => {
+            eprintln!("fc: history is only available in the interactive shell");
+            1
+        }
+    }
+}
+
+/// Find the editor to use for fc: $FCEDIT, then $EDITOR, then vi or nano from PATH
+/// Returns the editor command split into the program and its arguments
+pub fn find_editor() -> Option<Vec<String>> {
+    for key in ["FCEDIT", "EDITOR"] {
+        let command: Vec<String> = get_var(key)
+            .map(|value| value.to_string_repr())
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+        if !command.is_empty() {
+            return Some(command);
+        }
+    }
+
+    ["vi", "nano"]
+        .into_iter()
+        .find(|editor| !find_in_path(editor, false).is_empty())
+        .map(|editor| vec![editor.to_string()])
+}
+
+/// The directories to search for programs, from PATH
+/// Supports both List and String variants, falling back to a default PATH
+fn search_path_dirs() -> Vec<String> {
+    match get_var("PATH") {
         Some(EnvValue::List(items)) => {
             // PATH is a list - convert items to strings
             let mut dirs = Vec::new();
@@ -410,7 +927,22 @@ fn find_in_path(program: &str, find_all: bool) -> Vec<PathBuf> {
                 "/bin".to_string(),
             ]
         }
-    };
+    }
+}
+
+/// Find a program in PATH
+///
+/// Searches the PATH environment variable for executable files matching the program name.
+///
+/// Args:
+///   - program: The program name to search for
+///   - find_all: If true, returns all instances found; if false, returns only the first
+///
+/// Returns:
+///   - Vec of PathBuf containing all matching executable paths (empty if not found)
+fn find_in_path(program: &str, find_all: bool) -> Vec<PathBuf> {
+    let mut results = Vec::new();
+    let path_dirs = search_path_dirs();
 
     // Search each directory in PATH
     for dir in &path_dirs {
@@ -420,14 +952,14 @@ fn find_in_path(program: &str, find_all: bool) -> Vec<PathBuf> {
 
         let candidate = PathBuf::from(dir).join(program);
 
-        // Check if file exists and is executable
+        // Check if it's an executable regular file (directories are skipped)
         if candidate.exists() {
             #[cfg(unix)]
             {
                 if let Ok(metadata) = std::fs::metadata(&candidate) {
                     let permissions = metadata.permissions();
                     // Check if any execute bit is set
-                    if permissions.mode() & 0o111 != 0 {
+                    if metadata.is_file() && permissions.mode() & 0o111 != 0 {
                         results.push(candidate);
                         if !find_all {
                             break;
@@ -448,3 +980,200 @@ fn find_in_path(program: &str, find_all: bool) -> Vec<PathBuf> {
 
     results
 }
+
+/// Find every executable in PATH whose name matches a glob pattern
+/// Matches are listed directory by directory, in PATH order, sorted by name within each
+fn find_matching_in_path(pattern: &str) -> Vec<PathBuf> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut results = Vec::new();
+
+    for dir in search_path_dirs() {
+        if dir.is_empty() {
+            continue;
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        let mut matches: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| {
+                let name: Vec<char> = entry.file_name().to_string_lossy().chars().collect();
+                glob_match(&pattern, &name)
+            })
+            .map(|entry| entry.path())
+            .filter(|path| {
+                std::fs::metadata(path)
+                    .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            })
+            .collect();
+        matches.sort();
+        results.extend(matches);
+    }
+
+    results
+}
+
+/// Check if a program name is a glob pattern rather than a literal name
+fn is_glob_pattern(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+/// Match a file name against a glob pattern: `*` matches any run of characters, `?` any one
+/// character, and `[...]` one character from a set (with `a-z` ranges, negated by `!` or `^`)
+///
+/// Only the most recent `*` is ever backtracked to, so this takes O(pattern × name) steps
+/// however many stars the pattern has
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*`: the pattern after it and the name position it's
+    // currently standing in for up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            star = Some((p + 1, n));
+            p += 1;
+        } else if let Some(next) = match_one(pattern, p, name[n]) {
+            p = next;
+            n += 1;
+        } else if let Some((after_star, matched_to)) = star {
+            // Let the `*` swallow one more character and try the rest again
+            star = Some((after_star, matched_to + 1));
+            p = after_star;
+            n = matched_to + 1;
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match the single-character pattern element at `pattern[p]` (anything but `*`) against `c`
+/// Returns the index of the next pattern element if it matched
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match pattern.get(p)? {
+        '*' => None,
+        '?' => Some(p + 1),
+        '[' => match bracket_match(&pattern[p + 1..], c) {
+            Some((matched, rest)) => matched.then_some(pattern.len() - rest.len()),
+            // An unclosed bracket is just a literal '['
+            None => (c == '[').then_some(p + 1),
+        },
+        &literal => (literal == c).then_some(p + 1),
+    }
+}
+
+/// Match one character against a bracket expression (the pattern just after its `[`)
+/// Returns whether it matched and the pattern after the closing `]`, or None if it's unclosed
+fn bracket_match(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut i) = match pattern.first() {
+        Some('!' | '^') => (true, 1),
+        _ => (false, 0),
+    };
+
+    let mut matched = false;
+    let mut first = true;
+    // A ']' straight after the '[' (or negation) is part of the set
+    while i < pattern.len() && (pattern[i] != ']' || first) {
+        first = false;
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            matched |= (pattern[i]..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= c == pattern[i];
+            i += 1;
+        }
+    }
+
+    if i >= pattern.len() {
+        return None;
+    }
+    Some((matched != negated, &pattern[i + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, name: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        glob_match(&pattern, &name)
+    }
+
+    #[test]
+    fn glob_match_wildcards() {
+        assert!(glob("py*", "python3"));
+        assert!(glob("py*", "py"));
+        assert!(!glob("py*", "ipython"));
+        assert!(glob("*sh", "bash"));
+        assert!(glob("*", ""));
+        assert!(glob("ls?", "lsd"));
+        assert!(!glob("ls?", "ls"));
+        assert!(glob("g*z*p", "gunzip"));
+        assert!(glob("exact", "exact"));
+        assert!(!glob("exact", "exactly"));
+    }
+
+    #[test]
+    fn glob_match_brackets() {
+        assert!(glob("python[23]", "python3"));
+        assert!(!glob("python[23]", "python4"));
+        assert!(glob("x[a-c]", "xb"));
+        assert!(!glob("x[!a-c]", "xb"));
+        assert!(glob("x[^a-c]", "xd"));
+        // An unclosed bracket is a literal '['
+        assert!(glob("a[b", "a[b"));
+        assert!(!glob("a[b", "ab"));
+    }
+
+    #[test]
+    fn glob_match_many_stars_without_backtracking_blowup() {
+        let name = "a".repeat(44);
+        let started = std::time::Instant::now();
+        assert!(!glob("*a*a*a*a*a*a*a*a*b", &name));
+        assert!(glob("*a*a*a*a*a*a*a*a*", &name));
+        assert!(glob("a*a*a*a*a*a*a*a*a", &name));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        // Stars still give up characters to later elements
+        assert!(glob("*a*[b-c]?", "xxaxxbz"));
+        assert!(!glob("*a*[b-c]?", "xxaxxb"));
+        assert!(glob("**x", "x"));
+        assert!(glob("a*", "a"));
+        assert!(!glob("*a", ""));
+    }
+
+    #[test]
+    fn bracket_match_sets() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        let rest: &[char] = &['x'];
+        assert_eq!(bracket_match(&chars("abc]x"), 'b'), Some((true, rest)));
+        assert_eq!(bracket_match(&chars("!abc]x"), 'b'), Some((false, rest)));
+        assert_eq!(bracket_match(&chars("0-9]x"), '5'), Some((true, rest)));
+        // A ']' first in the set is a member, and '-' before the closing ']' is literal
+        assert_eq!(bracket_match(&chars("]a]x"), ']'), Some((true, rest)));
+        assert_eq!(bracket_match(&chars("a-]x"), '-'), Some((true, rest)));
+        assert_eq!(bracket_match(&chars("abc"), 'a'), None);
+    }
+
+    #[test]
+    fn exit_status_truncates_to_a_byte() {
+        assert_eq!(exit_status("0"), Ok(0));
+        assert_eq!(exit_status("42"), Ok(42));
+        assert_eq!(exit_status("256"), Ok(0));
+        assert_eq!(exit_status("257"), Ok(1));
+        assert_eq!(exit_status("-1"), Ok(255));
+        assert_eq!(exit_status(" 3 "), Ok(3));
+    }
+
+    #[test]
+    fn exit_status_rejects_non_numeric_codes() {
+        assert_eq!(
+            exit_status("abc"),
+            Err("exit: abc: numeric argument required".to_string())
+        );
+        assert!(exit_status("1.5").is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::path::PathBuf;
 use std::sync::{OnceLock, RwLock};
@@ -24,7 +24,7 @@ impl EnvValue {
         match self {
             EnvValue::String(s) => s.clone(),
             EnvValue::Integer(i) => i.to_string(),
-            EnvValue::Decimal(d) => d.to_string(),
+            EnvValue::Decimal(d) => format_decimal(*d),
             EnvValue::Bool(b) => {
                 if *b {
                     "True".to_string()
@@ -42,9 +42,35 @@ impl EnvValue {
         }
     }
 
+    /// Whether two list entries mean the same thing once their types are set aside, so
+    /// "/usr/bin" and Path("/usr/bin") are one PATH entry (as is "/usr/bin/")
+    pub(crate) fn same_entry(&self, other: &EnvValue) -> bool {
+        fn normalize(entry: &str) -> &str {
+            match entry.trim_end_matches('/') {
+                "" if !entry.is_empty() => "/",
+                trimmed => trimmed,
+            }
+        }
+        normalize(&self.to_string_repr()) == normalize(&other.to_string_repr())
+    }
+
+    /// Get an item of a list value, counting from the end for a negative index like Python
+    /// Returns None for an index out of range or a value that isn't a list
+    pub(crate) fn item(&self, index: i64) -> Option<&EnvValue> {
+        let EnvValue::List(items) = self else {
+            return None;
+        };
+        let index = if index < 0 {
+            items.len().checked_sub(index.unsigned_abs() as usize)?
+        } else {
+            index as usize
+        };
+        items.get(index)
+    }
+
     /// Parse a string value into an EnvValue, attempting to detect the appropriate type
     /// Priority order ensures roundtrip consistency and proper handling of edge cases
-    fn parse_from_string(s: &str) -> EnvValue {
+    pub(crate) fn parse_from_string(s: &str) -> EnvValue {
         // 1. Empty string → None
         if s.is_empty() {
             return EnvValue::None;
@@ -97,14 +123,79 @@ impl EnvValue {
     }
 }
 
+/// Format a decimal so it always reads back as a decimal (see parse_from_string)
+/// Like Python's str(float): plain notation with at least one fractional digit ("1.0"),
+/// switching to exponent notation only for very large or small magnitudes ("1.5e-7")
+fn format_decimal(d: f64) -> String {
+    if !d.is_finite() {
+        return d.to_string();
+    }
+
+    let magnitude = d.abs();
+    if magnitude != 0.0 && !(1e-4..1e16).contains(&magnitude) {
+        // Rust writes "1e16" - keep a decimal point in the mantissa so it parses as a decimal
+        let formatted = format!("{:e}", d);
+        return match formatted.split_once('e') {
+            Some((mantissa, exponent)) if !mantissa.contains('.') => {
+                format!("{}.0e{}", mantissa, exponent)
+            }
+            _ => formatted,
+        };
+    }
+
+    // Display never uses exponents and gives the shortest string that round-trips
+    let formatted = d.to_string();
+    if formatted.contains('.') {
+        formatted
+    } else {
+        format!("{}.0", formatted)
+    }
+}
+
+/// Represents errors that can occur when modifying the shell environment
+#[derive(Debug)]
+pub enum EnvError {
+    /// Variable has been marked readonly
+    ReadOnly(String),
+    /// Variable is maintained by the shell and can't be removed or set to a non-integer
+    /// ($?, $$, PPID)
+    ShellManaged(String),
+    /// Name can't be passed to child processes (empty, or contains '=' or NUL)
+    InvalidName(String),
+}
+
+impl EnvError {
+    /// Get the error message
+    pub fn message(&self) -> &str {
+        match self {
+            EnvError::ReadOnly(msg) => msg,
+            EnvError::ShellManaged(msg) => msg,
+            EnvError::InvalidName(msg) => msg,
+        }
+    }
+}
+
+/// Directories in PATH when the shell wasn't given one (macOS also includes /usr/sbin and /sbin)
+#[cfg(target_os = "macos")]
+const DEFAULT_PATH: [&str; 4] = ["/usr/bin", "/bin", "/usr/sbin", "/sbin"];
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_PATH: [&str; 2] = ["/usr/bin", "/bin"];
+
+/// Variables still passed to commands run with env_inherit(False)
+const ESSENTIAL_VARS: [&str; 3] = ["PATH", "HOME", "PWD"];
+
 /// The shell's environment, containing all environment variables and directory stack
 pub struct ShellEnvironment {
     env_vars: HashMap<String, EnvValue>,
+    readonly: HashSet<String>,
+    os_sync: bool,
     dir_stack: Vec<PathBuf>,
     pub last_exit: EnvValue,
     pid: EnvValue,
     ppid: EnvValue,
     old_pwd: EnvValue,
+    /// When set, the only variables passed to child processes (see limit_exports)
+    export_filter: Option<HashSet<String>>,
 }
 
 impl ShellEnvironment {
@@ -112,11 +203,14 @@ impl ShellEnvironment {
     pub fn new() -> Self {
         Self {
             env_vars: HashMap::new(),
+            readonly: HashSet::new(),
+            os_sync: false,
             dir_stack: Vec::new(),
             last_exit: EnvValue::Integer(0),
             pid: EnvValue::Integer(getpid().as_raw().into()),
             ppid: EnvValue::Integer(getppid().as_raw().into()),
             old_pwd: EnvValue::None,
+            export_filter: None,
         }
     }
 
@@ -124,15 +218,24 @@ impl ShellEnvironment {
     pub fn from_parent() -> Self {
         let mut env_vars = HashMap::new();
         for (key, value) in std::env::vars() {
-            env_vars.insert(key, EnvValue::parse_from_string(&value));
+            // An exported empty variable is set, unlike None, so keep it as an empty string
+            let value = if value.is_empty() {
+                EnvValue::String(String::new())
+            } else {
+                EnvValue::parse_from_string(&value)
+            };
+            env_vars.insert(key, value);
         }
         Self {
             env_vars,
+            readonly: HashSet::new(),
+            os_sync: false,
             dir_stack: Vec::new(),
             last_exit: EnvValue::Integer(0),
             pid: EnvValue::Integer(getpid().as_raw().into()),
             ppid: EnvValue::Integer(getppid().as_raw().into()),
             old_pwd: EnvValue::None,
+            export_filter: None,
         }
     }
 
@@ -148,9 +251,6 @@ impl ShellEnvironment {
             // Internal Shell Things
             "OLDPWD" => Some(&self.old_pwd),
 
-            // ENV not supported yet
-            "ENV" => panic!("ENV environment variable not supported yet"),
-
             // $? is exit status from most recent pipeline
             "?" => Some(&self.last_exit),
 
@@ -163,27 +263,117 @@ impl ShellEnvironment {
     }
 
     /// Set an environment variable
-    pub fn set(&mut self, key: String, value: EnvValue) {
+    pub fn set(&mut self, key: String, value: EnvValue) -> Result<(), EnvError> {
+        // A name like this can't be written as NAME=value, so to_envp would have to drop it
+        if key.is_empty() || key.contains(['=', '\0']) {
+            return Err(EnvError::InvalidName(format!(
+                "{:?}: invalid variable name",
+                key
+            )));
+        }
+        if self.readonly.contains(&key) {
+            return Err(EnvError::ReadOnly(format!("{}: readonly variable", key)));
+        }
+
         match key.as_ref() {
+            // Always the shell's own PID - a stored value would only reach children
+            "$" => {
+                return Err(EnvError::ShellManaged(format!(
+                    "{}: cannot set: shell-managed variable",
+                    key
+                )));
+            }
+
+            // Still integers like the values the shell sets, so last_exit() and ppid() are too
+            "PPID" | "?" if !matches!(value, EnvValue::Integer(_)) => {
+                return Err(EnvError::ShellManaged(format!(
+                    "{}: shell-managed variable must be an integer",
+                    key
+                )));
+            }
+
             // I guess you can set this if you *really* wanted to
             "PPID" => self.ppid = value,
+            "?" => self.last_exit = value,
 
             // Internally-managed variables
             "OLDPWD" => self.old_pwd = value,
 
-            // ENV not supported yet
-            "ENV" => panic!("ENV environment variable not supported yet"),
-
             // Everything else comes from the environment
             _ => {
                 self.env_vars.insert(key, value);
             }
         };
+        Ok(())
     }
 
     /// Remove an environment variable
-    pub fn unset(&mut self, key: &str) -> Option<EnvValue> {
-        self.env_vars.remove(key)
+    pub fn unset(&mut self, key: &str) -> Result<Option<EnvValue>, EnvError> {
+        if self.readonly.contains(key) {
+            return Err(EnvError::ReadOnly(format!(
+                "{}: cannot unset: readonly variable",
+                key
+            )));
+        }
+
+        match key {
+            // These always have a value while the shell is running
+            "PPID" | "?" | "$" => Err(EnvError::ShellManaged(format!(
+                "{}: cannot unset: shell-managed variable",
+                key
+            ))),
+
+            // Internally-managed variables go back to having no value
+            "OLDPWD" => {
+                // Drop any copy inherited from the parent too, which the field hides
+                self.env_vars.remove(key);
+                let previous = std::mem::replace(&mut self.old_pwd, EnvValue::None);
+                Ok(Some(previous).filter(|value| !matches!(value, EnvValue::None)))
+            }
+
+            _ => Ok(self.env_vars.remove(key)),
+        }
+    }
+
+    /// Remove all environment variables except readonly ones
+    /// Internally-managed values ($?, $$, PPID, OLDPWD) are not part of the map and are kept
+    /// Returns the names of the removed variables
+    pub fn clear_vars(&mut self) -> Vec<String> {
+        let removed: Vec<String> = self
+            .env_vars
+            .keys()
+            .filter(|key| !self.readonly.contains(*key))
+            .cloned()
+            .collect();
+        for key in &removed {
+            self.env_vars.remove(key);
+        }
+        removed
+    }
+
+    /// Mark a variable as readonly so it can no longer be set or unset
+    pub fn mark_readonly(&mut self, key: String) {
+        self.readonly.insert(key);
+    }
+
+    /// Check if a variable is readonly
+    pub fn is_readonly(&self, key: &str) -> bool {
+        self.readonly.contains(key)
+    }
+
+    /// Get the names of all readonly variables
+    pub fn readonly_keys(&self) -> impl Iterator<Item = &String> {
+        self.readonly.iter()
+    }
+
+    /// Enable or disable mirroring variable changes into the process environment
+    pub fn set_os_sync(&mut self, enabled: bool) {
+        self.os_sync = enabled;
+    }
+
+    /// Check if variable changes are mirrored into the process environment
+    pub fn os_sync(&self) -> bool {
+        self.os_sync
     }
 
     /// Get all environment variables
@@ -206,18 +396,61 @@ impl ShellEnvironment {
         self.env_vars.len()
     }
 
+    /// Pass child processes only the essential variables (PATH, HOME, PWD) and `keys`
+    /// With `inherit`, `keys` are only added to a limit that's already in place
+    /// Returns the previous limit, to put back with restore_exports()
+    pub fn limit_exports<'a>(
+        &mut self,
+        keys: impl IntoIterator<Item = &'a String>,
+        inherit: bool,
+    ) -> Option<HashSet<String>> {
+        let previous = self.export_filter.clone();
+        if !inherit {
+            self.export_filter = Some(ESSENTIAL_VARS.iter().map(|key| key.to_string()).collect());
+        }
+        if let Some(filter) = &mut self.export_filter {
+            filter.extend(keys.into_iter().cloned());
+        }
+        previous
+    }
+
+    /// Put back the limit on exported variables replaced by limit_exports()
+    pub fn restore_exports(&mut self, filter: Option<HashSet<String>>) {
+        self.export_filter = filter;
+    }
+
     /// Convert environment to Vec<CString> in "KEY=VALUE" format for execve
     pub fn to_envp(&self) -> Vec<CString> {
         self.env_vars
             .iter()
+            .filter(|(key, _)| {
+                self.export_filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.contains(*key))
+            })
             .filter_map(|(key, value)| {
                 let value_str = value.to_string_repr();
-                // Include all variables, even those with empty string values (EnvValue::None)
+                // Include all variables, even empty ones (EnvValue::None or ""), as KEY=
                 CString::new(format!("{}={}", key, value_str)).ok()
             })
             .collect()
     }
 
+    /// Get the exit status of the most recent pipeline
+    pub fn last_exit(&self) -> &EnvValue {
+        &self.last_exit
+    }
+
+    /// Get the shell's process ID
+    pub fn pid(&self) -> &EnvValue {
+        &self.pid
+    }
+
+    /// Get the shell's parent process ID
+    pub fn ppid(&self) -> &EnvValue {
+        &self.ppid
+    }
+
     /// Push a directory onto the directory stack
     pub fn push_dir(&mut self, dir: PathBuf) {
         self.dir_stack.push(dir);
@@ -249,18 +482,145 @@ pub fn get_var(key: &str) -> Option<EnvValue> {
     env_read.get(key).cloned()
 }
 
+/// Get HOME as a path, or None if it is unset or empty
+/// HOME is EnvValue::None when no home directory could be determined at startup
+pub fn home_dir() -> Option<PathBuf> {
+    match get_var("HOME")? {
+        EnvValue::FilePath(path) if !path.as_os_str().is_empty() => Some(path),
+        EnvValue::String(s) if !s.is_empty() => Some(PathBuf::from(s)),
+        _ => None,
+    }
+}
+
 /// Set an environment variable
-pub fn set_var(key: String, value: EnvValue) {
+pub fn set_var(key: String, value: EnvValue) -> Result<(), EnvError> {
     let env = get_shell_env();
-    let mut env_write = env.write().unwrap();
-    env_write.set(key, value);
+    env.write().unwrap().set(key.clone(), value.clone())?;
+
+    // Notify outside the lock so observers can use the shell environment
+    notify_observers(&key, Some(&value));
+    Ok(())
 }
 
 /// Remove an environment variable
-pub fn unset_var(key: &str) -> Option<EnvValue> {
+pub fn unset_var(key: &str) -> Result<Option<EnvValue>, EnvError> {
+    let env = get_shell_env();
+    let removed = env.write().unwrap().unset(key)?;
+
+    notify_observers(key, None);
+    Ok(removed)
+}
+
+/// Add entries to a list variable such as PATH, leaving out any it already has (see same_entry)
+/// Entries go at the end, or with `prepend` at the front - where an entry already in the list is
+/// moved, so it takes precedence. An unset or empty variable starts out as an empty list, and
+/// any other single value as a one-item list
+pub fn add_to_list(key: &str, entries: Vec<EnvValue>, prepend: bool) -> Result<(), EnvError> {
+    let mut items = match get_var(key) {
+        None | Some(EnvValue::None) => Vec::new(),
+        Some(EnvValue::String(s)) if s.is_empty() => Vec::new(),
+        Some(EnvValue::List(items)) => items,
+        Some(value) => vec![value],
+    };
+
+    let mut added: Vec<EnvValue> = Vec::new();
+    for entry in entries {
+        let present = |list: &[EnvValue]| list.iter().any(|item| item.same_entry(&entry));
+        if !present(&added) && (prepend || !present(&items)) {
+            added.push(entry);
+        }
+    }
+    if prepend {
+        items.retain(|item| !added.iter().any(|entry| entry.same_entry(item)));
+        items.splice(0..0, added);
+    } else {
+        items.extend(added);
+    }
+
+    set_var(key.to_string(), EnvValue::List(items))
+}
+
+/// Remove all environment variables except readonly ones
+pub fn clear_vars() {
+    let env = get_shell_env();
+    let removed = env.write().unwrap().clear_vars();
+
+    for key in &removed {
+        notify_observers(key, None);
+    }
+}
+
+/// Observer for variable changes: (key, Some(value)) on set, (key, None) on unset
+/// Only changes made through set_var(), unset_var() and clear_vars() are reported - not the
+/// temporary overlays of with_env()
+pub type EnvObserver = Box<dyn Fn(&str, Option<&EnvValue>) + Send + Sync>;
+static ENV_OBSERVERS: OnceLock<RwLock<Vec<EnvObserver>>> = OnceLock::new();
+
+fn get_env_observers() -> &'static RwLock<Vec<EnvObserver>> {
+    ENV_OBSERVERS.get_or_init(|| RwLock::new(Vec::new()))
+}
+
+/// Register an observer for environment variable changes
+/// Observers run in registration order and must not register further observers
+pub fn add_env_observer(observer: EnvObserver) {
+    get_env_observers().write().unwrap().push(observer);
+}
+
+/// Tell every observer about a change
+/// Callers must not hold the environment lock, since observers may read or modify variables
+fn notify_observers(key: &str, value: Option<&EnvValue>) {
+    let observers = get_env_observers().read().unwrap();
+    for observer in observers.iter() {
+        observer(key, value);
+    }
+}
+
+/// Enable or disable mirroring set_var()/unset_var() changes into the process environment
+pub fn set_os_sync(enabled: bool) {
     let env = get_shell_env();
     let mut env_write = env.write().unwrap();
-    env_write.unset(key)
+    env_write.set_os_sync(enabled);
+}
+
+/// Check if set_var()/unset_var() changes are mirrored into the process environment
+pub fn os_sync_enabled() -> bool {
+    let env = get_shell_env();
+    let env_read = env.read().unwrap();
+    env_read.os_sync()
+}
+
+/// Observer for synced variable changes: (key, Some(value)) on set, (key, None) on unset
+/// This lets the bindings layer update its own view of the environment (e.g. os.environ)
+type OsSyncHook = Box<dyn Fn(&str, Option<&str>) + Send + Sync>;
+static OS_SYNC_HOOK: OnceLock<OsSyncHook> = OnceLock::new();
+
+pub fn set_os_sync_hook(hook: OsSyncHook) {
+    OS_SYNC_HOOK.set(hook).ok();
+}
+
+/// Mirror a variable change into the process environment
+///
+/// Child processes never see this - they get the shell environment through to_envp() at
+/// execve - so it only matters to in-process code such as Python's os.environ or C libraries
+/// calling getenv()
+fn sync_to_os(key: &str, value: Option<&str>) {
+    // The process environment can't represent these, and std::env would panic on them
+    if key.is_empty() || key.contains(['=', '\0']) || value.is_some_and(|v| v.contains('\0')) {
+        return;
+    }
+
+    // SAFETY: the shell never reads the process environment from other threads - children
+    // receive an explicit envp instead
+    unsafe {
+        match value {
+            Some(value) => std::env::set_var(key, value),
+            None => std::env::remove_var(key),
+        }
+    }
+
+    if let Some(hook) = OS_SYNC_HOOK.get() {
+        hook(key, value);
+    }
 }
 
 /// Check if an environment variable exists
@@ -291,6 +651,20 @@ pub fn all_vars() -> HashMap<String, EnvValue> {
     env_read.all_vars().clone()
 }
 
+/// Get the environment child processes receive, as sorted KEY=VALUE strings
+pub fn export_list() -> Vec<String> {
+    let env = get_shell_env();
+    let mut exports: Vec<String> = env
+        .read()
+        .unwrap()
+        .to_envp()
+        .iter()
+        .map(|entry| entry.to_string_lossy().into_owned())
+        .collect();
+    exports.sort();
+    exports
+}
+
 /// Set the exit status of the last executed command
 pub fn set_last_exit(exit_code: u8) {
     let env = get_shell_env();
@@ -298,45 +672,101 @@ pub fn set_last_exit(exit_code: u8) {
     env_write.last_exit = EnvValue::Integer(exit_code as i64);
 }
 
+/// Get the exit status of the last executed command
+pub fn get_last_exit() -> EnvValue {
+    let env = get_shell_env();
+    let env_read = env.read().unwrap();
+    env_read.last_exit().clone()
+}
+
+/// Get the shell's process ID
+pub fn get_pid() -> EnvValue {
+    let env = get_shell_env();
+    let env_read = env.read().unwrap();
+    env_read.pid().clone()
+}
+
+/// Get the shell's parent process ID
+pub fn get_ppid() -> EnvValue {
+    let env = get_shell_env();
+    let env_read = env.read().unwrap();
+    env_read.ppid().clone()
+}
+
+/// Get the pushd/popd directory stack as a list of paths, in the order dirs lists them
+pub fn get_dir_stack() -> EnvValue {
+    let env = get_shell_env();
+    let env_read = env.read().unwrap();
+    EnvValue::List(
+        env_read
+            .dir_stack()
+            .iter()
+            .cloned()
+            .map(EnvValue::FilePath)
+            .collect(),
+    )
+}
+
+/// Check if this is the shell's own process rather than a forked child (e.g. a pipeline stage)
+pub fn is_shell_process() -> bool {
+    match get_pid() {
+        EnvValue::Integer(pid) => i64::from(getpid().as_raw()) == pid,
+        _ => false,
+    }
+}
+
 /// Initialize the shell environment from the parent process
 pub fn initialize_environment() {
+    // Mirror changes into the process environment while os sync is enabled
+    add_env_observer(Box::new(|key, value| {
+        if os_sync_enabled() {
+            sync_to_os(key, value.map(EnvValue::to_string_repr).as_deref());
+        }
+    }));
+
+    // Commands found with the old PATH may no longer be the ones it selects
+    add_env_observer(Box::new(|key, _value| {
+        if key == "PATH" {
+            crate::shell::exec::clear_resolution_cache();
+        }
+    }));
+
     let env = get_shell_env();
     let mut env_write = env.write().unwrap();
     *env_write = ShellEnvironment::from_parent();
 
+    // Nothing can be readonly yet, so the sets below cannot fail
+
     // HOME is either inherited from the parent, or retrieved from the user database
     let home_dir = match home::home_dir() {
         Some(path) if !path.as_os_str().is_empty() => EnvValue::FilePath(path),
         _ => EnvValue::None,
     };
-    env_write.set("HOME".to_string(), home_dir.clone());
+    env_write.set("HOME".to_string(), home_dir.clone()).ok();
 
     // PWD is the CWD, or we default to home if not set
     if env_write.get("PWD").is_none() {
-        env_write.set(
-            "PWD".to_string(),
-            match getcwd() {
-                Ok(path) => EnvValue::FilePath(path),
-                Err(_) => home_dir,
-            },
-        );
+        env_write
+            .set(
+                "PWD".to_string(),
+                match getcwd() {
+                    Ok(path) => EnvValue::FilePath(path),
+                    Err(_) => home_dir,
+                },
+            )
+            .ok();
     }
 
     // Default path is /usr/bin:/bin (and /usr/sbin:/sbin on macOS)
     if env_write.get("PATH").is_none() {
-        let mut default_paths = vec![
-            EnvValue::FilePath(PathBuf::from("/usr/bin")),
-            EnvValue::FilePath(PathBuf::from("/bin")),
-        ];
-
-        // On macOS, also include /usr/sbin and /sbin
-        #[cfg(target_os = "macos")]
-        {
-            default_paths.push(EnvValue::FilePath(PathBuf::from("/usr/sbin")));
-            default_paths.push(EnvValue::FilePath(PathBuf::from("/sbin")));
-        }
+        let default_paths = DEFAULT_PATH
+            .iter()
+            .map(|dir| EnvValue::FilePath(PathBuf::from(dir)))
+            .collect();
 
-        env_write.set("PATH".to_string(), EnvValue::List(default_paths));
+        env_write
+            .set("PATH".to_string(), EnvValue::List(default_paths))
+            .ok();
     }
 
     // Increment SHLVL (inheriting from parent if present)
@@ -344,5 +774,142 @@ pub fn initialize_environment() {
         Some(EnvValue::Integer(i)) => *i + 1,
         _ => 0,
     };
-    env_write.set("SHLVL".to_string(), EnvValue::Integer(current_shlvl));
+    env_write
+        .set("SHLVL".to_string(), EnvValue::Integer(current_shlvl))
+        .ok();
+    drop(env_write);
+
+    // COLUMNS and LINES follow the terminal's size
+    crate::shell::terminal::track_terminal_size();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_decimal_keeps_a_decimal_point() {
+        assert_eq!(format_decimal(1.0), "1.0");
+        assert_eq!(format_decimal(-3.0), "-3.0");
+        assert_eq!(format_decimal(0.0), "0.0");
+        assert_eq!(format_decimal(2.5), "2.5");
+        assert_eq!(format_decimal(1e16), "1.0e16");
+        assert_eq!(format_decimal(1.5e-7), "1.5e-7");
+        assert_eq!(format_decimal(f64::INFINITY), "inf");
+    }
+
+    #[test]
+    fn decimals_round_trip_through_strings() {
+        for d in [
+            0.0, 1.0, -1.0, 0.1, 2.5, 100.0, 1e-4, 1.5e-7, 1e15, 1e16, 123456.789, -2.5e20,
+        ] {
+            let formatted = EnvValue::Decimal(d).to_string_repr();
+            assert_eq!(
+                EnvValue::parse_from_string(&formatted),
+                EnvValue::Decimal(d),
+                "{} formatted as {:?}",
+                d,
+                formatted
+            );
+        }
+    }
+
+    #[test]
+    fn observers_are_notified_on_set_and_unset() {
+        static SEEN: std::sync::Mutex<Vec<(String, Option<EnvValue>)>> =
+            std::sync::Mutex::new(Vec::new());
+        let key = "OBSERVER_TEST_VAR";
+        add_env_observer(Box::new(move |changed, value| {
+            if changed == key {
+                // The environment isn't locked, so observers can read it back
+                assert_eq!(get_var(key).as_ref(), value);
+                SEEN.lock()
+                    .unwrap()
+                    .push((changed.to_string(), value.cloned()));
+            }
+        }));
+
+        set_var(key.to_string(), EnvValue::String("one".to_string())).unwrap();
+        unset_var(key).unwrap();
+        assert_eq!(
+            *SEEN.lock().unwrap(),
+            [
+                (key.to_string(), Some(EnvValue::String("one".to_string()))),
+                (key.to_string(), None)
+            ]
+        );
+    }
+
+    fn path(dir: &str) -> EnvValue {
+        EnvValue::FilePath(PathBuf::from(dir))
+    }
+
+    fn string(s: &str) -> EnvValue {
+        EnvValue::String(s.to_string())
+    }
+
+    #[test]
+    fn same_entry_ignores_type_and_trailing_slashes() {
+        assert!(path("/usr/bin").same_entry(&string("/usr/bin")));
+        assert!(path("/usr/bin").same_entry(&string("/usr/bin/")));
+        assert!(string("/").same_entry(&path("//")));
+        assert!(EnvValue::Integer(8080).same_entry(&string("8080")));
+        assert!(!path("/usr/bin").same_entry(&path("/usr/sbin")));
+        assert!(!string("/").same_entry(&string("")));
+    }
+
+    #[test]
+    fn add_to_list_skips_entries_already_present() {
+        let key = "ADD_TO_LIST_TEST_APPEND";
+        set_var(
+            key.to_string(),
+            EnvValue::List(vec![path("/usr/bin"), path("/bin")]),
+        )
+        .unwrap();
+
+        add_to_list(key, vec![string("/usr/bin"), string("/opt/bin")], false).unwrap();
+        assert_eq!(
+            get_var(key),
+            Some(EnvValue::List(vec![
+                path("/usr/bin"),
+                path("/bin"),
+                string("/opt/bin")
+            ]))
+        );
+    }
+
+    #[test]
+    fn add_to_list_prepend_moves_entries_to_the_front() {
+        let key = "ADD_TO_LIST_TEST_PREPEND";
+        set_var(
+            key.to_string(),
+            EnvValue::List(vec![path("/usr/bin"), path("/bin")]),
+        )
+        .unwrap();
+
+        add_to_list(key, vec![string("/bin/"), string("/opt/bin")], true).unwrap();
+        assert_eq!(
+            get_var(key),
+            Some(EnvValue::List(vec![
+                string("/bin/"),
+                string("/opt/bin"),
+                path("/usr/bin")
+            ]))
+        );
+    }
+
+    #[test]
+    fn add_to_list_starts_unset_and_single_values_as_lists() {
+        let unset = "ADD_TO_LIST_TEST_UNSET";
+        add_to_list(unset, vec![path("/a"), string("/a/")], false).unwrap();
+        assert_eq!(get_var(unset), Some(EnvValue::List(vec![path("/a")])));
+
+        let single = "ADD_TO_LIST_TEST_SINGLE";
+        set_var(single.to_string(), path("/a")).unwrap();
+        add_to_list(single, vec![path("/b")], false).unwrap();
+        assert_eq!(
+            get_var(single),
+            Some(EnvValue::List(vec![path("/a"), path("/b")]))
+        );
+    }
 }
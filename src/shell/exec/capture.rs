@@ -1,33 +1,24 @@
 use nix::libc;
-use nix::sys::wait::{WaitStatus, waitpid};
+use nix::pty::{Winsize, openpty};
+use nix::sys::termios::{OutputFlags, SetArg, tcgetattr, tcsetattr};
 use nix::unistd::{ForkResult, Pid, fork, pipe};
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
 use std::os::unix::io::{AsRawFd, IntoRawFd};
+use std::thread;
 
+use super::pipeline::{capture_file, exec_pipeline_stage};
 use super::resolution::resolve_and_exec;
 use super::types::{CommandSpec, ShellResult};
 use crate::shell::env::{EnvValue, get_shell_env};
+use crate::shell::terminal::terminal_size;
 
-/// Wait for a child and return captured result with FDs
-fn wait_for_child_captured(child: Pid, stdout_fd: i32, stderr_fd: i32) -> ShellResult {
-    match waitpid(child, None) {
-        Ok(WaitStatus::Exited(_pid, exit_code)) => ShellResult::Captured {
-            exit_code: exit_code as u8,
-            stdout_fd,
-            stderr_fd,
-        },
-        Ok(WaitStatus::Signaled(_pid, signal, _core_dump)) => ShellResult::Captured {
-            exit_code: 128 + (signal as i32) as u8,
-            stdout_fd,
-            stderr_fd,
-        },
-        Ok(status) => {
-            panic!("Unexpected wait status: {:?}", status);
-        }
-        Err(e) => {
-            panic!("waitpid failed: {}", e);
-        }
-    }
+/// Wait for a child writing to capture files and return them as the captured result
+/// A stopped child is continued, since its output can't be read until it finishes
+fn wait_for_child_captured(child: Pid, stdout: File, stderr: File) -> ShellResult {
+    let exit_code = super::wait_exit_code(child, &[child], true);
+    captured_files(exit_code, stdout, stderr)
 }
 
 /// Internal execution with capture: Execute a CommandSpec and capture stdout/stderr
@@ -48,71 +39,37 @@ pub(super) fn execute_command_spec_with_capture(spec: &CommandSpec) -> ShellResu
             // Redirect wins - execute normally and return empty capture
             // The output goes to the file, not our pipes
             let result = super::execute_redirect(runnable, target);
-
-            // Create dummy pipes that are already closed (empty)
-            let (stdout_read, stdout_write) = pipe().expect("Failed to create pipe");
-            let (stderr_read, stderr_write) = pipe().expect("Failed to create pipe");
-
-            // Close write ends immediately (no data will be written)
-            drop(stdout_write);
-            drop(stderr_write);
-
-            // Leak the read ends and return
-            ShellResult::Captured {
-                exit_code: result.exit_code(),
-                stdout_fd: stdout_read.into_raw_fd(),
-                stderr_fd: stderr_read.into_raw_fd(),
-            }
+            fixed_capture(result.exit_code(), "")
         }
         CommandSpec::WithEnv {
             runnable,
             env_overlay,
-        } => execute_with_env_captured(runnable, env_overlay),
-    }
-}
-
-/// Execute a command with stdout/stderr capture
-fn execute_command_captured(program: &str, args: &[String]) -> ShellResult {
-    // Create pipes for stdout and stderr
-    let (stdout_read, stdout_write) = pipe().expect("Failed to create stdout pipe");
-    let (stderr_read, stderr_write) = pipe().expect("Failed to create stderr pipe");
-
-    match unsafe { fork() } {
-        Ok(ForkResult::Parent { child }) => {
-            // Parent: close write ends
-            drop(stdout_write);
-            drop(stderr_write);
-
-            // Leak read ends and wait for child
-            let stdout_fd = stdout_read.into_raw_fd();
-            let stderr_fd = stderr_read.into_raw_fd();
-            wait_for_child_captured(child, stdout_fd, stderr_fd)
-        }
-        Ok(ForkResult::Child) => {
-            // Child: close read ends and redirect stdout/stderr
-            drop(stdout_read);
-            drop(stderr_read);
-
-            unsafe {
-                libc::dup2(stdout_write.as_raw_fd(), 1); // stdout
-                libc::dup2(stderr_write.as_raw_fd(), 2); // stderr
-            }
-            drop(stdout_write);
-            drop(stderr_write);
-
-            // Execute the program
-            resolve_and_exec(program, args);
+            inherit,
+        } => execute_with_env_captured(runnable, env_overlay, *inherit),
+        // Stdin is set up in a child anyway, so capture that whole child like a subshell
+        CommandSpec::Input { .. } | CommandSpec::InputRedirect { .. } => {
+            execute_subshell_captured(spec)
         }
-        Err(e) => panic!("fork failed: {}", e),
     }
 }
 
-/// Execute a builtin with stdout/stderr capture
-fn execute_builtin_captured(func: &fn(&[String]) -> i32, args: &[String]) -> ShellResult {
-    // Create pipes for stdout and stderr
-    let (stdout_read, stdout_write) = pipe().expect("Failed to create stdout pipe");
-    let (stderr_read, stderr_write) = pipe().expect("Failed to create stderr pipe");
+/// Execute a CommandSpec with stdout and stderr sent to the same capture file
+/// Both streams share one open file, so the order of writes across them is preserved
+/// Returns a Captured result with the combined output as stdout and an empty stderr
+pub(super) fn execute_command_spec_with_merged_capture(spec: &CommandSpec) -> ShellResult {
+    let output = capture_file();
+    let result = with_output_redirected(&output, &output, || super::execute_command_spec(spec));
+    captured_files(result.exit_code(), output, capture_file())
+}
 
+/// Run `f` in the shell process with stdout and stderr (fds 1 and 2) pointing at the given
+/// files, then put them back - how anything run in-process is captured (builtins, and the whole
+/// spec for merged capture). Whatever `f` forks inherits the redirection, so a builtin that
+/// starts commands has their output captured with its own
+///
+/// Files rather than pipes, since nothing reads the output until `f` returns: a pipe would fill
+/// up and block a builtin, or a wait on a child, once there was a pipe buffer's worth
+pub(super) fn with_output_redirected<T>(stdout: &File, stderr: &File, f: impl FnOnce() -> T) -> T {
     // Save original stdout and stderr
     let saved_stdout = unsafe { libc::dup(1) };
     let saved_stderr = unsafe { libc::dup(2) };
@@ -120,18 +77,16 @@ fn execute_builtin_captured(func: &fn(&[String]) -> i32, args: &[String]) -> She
         panic!("Failed to save stdout/stderr");
     }
 
-    // Redirect stdout and stderr to pipes
     unsafe {
-        libc::dup2(stdout_write.as_raw_fd(), 1);
-        libc::dup2(stderr_write.as_raw_fd(), 2);
+        libc::dup2(stdout.as_raw_fd(), 1);
+        libc::dup2(stderr.as_raw_fd(), 2);
     }
 
-    // Close write ends (dup2 created copies at fd 1 and 2)
-    drop(stdout_write);
-    drop(stderr_write);
+    let result = f();
 
-    // Execute the builtin
-    let exit_code = func(args);
+    // Output still buffered in the shell belongs to the capture, not the terminal
+    std::io::stdout().flush().ok();
+    std::io::stderr().flush().ok();
 
     // Restore original stdout and stderr
     unsafe {
@@ -140,43 +95,149 @@ fn execute_builtin_captured(func: &fn(&[String]) -> i32, args: &[String]) -> She
         libc::close(saved_stdout);
         libc::close(saved_stderr);
     }
+    result
+}
 
-    // Leak read ends and return
+/// Rewind capture files and leak them as a result's FDs
+pub(super) fn captured_files(exit_code: u8, mut stdout: File, mut stderr: File) -> ShellResult {
+    stdout.seek(SeekFrom::Start(0)).ok();
+    stderr.seek(SeekFrom::Start(0)).ok();
     ShellResult::Captured {
-        exit_code: exit_code as u8,
-        stdout_fd: stdout_read.into_raw_fd(),
-        stderr_fd: stderr_read.into_raw_fd(),
+        exit_code,
+        stdout_fd: stdout.into_raw_fd(),
+        stderr_fd: stderr.into_raw_fd(),
     }
 }
 
-/// Execute a subshell with capture
-fn execute_subshell_captured(spec: &CommandSpec) -> ShellResult {
-    // Create pipes for stdout and stderr
-    let (stdout_read, stdout_write) = pipe().expect("Failed to create stdout pipe");
-    let (stderr_read, stderr_write) = pipe().expect("Failed to create stderr pipe");
+/// Internal execution with capture: Execute a CommandSpec with its stdout on a pseudo-terminal
+/// and capture what it writes there, so tools behave as they would interactively (columns,
+/// colours, line buffering). stderr is captured separately, unless `merge_stderr` sends it to
+/// the terminal too (the result's stderr is then empty)
+pub(super) fn execute_command_spec_with_pty_capture(
+    spec: &CommandSpec,
+    merge_stderr: bool,
+) -> ShellResult {
+    // Size the terminal like the shell's, for tools that lay out output to fit it
+    let winsize = terminal_size().map(|(cols, rows)| Winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    });
+    let pty = openpty(winsize.as_ref(), None).expect("Failed to open pseudo-terminal");
+
+    // Keep newlines as written, rather than translated to \r\n on their way through
+    if let Ok(mut termios) = tcgetattr(&pty.slave) {
+        termios.output_flags.remove(OutputFlags::ONLCR);
+        tcsetattr(&pty.slave, SetArg::TCSANOW, &termios).ok();
+    }
+
+    let stdout_capture = capture_file();
+    let stderr_capture = capture_file();
 
     match unsafe { fork() } {
         Ok(ForkResult::Parent { child }) => {
-            // Parent: close write ends
-            drop(stdout_write);
-            drop(stderr_write);
-
-            // Leak read ends and wait for child
-            let stdout_fd = stdout_read.into_raw_fd();
-            let stderr_fd = stderr_read.into_raw_fd();
-            wait_for_child_captured(child, stdout_fd, stderr_fd)
+            drop(pty.slave);
+
+            // Drain the terminal while the command runs, as its buffer is small - once every
+            // process has closed it, reads fail (EIO) instead of returning EOF
+            let mut master = File::from(pty.master);
+            let mut capture = stdout_capture
+                .try_clone()
+                .expect("Failed to duplicate capture file");
+            let reader = thread::spawn(move || std::io::copy(&mut master, &mut capture).ok());
+
+            let exit_code = super::wait_exit_code(child, &[child], true);
+            reader.join().ok();
+
+            captured_files(exit_code, stdout_capture, stderr_capture)
         }
         Ok(ForkResult::Child) => {
-            // Child: close read ends and redirect stdout/stderr
-            drop(stdout_read);
-            drop(stderr_read);
+            drop(pty.master);
+            unsafe {
+                libc::dup2(pty.slave.as_raw_fd(), 1);
+                if merge_stderr {
+                    libc::dup2(pty.slave.as_raw_fd(), 2);
+                } else {
+                    libc::dup2(stderr_capture.as_raw_fd(), 2);
+                }
+            }
+            drop(pty.slave);
+            drop(stdout_capture);
+            drop(stderr_capture);
 
+            // Builtins run right here, anything else is exec'd or run by this child shell
+            exec_pipeline_stage(spec);
+        }
+        Err(e) => panic!("fork failed: {}", e),
+    }
+}
+
+/// Build a captured result without running anything
+/// stdout is empty and stderr contains the given text
+fn fixed_capture(exit_code: u8, stderr_content: &str) -> ShellResult {
+    // Create dummy pipes that are closed once the content is written
+    let (stdout_read, stdout_write) = pipe().expect("Failed to create pipe");
+    let (stderr_read, stderr_write) = pipe().expect("Failed to create pipe");
+
+    // Short messages fit in the pipe buffer, so this write won't block
+    File::from(stderr_write)
+        .write_all(stderr_content.as_bytes())
+        .ok();
+    drop(stdout_write);
+
+    // Leak the read ends and return
+    ShellResult::Captured {
+        exit_code,
+        stdout_fd: stdout_read.into_raw_fd(),
+        stderr_fd: stderr_read.into_raw_fd(),
+    }
+}
+
+/// Execute a command with stdout/stderr capture
+/// Output goes to capture files, as nothing reads it until the command has finished
+fn execute_command_captured(program: &str, args: &[String]) -> ShellResult {
+    let (stdout, stderr) = (capture_file(), capture_file());
+
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child }) => wait_for_child_captured(child, stdout, stderr),
+        Ok(ForkResult::Child) => {
             unsafe {
-                libc::dup2(stdout_write.as_raw_fd(), 1);
-                libc::dup2(stderr_write.as_raw_fd(), 2);
+                libc::dup2(stdout.as_raw_fd(), 1); // stdout
+                libc::dup2(stderr.as_raw_fd(), 2); // stderr
             }
-            drop(stdout_write);
-            drop(stderr_write);
+            drop(stdout);
+            drop(stderr);
+
+            // Execute the program
+            resolve_and_exec(program, args);
+        }
+        Err(e) => panic!("fork failed: {}", e),
+    }
+}
+
+/// Execute a builtin with stdout/stderr capture
+/// Builtins run in the shell process (so cd and friends take effect) with their output
+/// redirected - see with_output_redirected for what that covers
+fn execute_builtin_captured(func: &fn(&[String]) -> i32, args: &[String]) -> ShellResult {
+    let (stdout, stderr) = (capture_file(), capture_file());
+    let exit_code = with_output_redirected(&stdout, &stderr, || func(args));
+    captured_files(exit_code as u8, stdout, stderr)
+}
+
+/// Execute a subshell with capture, into capture files like a single command
+fn execute_subshell_captured(spec: &CommandSpec) -> ShellResult {
+    let (stdout, stderr) = (capture_file(), capture_file());
+
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child }) => wait_for_child_captured(child, stdout, stderr),
+        Ok(ForkResult::Child) => {
+            unsafe {
+                libc::dup2(stdout.as_raw_fd(), 1);
+                libc::dup2(stderr.as_raw_fd(), 2);
+            }
+            drop(stdout);
+            drop(stderr);
 
             // Execute the subshell command (without additional capture)
             let result = super::execute_command_spec(spec);
@@ -190,6 +251,7 @@ fn execute_subshell_captured(spec: &CommandSpec) -> ShellResult {
 fn execute_with_env_captured(
     spec: &CommandSpec,
     overlay: &HashMap<String, EnvValue>,
+    inherit: bool,
 ) -> ShellResult {
     // Save current environment state for variables in the overlay
     let env = get_shell_env();
@@ -201,13 +263,17 @@ fn execute_with_env_captured(
             .collect()
     };
 
-    // Apply overlay to environment
+    // Apply overlay to environment (readonly variables can't be overlaid)
     {
         let mut env_write = env.write().unwrap();
+        if let Some(key) = overlay.keys().find(|k| env_write.is_readonly(k)) {
+            return fixed_capture(1, &format!("{}: readonly variable\n", key));
+        }
         for (key, value) in overlay {
-            env_write.set(key.clone(), value.clone());
+            env_write.set(key.clone(), value.clone()).ok();
         }
     }
+    let saved_exports = env.write().unwrap().limit_exports(overlay.keys(), inherit);
 
     // Execute wrapped command with capture
     let result = execute_command_spec_with_capture(spec);
@@ -215,11 +281,15 @@ fn execute_with_env_captured(
     // Restore original environment
     {
         let mut env_write = env.write().unwrap();
+        env_write.restore_exports(saved_exports);
         for (key, original_value) in saved_vars {
+            // Restoring can only fail if the command made the variable readonly
             match original_value {
-                Some(value) => env_write.set(key, value),
+                Some(value) => {
+                    env_write.set(key, value).ok();
+                }
                 None => {
-                    env_write.unset(&key);
+                    env_write.unset(&key).ok();
                 }
             }
         }
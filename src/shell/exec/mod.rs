@@ -4,22 +4,51 @@ mod resolution;
 mod types;
 
 use nix::libc;
-use nix::sys::wait::{WaitStatus, waitpid};
-use nix::unistd::{ForkResult, Pid, fork};
+use nix::sys::signal::{SigHandler, Signal, kill, signal};
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::{ForkResult, Pid, fork, pipe, setpgid, setsid};
 use std::collections::HashMap;
+use std::io::{IsTerminal, Read, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
 
 // Re-export public types
-pub use types::{ExecRequest, RedirectTarget, ShellResult};
+pub use resolution::{cached_programs, clear_resolution_cache, rehash_program};
+pub use types::{ExecRequest, RedirectSource, RedirectTarget, ShellResult};
 
 use crate::shell::env::{EnvValue, get_shell_env};
-use pipeline::run_pipeline;
+use crate::shell::jobs;
+use crate::shell::options::get_option;
+use pipeline::{exec_pipeline_stage, reset_sigpipe, run_pipeline};
 use resolution::resolve_and_exec;
 use types::CommandSpec;
 
+/// Build the CommandSpec to run for a request
+/// With the debug option on, the command and its spec are printed to stderr first. With the
+/// dryrun option on, the command is printed to stderr and replaced by a builtin that does
+/// nothing and succeeds, so it runs in-process wherever the request would have forked or
+/// redirected (background and spawned requests still fork to have a PID to report)
+fn command_spec(request: &ExecRequest) -> CommandSpec {
+    let spec = CommandSpec::from(request);
+    if get_option("debug") == Some(true) {
+        eprintln!("+ {}\n  {:?}", request, spec);
+    }
+    // The set builtin still runs, so it can turn the dry run off again
+    let is_set = matches!(request, ExecRequest::Program { name, .. } if name == "set");
+    if get_option("dryrun") == Some(true) && !is_set {
+        eprintln!("dry run: {}", request);
+        return CommandSpec::Builtin {
+            name: "dry run".to_string(),
+            func: |_| 0,
+            args: Vec::new(),
+        };
+    }
+    spec
+}
+
 /// Public interface: Execute an ExecRequest (command, pipeline, subshell, or redirect)
 pub fn execute(request: &ExecRequest) -> ShellResult {
-    let spec = CommandSpec::from(request);
-    let result = execute_command_spec(&spec);
+    let spec = command_spec(request);
+    let result = jobs::in_foreground(request.to_string(), || execute_command_spec(&spec));
 
     // Update $? with the exit code
     crate::shell::set_last_exit(result.exit_code());
@@ -30,7 +59,7 @@ pub fn execute(request: &ExecRequest) -> ShellResult {
 /// Public interface: Execute an ExecRequest and capture stdout/stderr
 /// Returns file descriptors that the caller must close
 pub fn execute_with_capture(request: &ExecRequest) -> ShellResult {
-    let spec = CommandSpec::from(request);
+    let spec = command_spec(request);
     let result = capture::execute_command_spec_with_capture(&spec);
 
     // Update $? with the exit code
@@ -39,6 +68,284 @@ pub fn execute_with_capture(request: &ExecRequest) -> ShellResult {
     result
 }
 
+/// Public interface: Execute an ExecRequest capturing stdout and stderr as one ordered stream
+/// The combined output is returned as stdout (stderr is empty); the caller must close both
+pub fn execute_with_merged_capture(request: &ExecRequest) -> ShellResult {
+    let spec = command_spec(request);
+    let result = capture::execute_command_spec_with_merged_capture(&spec);
+
+    // Update $? with the exit code
+    crate::shell::set_last_exit(result.exit_code());
+
+    result
+}
+
+/// Public interface: Execute an ExecRequest with stdout on a pseudo-terminal and capture it
+/// With merge_stderr, stderr goes to the terminal too and the captured stderr is empty; the
+/// caller must close both
+pub fn execute_with_pty_capture(request: &ExecRequest, merge_stderr: bool) -> ShellResult {
+    let spec = command_spec(request);
+    let result = capture::execute_command_spec_with_pty_capture(&spec, merge_stderr);
+
+    // Update $? with the exit code
+    crate::shell::set_last_exit(result.exit_code());
+
+    result
+}
+
+/// Public interface: Execute an ExecRequest capturing the stdout/stderr of every pipeline stage
+/// Returns one Captured result per stage (a single result for non-pipelines) with file
+/// descriptors that the caller must close
+pub fn execute_capture_all(request: &ExecRequest) -> Vec<ShellResult> {
+    let stages = match command_spec(request) {
+        CommandSpec::Pipeline {
+            mut predecessors,
+            final_cmd,
+        } => {
+            predecessors.push(*final_cmd);
+            predecessors
+        }
+        spec => vec![spec],
+    };
+    let results = pipeline::run_pipeline_capture_all(&stages);
+
+    // Update $? as the pipeline would have
+    let codes: Vec<u8> = results.iter().map(ShellResult::exit_code).collect();
+    if let Some((final_code, predecessor_codes)) = codes.split_last() {
+        crate::shell::set_last_exit(pipeline::pipeline_exit_code(*final_code, predecessor_codes));
+    }
+
+    results
+}
+
+/// Public interface: Start an ExecRequest with its stdout connected to a pipe
+/// Returns the child PID and the read end of the pipe - the caller must drain the pipe and then
+/// call wait_spawned() with the PID
+pub fn spawn_with_stdout_pipe(request: &ExecRequest) -> (Pid, OwnedFd) {
+    let spec = command_spec(request);
+    let (stdout_read, stdout_write) = pipe().expect("Failed to create stdout pipe");
+
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child }) => {
+            // Parent: close write end so EOF arrives when the child exits
+            drop(stdout_write);
+            (child, stdout_read)
+        }
+        Ok(ForkResult::Child) => {
+            // Child: redirect stdout to the pipe and run like a pipeline stage
+            reset_sigpipe();
+            drop(stdout_read);
+            unsafe {
+                libc::dup2(stdout_write.as_raw_fd(), 1);
+            }
+            drop(stdout_write);
+            exec_pipeline_stage(&spec);
+        }
+        Err(e) => panic!("fork failed: {}", e),
+    }
+}
+
+/// Public interface: Start an ExecRequest with its stdin connected to a pipe
+/// Returns the child PID and the write end of the pipe - the caller must close the pipe when
+/// done writing and then wait for the child with wait_for_child()
+pub fn spawn_with_stdin_pipe(request: &ExecRequest) -> (Pid, OwnedFd) {
+    let spec = command_spec(request);
+    let (stdin_read, stdin_write) = pipe().expect("Failed to create stdin pipe");
+    let group = jobs::foreground_group(None);
+
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child }) => {
+            jobs::enter_foreground_group(child, group);
+            // Parent: close read end so writes fail once the child exits
+            drop(stdin_read);
+            (child, stdin_write)
+        }
+        Ok(ForkResult::Child) => {
+            jobs::enter_foreground_group(Pid::from_raw(0), group);
+            // Child: read stdin from the pipe and run like a pipeline stage
+            drop(stdin_write);
+            unsafe {
+                libc::dup2(stdin_read.as_raw_fd(), 0);
+            }
+            drop(stdin_read);
+            exec_pipeline_stage(&spec);
+        }
+        Err(e) => panic!("fork failed: {}", e),
+    }
+}
+
+/// Public interface: Start an ExecRequest in the background and add it to the job table
+/// The job runs in its own process group so terminal signals like Ctrl-C don't reach it
+/// Returns the job id
+pub fn spawn_background(request: &ExecRequest) -> usize {
+    let spec = command_spec(request);
+
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child }) => {
+            // Set the group from both sides so it's in place whichever process runs first
+            setpgid(child, child).ok();
+
+            // Starting a background job always succeeds
+            crate::shell::set_last_exit(0);
+
+            jobs::add_job(child, request.to_string())
+        }
+        Ok(ForkResult::Child) => exec_in_own_group(&spec),
+        Err(e) => panic!("fork failed: {}", e),
+    }
+}
+
+/// Public interface: Start an ExecRequest without waiting for it or adding it to the job table
+/// Like a background job it runs in its own process group, so terminal signals like Ctrl-C
+/// don't reach it and signalling the group reaches every stage of a pipeline. The caller must
+/// reap it with jobs::wait_process()
+pub fn spawn(request: &ExecRequest) -> Pid {
+    let spec = command_spec(request);
+
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child }) => {
+            // Set the group from both sides so it's in place whichever process runs first
+            setpgid(child, child).ok();
+            child
+        }
+        Ok(ForkResult::Child) => exec_in_own_group(&spec),
+        Err(e) => panic!("fork failed: {}", e),
+    }
+}
+
+/// Run a spec in a forked child as the leader of a new process group, exiting with its result
+fn exec_in_own_group(spec: &CommandSpec) -> ! {
+    setpgid(Pid::from_raw(0), Pid::from_raw(0)).ok();
+    match spec {
+        // Pipelines can't be a single stage, so run them and exit with their result
+        CommandSpec::Pipeline { .. } => {
+            let result = execute_command_spec(spec);
+            std::process::exit(result.exit_code() as i32);
+        }
+        _ => exec_pipeline_stage(spec),
+    }
+}
+
+/// Public interface: Start an ExecRequest detached from the shell, like `nohup cmd &`
+/// The command runs in its own session with SIGHUP ignored, so it survives the shell exiting or
+/// its terminal closing. Output meant for the terminal goes to nohup.out instead. The command
+/// is started through an intermediate child that exits at once, so it's never in the job table
+/// and init reaps it. Returns the command's PID
+pub fn spawn_detached(request: &ExecRequest) -> Pid {
+    let spec = command_spec(request);
+    let (pid_read, pid_write) = pipe().expect("Failed to create pid pipe");
+
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child }) => {
+            // Parent: the intermediate child reports the detached PID, then exits
+            drop(pid_write);
+            let mut pid_bytes = [0u8; 4];
+            std::fs::File::from(pid_read)
+                .read_exact(&mut pid_bytes)
+                .expect("Failed to start detached process");
+            wait_for_child(child);
+
+            // Starting a detached command always succeeds
+            crate::shell::set_last_exit(0);
+
+            Pid::from_raw(i32::from_ne_bytes(pid_bytes))
+        }
+        Ok(ForkResult::Child) => {
+            // Intermediate child: leave the terminal's session, then fork the command
+            drop(pid_read);
+            setsid().ok();
+            match unsafe { fork() } {
+                Ok(ForkResult::Parent { child }) => {
+                    std::fs::File::from(pid_write)
+                        .write_all(&child.as_raw().to_ne_bytes())
+                        .ok();
+                    std::process::exit(0);
+                }
+                Ok(ForkResult::Child) => {
+                    drop(pid_write);
+                    unsafe {
+                        signal(Signal::SIGHUP, SigHandler::SigIgn).ok();
+                    }
+                    reset_sigpipe();
+                    // Nothing runs in a dry run, so don't create nohup.out
+                    if get_option("dryrun") != Some(true) {
+                        redirect_nohup_stdio();
+                    }
+                    match spec {
+                        CommandSpec::Pipeline { .. } => {
+                            let result = execute_command_spec(&spec);
+                            std::process::exit(result.exit_code() as i32);
+                        }
+                        _ => exec_pipeline_stage(&spec),
+                    }
+                }
+                Err(e) => panic!("fork failed: {}", e),
+            }
+        }
+        Err(e) => panic!("fork failed: {}", e),
+    }
+}
+
+/// Point a detached command's terminal streams elsewhere, as nohup does
+/// stdin reads /dev/null, stdout appends to ./nohup.out (or ~/nohup.out if that can't be opened)
+/// and stderr follows stdout. Streams that aren't terminals are left alone
+fn redirect_nohup_stdio() {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    if std::io::stdin().is_terminal()
+        && let Ok(null) = std::fs::File::open("/dev/null")
+    {
+        unsafe {
+            libc::dup2(null.as_raw_fd(), 0);
+        }
+    }
+
+    if std::io::stdout().is_terminal() {
+        let open = |path: &str| {
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .mode(0o600)
+                .open(path)
+                .map(|file| (path.to_string(), file))
+        };
+        let home_path = std::env::var("HOME").map(|home| format!("{}/nohup.out", home));
+        let opened = open("nohup.out").or_else(|e| match &home_path {
+            Ok(home_path) => open(home_path),
+            Err(_) => Err(e),
+        });
+        match opened {
+            Ok((path, file)) => {
+                eprintln!("nohup: appending output to '{}'", path);
+                unsafe {
+                    libc::dup2(file.as_raw_fd(), 1);
+                }
+            }
+            Err(e) => {
+                eprintln!("nohup: can't open nohup.out: {}", e);
+                std::process::exit(127);
+            }
+        }
+    }
+
+    if std::io::stderr().is_terminal() {
+        unsafe {
+            libc::dup2(1, 2);
+        }
+    }
+}
+
+/// Public interface: Wait for a child started by spawn_with_stdout_pipe()
+pub fn wait_spawned(child: Pid) -> ShellResult {
+    let result = wait_for_child(child);
+
+    // Update $? with the exit code
+    crate::shell::set_last_exit(result.exit_code());
+
+    result
+}
+
 /// Internal execution: Execute a CommandSpec
 pub(crate) fn execute_command_spec(spec: &CommandSpec) -> ShellResult {
     match spec {
@@ -59,7 +366,10 @@ pub(crate) fn execute_command_spec(spec: &CommandSpec) -> ShellResult {
         CommandSpec::WithEnv {
             runnable,
             env_overlay,
-        } => execute_with_env(runnable, env_overlay),
+            inherit,
+        } => execute_with_env(runnable, env_overlay, *inherit),
+        CommandSpec::Input { runnable, data } => execute_input(runnable, data),
+        CommandSpec::InputRedirect { runnable, source } => execute_input_redirect(runnable, source),
     }
 }
 
@@ -69,9 +379,14 @@ fn fork_and_run<F>(child_fn: F) -> ShellResult
 where
     F: FnOnce() -> i32,
 {
+    let group = jobs::foreground_group(None);
     match unsafe { fork() } {
-        Ok(ForkResult::Parent { child }) => wait_for_child(child),
+        Ok(ForkResult::Parent { child }) => {
+            jobs::enter_foreground_group(child, group);
+            wait_for_child(child)
+        }
         Ok(ForkResult::Child) => {
+            jobs::enter_foreground_group(Pid::from_raw(0), group);
             let exit_code = child_fn();
             std::process::exit(exit_code);
         }
@@ -81,9 +396,16 @@ where
 
 /// Execute a single command
 fn execute_command(program: &str, args: &[String]) -> ShellResult {
+    let group = jobs::foreground_group(None);
     match unsafe { fork() } {
-        Ok(ForkResult::Parent { child }) => wait_for_child(child),
-        Ok(ForkResult::Child) => resolve_and_exec(program, args),
+        Ok(ForkResult::Parent { child }) => {
+            jobs::enter_foreground_group(child, group);
+            wait_for_child(child)
+        }
+        Ok(ForkResult::Child) => {
+            jobs::enter_foreground_group(Pid::from_raw(0), group);
+            resolve_and_exec(program, args)
+        }
         Err(e) => panic!("fork failed: {}", e),
     }
 }
@@ -101,17 +423,42 @@ pub(super) fn execute_redirect(spec: &CommandSpec, target: &types::RedirectTarge
     fork_and_run(|| {
         // Set up the output redirection
         match target {
-            types::RedirectTarget::FilePath { path, append } => {
+            types::RedirectTarget::FilePath {
+                path,
+                append,
+                clobber,
+                mode,
+            } => {
+                // noclobber refuses to truncate existing regular files unless forced
+                let noclobber = !append && !clobber && get_option("noclobber") == Some(true);
+                if noclobber && std::fs::metadata(path).is_ok_and(|m| m.is_file()) {
+                    eprintln!("{}: cannot overwrite existing file", path);
+                    return 1;
+                }
+
                 // Open the file with appropriate flags
                 use std::fs::OpenOptions;
-                let file = OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(!append)
-                    .append(*append)
-                    .open(path);
+                use std::os::unix::fs::OpenOptionsExt;
+                let mut options = OpenOptions::new();
+                options.write(true).append(*append);
+                if !noclobber {
+                    options.create(true).truncate(!append);
+                } else if std::fs::metadata(path).is_err() {
+                    // Create it exclusively (O_EXCL) so a file that appears after the check
+                    // above isn't truncated
+                    options.create_new(true);
+                }
+                // (Otherwise it's something like /dev/null, written without truncating)
+                if let Some(mode) = mode {
+                    options.mode(*mode);
+                }
+                let file = options.open(path);
 
                 match file {
+                    Err(e) if noclobber && e.kind() == std::io::ErrorKind::AlreadyExists => {
+                        eprintln!("{}: cannot overwrite existing file", path);
+                        return 1;
+                    }
                     Ok(f) => {
                         use std::os::unix::io::IntoRawFd;
                         let fd = f.into_raw_fd();
@@ -143,8 +490,78 @@ pub(super) fn execute_redirect(spec: &CommandSpec, target: &types::RedirectTarge
     })
 }
 
+/// Execute command with input redirection
+fn execute_input_redirect(spec: &CommandSpec, source: &types::RedirectSource) -> ShellResult {
+    fork_and_run(|| {
+        match source {
+            types::RedirectSource::FilePath { path } => match std::fs::File::open(path) {
+                Ok(file) => unsafe {
+                    libc::dup2(file.as_raw_fd(), 0);
+                },
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    return 1;
+                }
+            },
+            types::RedirectSource::FileDescriptor { fd } => {
+                // Read stdin from the provided file descriptor, closing the original copy
+                unsafe {
+                    libc::dup2(*fd, 0);
+                    libc::close(*fd);
+                }
+            }
+        }
+
+        let result = execute_command_spec(spec);
+        result.exit_code() as i32
+    })
+}
+
+/// Execute command with its stdin fed from in-memory data
+/// A separate writer process fills the pipe, so input larger than the pipe buffer can't
+/// deadlock against a command that writes output before it has read everything
+fn execute_input(spec: &CommandSpec, data: &[u8]) -> ShellResult {
+    fork_and_run(|| {
+        let (stdin_read, stdin_write) = pipe().expect("Failed to create stdin pipe");
+
+        let writer = match unsafe { fork() } {
+            Ok(ForkResult::Parent { child }) => child,
+            Ok(ForkResult::Child) => {
+                // A command that exits without reading everything just ends the write
+                reset_sigpipe();
+                drop(stdin_read);
+                std::fs::File::from(stdin_write).write_all(data).ok();
+                std::process::exit(0);
+            }
+            Err(e) => panic!("fork failed: {}", e),
+        };
+
+        drop(stdin_write);
+        unsafe {
+            libc::dup2(stdin_read.as_raw_fd(), 0);
+        }
+        drop(stdin_read);
+
+        let result = execute_command_spec(spec);
+
+        // Close our read end too, so a writer blocked on unread input gets SIGPIPE
+        if let Ok(null) = std::fs::File::open("/dev/null") {
+            unsafe {
+                libc::dup2(null.as_raw_fd(), 0);
+            }
+        }
+        waitpid(writer, None).ok();
+
+        result.exit_code() as i32
+    })
+}
+
 /// Execute command with environment overlay
-fn execute_with_env(spec: &CommandSpec, overlay: &HashMap<String, EnvValue>) -> ShellResult {
+fn execute_with_env(
+    spec: &CommandSpec,
+    overlay: &HashMap<String, EnvValue>,
+    inherit: bool,
+) -> ShellResult {
     // Save current environment state for variables in the overlay
     let env = get_shell_env();
     let saved_vars: HashMap<String, Option<EnvValue>> = {
@@ -155,13 +572,18 @@ fn execute_with_env(spec: &CommandSpec, overlay: &HashMap<String, EnvValue>) ->
             .collect()
     };
 
-    // Apply overlay to environment
+    // Apply overlay to environment (readonly variables can't be overlaid)
     {
         let mut env_write = env.write().unwrap();
+        if let Some(key) = overlay.keys().find(|k| env_write.is_readonly(k)) {
+            eprintln!("{}: readonly variable", key);
+            return ShellResult::ExitOnly { exit_code: 1 };
+        }
         for (key, value) in overlay {
-            env_write.set(key.clone(), value.clone());
+            env_write.set(key.clone(), value.clone()).ok();
         }
     }
+    let saved_exports = env.write().unwrap().limit_exports(overlay.keys(), inherit);
 
     // Execute wrapped command
     let result = execute_command_spec(spec);
@@ -169,11 +591,15 @@ fn execute_with_env(spec: &CommandSpec, overlay: &HashMap<String, EnvValue>) ->
     // Restore original environment
     {
         let mut env_write = env.write().unwrap();
+        env_write.restore_exports(saved_exports);
         for (key, original_value) in saved_vars {
+            // Restoring can only fail if the command made the variable readonly
             match original_value {
-                Some(value) => env_write.set(key, value),
+                Some(value) => {
+                    env_write.set(key, value).ok();
+                }
                 None => {
-                    env_write.unset(&key);
+                    env_write.unset(&key).ok();
                 }
             }
         }
@@ -182,20 +608,83 @@ fn execute_with_env(spec: &CommandSpec, overlay: &HashMap<String, EnvValue>) ->
     result
 }
 
-/// Wait for a child and convert its status to ShellResult
-pub(crate) fn wait_for_child(child: Pid) -> ShellResult {
-    match waitpid(child, None) {
-        Ok(WaitStatus::Exited(_pid, exit_code)) => ShellResult::ExitOnly {
-            exit_code: exit_code as u8,
-        },
-        Ok(WaitStatus::Signaled(_pid, signal, _core_dump)) => ShellResult::ExitOnly {
-            exit_code: 128 + (signal as i32) as u8,
-        },
-        Ok(status) => {
-            panic!("Unexpected wait status: {:?}", status);
-        }
-        Err(e) => {
-            panic!("waitpid failed: {}", e);
+/// Wait for a child to finish and return its exit code
+///
+/// If the shell itself is waiting and the child is stopped by a signal (e.g. SIGSTOP or Ctrl-Z),
+/// control returns to the shell with 128 + the signal, and the child is recorded as a stopped
+/// job. Otherwise every process in `group` (the child and any pipeline stages it's running
+/// alongside) is continued and the child is waited on again:
+///   - with `keep_running`, as captures need the child to finish before its output can be read
+///   - in a forked child shell, which only sees the stop after it was stopped and continued itself
+///   - without job control, where Ctrl-Z suspends the shell along with the command
+pub(super) fn wait_exit_code(child: Pid, group: &[Pid], keep_running: bool) -> u8 {
+    loop {
+        match jobs::wait_process(child, Some(WaitPidFlag::WUNTRACED)) {
+            Ok(WaitStatus::Exited(_pid, exit_code)) => return exit_code as u8,
+            Ok(WaitStatus::Signaled(_pid, signal, _core_dump)) => {
+                if signal == Signal::SIGINT && jobs::foreground_group(None).is_some() {
+                    forward_sigint();
+                }
+                return jobs::signal_exit_code(signal as i32);
+            }
+            Ok(WaitStatus::Stopped(_pid, signal))
+                if !keep_running
+                    && crate::shell::is_shell_process()
+                    && (jobs::job_control_enabled() || signal == Signal::SIGSTOP) =>
+            {
+                jobs::add_stopped_process(child);
+                return jobs::signal_exit_code(signal as i32);
+            }
+            Ok(WaitStatus::Stopped(..)) => {
+                for pid in group {
+                    kill(*pid, Signal::SIGCONT).ok();
+                }
+            }
+            // Only reported with WCONTINUED, but a continued child is still running either way
+            Ok(WaitStatus::Continued(_pid)) => {}
+            Ok(status) => {
+                panic!("Unexpected wait status: {:?}", status);
+            }
+            Err(e) => {
+                panic!("waitpid failed: {}", e);
+            }
         }
     }
 }
+
+/// Pass Ctrl-C on to the shell's own SIGINT handler, if it has one (e.g. while watch() runs)
+/// With job control only the foreground command's process group gets the signal from the
+/// terminal, where it used to reach the shell as well
+fn forward_sigint() {
+    let Ok(handler) = (unsafe { signal(Signal::SIGINT, SigHandler::SigDfl) }) else {
+        return;
+    };
+    unsafe { signal(Signal::SIGINT, handler) }.ok();
+    if matches!(handler, SigHandler::Handler(_) | SigHandler::SigAction(_)) {
+        nix::sys::signal::raise(Signal::SIGINT).ok();
+    }
+}
+
+/// Continue a job in the foreground and wait for it to finish or stop again, as fg does
+/// Returns the exit code of the job's last process
+pub fn resume_in_foreground(job: &jobs::Job) -> u8 {
+    if let Some(group) = job.group {
+        jobs::give_terminal(group);
+    }
+    jobs::signal_job(job, Signal::SIGCONT);
+
+    let exit_code = jobs::in_foreground(job.command.clone(), || {
+        job.processes
+            .iter()
+            .fold(0, |_, pid| wait_exit_code(*pid, &job.processes, false))
+    });
+    jobs::reclaim_terminal();
+    exit_code
+}
+
+/// Wait for a child and convert its status to ShellResult
+pub(crate) fn wait_for_child(child: Pid) -> ShellResult {
+    let exit_code = wait_exit_code(child, &[child], false);
+    jobs::reclaim_terminal();
+    ShellResult::ExitOnly { exit_code }
+}
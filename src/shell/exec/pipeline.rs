@@ -1,12 +1,22 @@
 use nix::libc;
-use nix::sys::wait::{WaitStatus, waitpid};
+use nix::sys::signal::{SigHandler, Signal, signal};
 use nix::unistd::{ForkResult, Pid, fork, pipe};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::os::fd::{AsRawFd, IntoRawFd, OwnedFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
 
 use super::resolution::resolve_and_exec;
 use super::types::{CommandSpec, ShellResult};
+use crate::shell::jobs;
+use crate::shell::options::{get_option, get_pipe_size};
 
 /// Execute a CommandSpec in a pipeline stage (doesn't return on success)
+///
+/// Builtins run right here in the stage's forked process, like any other stage, so ones that
+/// produce output (pwd, dirs, which, ...) work as pipeline sources. As in other shells, state
+/// they change (cd, set, ...) only lasts for that stage
 pub fn exec_pipeline_stage(spec: &CommandSpec) -> ! {
     match spec {
         CommandSpec::Command { program, args } => {
@@ -14,8 +24,10 @@ pub fn exec_pipeline_stage(spec: &CommandSpec) -> ! {
         }
         CommandSpec::Builtin { .. }
         | CommandSpec::Redirect { .. }
-        | CommandSpec::WithEnv { .. } => {
-            // Execute the builtin in a subshell and exit with its result
+        | CommandSpec::WithEnv { .. }
+        | CommandSpec::Input { .. }
+        | CommandSpec::InputRedirect { .. } => {
+            // Execute in this stage's process and exit with its result
             let result = super::execute_command_spec(spec);
             std::process::exit(result.exit_code() as i32);
         }
@@ -30,47 +42,99 @@ pub fn exec_pipeline_stage(spec: &CommandSpec) -> ! {
     }
 }
 
+/// Give a forked pipeline stage the default SIGPIPE disposition
+/// The shell process ignores SIGPIPE (Rust and Python both set that up), and an ignored signal
+/// survives exec - without this a producer like `yes` keeps running after its reader exits
+pub(super) fn reset_sigpipe() {
+    unsafe {
+        signal(Signal::SIGPIPE, SigHandler::SigDfl).ok();
+    }
+}
+
+/// Give a pipe between pipeline stages the buffer size set with set_pipe_size(), if any
+/// Sizes above the system limit are reduced to it, as only root may exceed it
+#[cfg(target_os = "linux")]
+fn resize_pipe(fd: &OwnedFd) {
+    use nix::fcntl::{FcntlArg, fcntl};
+
+    let Some(size) = get_pipe_size() else {
+        return;
+    };
+    let limit = std::fs::read_to_string("/proc/sys/fs/pipe-max-size")
+        .ok()
+        .and_then(|limit| limit.trim().parse::<usize>().ok())
+        .unwrap_or(1024 * 1024);
+    let size = size.min(limit).min(i32::MAX as usize) as i32;
+    fcntl(fd, FcntlArg::F_SETPIPE_SZ(size)).ok();
+}
+
+/// Pipes can't be resized here, so they keep the system's buffer size
+#[cfg(not(target_os = "linux"))]
+fn resize_pipe(_fd: &OwnedFd) {}
+
 /// Helper to execute a pipeline with optional output capture
-/// If capture_pipes is Some, the final command's stdout/stderr are captured
-/// If capture_pipes is None, the final command inherits stdout/stderr
+/// If capture_files is Some, the final command's stdout/stderr are captured into them
+/// If capture_files is None, the final command inherits stdout/stderr
 fn run_pipeline_internal(
     predecessors: &[CommandSpec],
     final_cmd: &CommandSpec,
-    capture_pipes: Option<(OwnedFd, OwnedFd, OwnedFd, OwnedFd)>, // (stdout_read, stdout_write, stderr_read, stderr_write)
+    capture_files: Option<(File, File)>, // (stdout, stderr)
 ) -> ShellResult {
-    let num_pipes = predecessors.len();
-
-    // Create all pipes
-    let mut pipes: Vec<(OwnedFd, OwnedFd)> = Vec::new();
-    for _ in 0..num_pipes {
-        let (read_fd, write_fd) = pipe().expect("Failed to create pipe");
-        pipes.push((read_fd, write_fd));
-    }
+    // A captured pipeline has to finish before its output can be read, so stopped stages are
+    // continued rather than returning control to the shell
+    let keep_running = capture_files.is_some();
 
     // Track all child PIDs
     let mut child_pids: Vec<Pid> = Vec::new();
 
+    // Read end of the pipe from the previous stage - each pipe is created just before the
+    // stage that writes to it and closed in the parent once both its stages are forked, so at
+    // most one pipe is open at a time however long the pipeline is
+    let mut prev_read: Option<OwnedFd> = None;
+
+    // With job control, an uncaptured pipeline is one process group led by its first stage
+    let stage_group = |child_pids: &[Pid]| {
+        (!keep_running)
+            .then(|| jobs::foreground_group(child_pids.first().copied()))
+            .flatten()
+    };
+
     // Fork and execute each predecessor
-    for (i, spec) in predecessors.iter().enumerate() {
+    for spec in predecessors {
+        let (read_fd, write_fd) = pipe().expect("Failed to create pipe");
+        resize_pipe(&read_fd);
+        let group = stage_group(&child_pids);
+
         match unsafe { fork() } {
             Ok(ForkResult::Parent { child }) => {
+                jobs::enter_foreground_group(child, group);
                 child_pids.push(child);
+
+                // The child has its copies; keep only the read end for the next stage
+                drop(write_fd);
+                prev_read = Some(read_fd);
             }
             Ok(ForkResult::Child) => {
+                jobs::enter_foreground_group(Pid::from_raw(0), group);
+                reset_sigpipe();
+
                 // Redirect stdin from previous pipe (if not first)
-                if i > 0 {
+                if let Some(ref prev_read) = prev_read {
                     unsafe {
-                        libc::dup2(pipes[i - 1].0.as_raw_fd(), 0);
+                        libc::dup2(prev_read.as_raw_fd(), 0);
                     }
                 }
 
                 // Redirect stdout to current pipe
                 unsafe {
-                    libc::dup2(pipes[i].1.as_raw_fd(), 1);
+                    libc::dup2(write_fd.as_raw_fd(), 1);
                 }
 
-                // Close all pipe file descriptors (they get closed when dropped anyway)
-                drop(pipes);
+                // Close the originals, and the capture files only the final stage writes to
+                drop(prev_read);
+                drop(read_fd);
+                drop(write_fd);
+                drop(capture_files);
 
                 // Execute the command or subshell
                 exec_pipeline_stage(spec);
@@ -81,114 +145,90 @@ fn run_pipeline_internal(
         }
     }
 
+    // Exit codes of the predecessors, in pipeline order (for pipefail)
+    let mut predecessor_codes: Vec<u8> = Vec::new();
+
     // Check if final command is a builtin - if so, execute in parent for efficiency
-    if let CommandSpec::Builtin { func, args, .. } = final_cmd {
-        // Save original stdin, and possibly stdout/stderr if capturing
+    let result = if let CommandSpec::Builtin { func, args, .. } = final_cmd {
+        // Save original stdin
         let saved_stdin = unsafe { libc::dup(0) };
-        let (saved_stdout, saved_stderr) = if capture_pipes.is_some() {
-            let out = unsafe { libc::dup(1) };
-            let err = unsafe { libc::dup(2) };
-            (Some(out), Some(err))
-        } else {
-            (None, None)
-        };
-
-        if saved_stdin == -1
-            || (saved_stdout.is_some() && saved_stdout.unwrap() == -1)
-            || (saved_stderr.is_some() && saved_stderr.unwrap() == -1)
-        {
-            panic!("Failed to save stdin/stdout/stderr");
+        if saved_stdin == -1 {
+            panic!("Failed to save stdin");
         }
 
-        // Redirect stdin from last pipe (if any)
-        if num_pipes > 0 {
+        // Redirect stdin from last pipe (if any), keeping no other copy of its read end
+        if let Some(prev_read) = prev_read.take() {
             unsafe {
-                libc::dup2(pipes[num_pipes - 1].0.as_raw_fd(), 0);
+                libc::dup2(prev_read.as_raw_fd(), 0);
             }
         }
 
-        // If capturing, redirect stdout/stderr to capture pipes
-        let capture_fds =
-            if let Some((stdout_read, stdout_write, stderr_read, stderr_write)) = capture_pipes {
-                unsafe {
-                    libc::dup2(stdout_write.as_raw_fd(), 1);
-                    libc::dup2(stderr_write.as_raw_fd(), 2);
-                }
-                drop(stdout_write);
-                drop(stderr_write);
-                Some((stdout_read, stderr_read))
-            } else {
-                None
-            };
-
-        // Close all pipe file descriptors
-        drop(pipes);
-
-        // Wait for all predecessor children before executing
-        for child_pid in child_pids {
-            waitpid(child_pid, None).ok();
-        }
+        // Execute builtin directly in parent (no fork), before waiting on the predecessors:
+        // they can't finish until what they write is read, or the builtin is done with stdin
+        let exit_code = match &capture_files {
+            Some((stdout, stderr)) => {
+                super::capture::with_output_redirected(stdout, stderr, || func(args))
+            }
+            None => {
+                let exit_code = func(args);
 
-        // Execute builtin directly in parent (no fork)
-        let exit_code = func(args);
+                // Output still buffered would otherwise land wherever stdout points next
+                std::io::stdout().flush().ok();
+                exit_code
+            }
+        };
 
-        // Restore original stdin and possibly stdout/stderr
+        // Restore original stdin, closing the last pipe - a predecessor still writing to it
+        // gets SIGPIPE rather than blocking on a reader that's gone
         unsafe {
             libc::dup2(saved_stdin, 0);
             libc::close(saved_stdin);
-            if let Some(out) = saved_stdout {
-                libc::dup2(out, 1);
-                libc::close(out);
-            }
-            if let Some(err) = saved_stderr {
-                libc::dup2(err, 2);
-                libc::close(err);
-            }
+        }
+
+        // Wait for all predecessor children
+        for child_pid in &child_pids {
+            predecessor_codes.push(super::wait_exit_code(*child_pid, &child_pids, keep_running));
         }
 
         // Return appropriate result variant
-        if let Some((stdout_read, stderr_read)) = capture_fds {
-            ShellResult::Captured {
-                exit_code: exit_code as u8,
-                stdout_fd: stdout_read.into_raw_fd(),
-                stderr_fd: stderr_read.into_raw_fd(),
+        match capture_files {
+            Some((stdout, stderr)) => {
+                super::capture::captured_files(exit_code as u8, stdout, stderr)
             }
-        } else {
-            ShellResult::ExitOnly {
+            None => ShellResult::ExitOnly {
                 exit_code: exit_code as u8,
-            }
+            },
         }
     } else {
         // Fork and execute the last command (regular commands)
-        let capture_fds =
-            if let Some((stdout_read, stdout_write, stderr_read, stderr_write)) = capture_pipes {
-                // We're capturing - set up in child
-                Some((stdout_read, stdout_write, stderr_read, stderr_write))
-            } else {
-                None
-            };
-
+        let group = stage_group(&child_pids);
         let last_child = match unsafe { fork() } {
-            Ok(ForkResult::Parent { child }) => child,
+            Ok(ForkResult::Parent { child }) => {
+                jobs::enter_foreground_group(child, group);
+                child
+            }
             Ok(ForkResult::Child) => {
+                jobs::enter_foreground_group(Pid::from_raw(0), group);
+                reset_sigpipe();
+
                 // Redirect stdin from last pipe
-                if num_pipes > 0 {
+                if let Some(ref prev_read) = prev_read {
                     unsafe {
-                        libc::dup2(pipes[num_pipes - 1].0.as_raw_fd(), 0);
+                        libc::dup2(prev_read.as_raw_fd(), 0);
                     }
                 }
 
-                // If capturing, redirect stdout/stderr to capture pipes
-                if let Some((_, ref stdout_write, _, ref stderr_write)) = capture_fds {
+                // If capturing, redirect stdout/stderr to the capture files
+                if let Some((ref stdout, ref stderr)) = capture_files {
                     unsafe {
-                        libc::dup2(stdout_write.as_raw_fd(), 1);
-                        libc::dup2(stderr_write.as_raw_fd(), 2);
+                        libc::dup2(stdout.as_raw_fd(), 1);
+                        libc::dup2(stderr.as_raw_fd(), 2);
                     }
                 }
 
-                // Close all pipe file descriptors
-                drop(pipes);
-                drop(capture_fds);
+                // Close the last pipe and the capture files
+                drop(prev_read);
+                drop(capture_files);
 
                 // Execute the final command or subshell
                 exec_pipeline_stage(final_cmd);
@@ -198,55 +238,49 @@ fn run_pipeline_internal(
             }
         };
 
-        // Parent: close all pipe file descriptors and write ends of capture pipes
-        drop(pipes);
-        let leaked_fds =
-            if let Some((stdout_read, stdout_write, stderr_read, stderr_write)) = capture_fds {
-                drop(stdout_write);
-                drop(stderr_write);
-                Some((stdout_read, stderr_read))
-            } else {
-                None
-            };
+        // Parent: close the last pipe
+        drop(prev_read);
 
         // Wait for all predecessor children
-        for child_pid in child_pids {
-            waitpid(child_pid, None).ok();
+        child_pids.push(last_child);
+        for child_pid in &child_pids[..child_pids.len() - 1] {
+            predecessor_codes.push(super::wait_exit_code(*child_pid, &child_pids, keep_running));
         }
 
         // Wait for the last child and return result
-        if let Some((stdout_read, stderr_read)) = leaked_fds {
-            // Capturing - wait and return Captured variant
-            let stdout_fd = stdout_read.into_raw_fd();
-            let stderr_fd = stderr_read.into_raw_fd();
-
-            match waitpid(last_child, None) {
-                Ok(WaitStatus::Exited(_pid, exit_code)) => ShellResult::Captured {
-                    exit_code: exit_code as u8,
-                    stdout_fd,
-                    stderr_fd,
-                },
-                Ok(WaitStatus::Signaled(_pid, signal, _core_dump)) => ShellResult::Captured {
-                    exit_code: 128 + (signal as i32) as u8,
-                    stdout_fd,
-                    stderr_fd,
-                },
-                Ok(status) => {
-                    panic!("Unexpected wait status: {:?}", status);
-                }
-                Err(e) => {
-                    panic!("waitpid failed: {}", e);
-                }
-            }
-        } else {
-            // Not capturing - use normal wait_for_child
-            super::wait_for_child(last_child)
+        let exit_code = super::wait_exit_code(last_child, &child_pids, keep_running);
+        match capture_files {
+            Some((stdout, stderr)) => super::capture::captured_files(exit_code, stdout, stderr),
+            None => ShellResult::ExitOnly { exit_code },
         }
+    };
+
+    jobs::reclaim_terminal();
+
+    let exit_code = pipeline_exit_code(result.exit_code(), &predecessor_codes);
+    result.with_exit_code(exit_code)
+}
+
+/// Compute a pipeline's exit code from its final stage and predecessors
+/// With pipefail, the rightmost failing stage determines the pipeline's exit code
+pub(super) fn pipeline_exit_code(final_code: u8, predecessor_codes: &[u8]) -> u8 {
+    if final_code == 0
+        && get_option("pipefail") == Some(true)
+        && let Some(code) = predecessor_codes.iter().rev().find(|code| **code != 0)
+    {
+        return *code;
     }
+
+    final_code
 }
 
 /// Execute a pipeline: predecessors → last (normal execution, no capture)
 pub fn run_pipeline(predecessors: &[CommandSpec], final_cmd: &CommandSpec) -> ShellResult {
+    // A single-stage pipeline is just the command itself
+    if predecessors.is_empty() {
+        return super::execute_command_spec(final_cmd);
+    }
+
     run_pipeline_internal(predecessors, final_cmd, None)
 }
 
@@ -255,13 +289,182 @@ pub(super) fn run_pipeline_captured(
     predecessors: &[CommandSpec],
     final_cmd: &CommandSpec,
 ) -> ShellResult {
-    // Create capture pipes
-    let (stdout_read, stdout_write) = pipe().expect("Failed to create stdout pipe");
-    let (stderr_read, stderr_write) = pipe().expect("Failed to create stderr pipe");
+    // A single-stage pipeline is just the command itself
+    if predecessors.is_empty() {
+        return super::capture::execute_command_spec_with_capture(final_cmd);
+    }
 
+    // Capture into files rather than pipes, since nothing reads the output until every stage
+    // has finished
     run_pipeline_internal(
         predecessors,
         final_cmd,
-        Some((stdout_read, stdout_write, stderr_read, stderr_write)),
+        Some((capture_file(), capture_file())),
     )
 }
+
+/// Create an unlinked temporary file to hold captured output
+/// Unlike a pipe, a file buffers any amount of output without a reader draining it
+pub(super) fn capture_file() -> File {
+    static CAPTURE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "shp-capture-{}-{}",
+        std::process::id(),
+        CAPTURE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .expect("Failed to create capture file");
+
+    // The open handle keeps the contents alive after the name is gone
+    std::fs::remove_file(&path).ok();
+    file
+}
+
+/// Copy a stage's stdout into its capture file and on to the next stage
+fn tee_stage_output(source: OwnedFd, mut capture: File, next_stage: OwnedFd) {
+    let mut source = File::from(source);
+    let mut next_stage = Some(File::from(next_stage));
+    let mut buf = [0u8; 8192];
+
+    loop {
+        let n = match source.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        capture.write_all(&buf[..n]).ok();
+
+        // Once the next stage stops reading (e.g. head), keep capturing but stop forwarding
+        if let Some(next) = next_stage.as_mut()
+            && next.write_all(&buf[..n]).is_err()
+        {
+            next_stage = None;
+        }
+    }
+}
+
+/// Execute pipeline stages capturing every stage's stdout and stderr
+/// Returns one Captured result per stage, in pipeline order
+///
+/// Each predecessor's stdout goes through a tee thread in the parent, which drains it
+/// concurrently into the stage's capture file and the next stage's stdin
+pub(super) fn run_pipeline_capture_all(stages: &[CommandSpec]) -> Vec<ShellResult> {
+    let num_pipes = stages.len().saturating_sub(1);
+
+    // Stage i writes to tee_pipes[i]; its tee thread feeds stage i + 1 through stdin_pipes[i]
+    let mut tee_pipes: Vec<(OwnedFd, OwnedFd)> = Vec::new();
+    let mut stdin_pipes: Vec<(OwnedFd, OwnedFd)> = Vec::new();
+    for _ in 0..num_pipes {
+        tee_pipes.push(pipe().expect("Failed to create pipe"));
+        stdin_pipes.push(pipe().expect("Failed to create pipe"));
+    }
+    let captures: Vec<(File, File)> = stages
+        .iter()
+        .map(|_| (capture_file(), capture_file()))
+        .collect();
+
+    // Fork every stage before starting the tee threads, so no child inherits a pipe end
+    // that a thread would otherwise be holding open
+    let mut child_pids: Vec<Pid> = Vec::new();
+    for (i, spec) in stages.iter().enumerate() {
+        match unsafe { fork() } {
+            Ok(ForkResult::Parent { child }) => {
+                child_pids.push(child);
+            }
+            Ok(ForkResult::Child) => {
+                reset_sigpipe();
+                unsafe {
+                    if i > 0 {
+                        libc::dup2(stdin_pipes[i - 1].0.as_raw_fd(), 0);
+                    }
+                    if i < num_pipes {
+                        libc::dup2(tee_pipes[i].1.as_raw_fd(), 1);
+                    } else {
+                        libc::dup2(captures[i].0.as_raw_fd(), 1);
+                    }
+                    libc::dup2(captures[i].1.as_raw_fd(), 2);
+                }
+
+                // Close all pipe and capture file descriptors
+                drop(tee_pipes);
+                drop(stdin_pipes);
+                drop(captures);
+
+                exec_pipeline_stage(spec);
+            }
+            Err(e) => {
+                panic!("fork failed: {}", e);
+            }
+        }
+    }
+
+    // Parent: keep only the ends the tee threads need
+    let tee_threads: Vec<_> = tee_pipes
+        .into_iter()
+        .zip(stdin_pipes)
+        .zip(&captures)
+        .map(
+            |(((tee_read, tee_write), (stdin_read, stdin_write)), (stdout_capture, _))| {
+                drop(tee_write);
+                drop(stdin_read);
+                let capture = stdout_capture
+                    .try_clone()
+                    .expect("Failed to duplicate capture file");
+                thread::spawn(move || tee_stage_output(tee_read, capture, stdin_write))
+            },
+        )
+        .collect();
+
+    // The tee threads keep draining while we wait, so no stage can block on a full pipe
+    let exit_codes: Vec<u8> = child_pids
+        .iter()
+        .map(|pid| super::wait_exit_code(*pid, &child_pids, true))
+        .collect();
+    for handle in tee_threads {
+        handle.join().ok();
+    }
+
+    // Rewind the capture files and leak them as the result FDs
+    captures
+        .into_iter()
+        .zip(exit_codes)
+        .map(|((mut stdout_capture, mut stderr_capture), exit_code)| {
+            stdout_capture.seek(SeekFrom::Start(0)).ok();
+            stderr_capture.seek(SeekFrom::Start(0)).ok();
+            ShellResult::Captured {
+                exit_code,
+                stdout_fd: stdout_capture.into_raw_fd(),
+                stderr_fd: stderr_capture.into_raw_fd(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::options::set_option;
+
+    #[test]
+    fn pipefail_selects_rightmost_failing_stage() {
+        // One test toggles the option both ways, so tests running in parallel can't race on it
+        set_option("pipefail", false);
+        assert_eq!(pipeline_exit_code(0, &[1, 2]), 0);
+        assert_eq!(pipeline_exit_code(3, &[1, 0]), 3);
+
+        set_option("pipefail", true);
+        assert_eq!(pipeline_exit_code(0, &[0, 0]), 0);
+        assert_eq!(pipeline_exit_code(0, &[1, 2, 0]), 2);
+        assert_eq!(pipeline_exit_code(0, &[141, 0]), 141);
+        assert_eq!(pipeline_exit_code(4, &[1, 2]), 4);
+
+        set_option("pipefail", false);
+        assert_eq!(pipeline_exit_code(0, &[1, 2]), 0);
+    }
+}
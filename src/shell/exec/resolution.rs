@@ -1,17 +1,128 @@
 use nix::unistd::execve;
+use std::collections::HashMap;
 use std::ffi::CString;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
 
 use super::super::env::{EnvValue, get_shell_env, get_var};
+use super::super::options::get_option;
 use super::types::ProgramResolutionError;
 
+/// Programs found by searching PATH, so repeated commands skip the search (see `hashall`)
+/// Entries are only used with the PATH they were found with - changing PATH clears them, and a
+/// with_env() PATH (which doesn't notify env observers) simply doesn't match
+struct ResolutionCache {
+    search_path: Vec<String>,
+    programs: HashMap<String, PathBuf>,
+}
+
+static RESOLUTION_CACHE: OnceLock<RwLock<ResolutionCache>> = OnceLock::new();
+
+fn get_resolution_cache() -> &'static RwLock<ResolutionCache> {
+    RESOLUTION_CACHE.get_or_init(|| {
+        RwLock::new(ResolutionCache {
+            search_path: Vec::new(),
+            programs: HashMap::new(),
+        })
+    })
+}
+
+/// Forget every cached program location (after PATH changes, or for the rehash builtin)
+pub fn clear_resolution_cache() {
+    get_resolution_cache().write().unwrap().programs.clear();
+}
+
+/// Resolve a program in the shell process so its location is cached
+/// Commands are resolved again in the forked child, which can't update the shell's cache itself
+pub fn hash_program(program: &str) {
+    if !program.contains('/') {
+        resolve_program_path(program).ok();
+    }
+}
+
+/// Search PATH afresh for a program and cache where it was found, for the hash builtin
+pub fn rehash_program(program: &str) -> Result<PathBuf, ProgramResolutionError> {
+    get_resolution_cache()
+        .write()
+        .unwrap()
+        .programs
+        .remove(program);
+    resolve_program_path(program)
+}
+
+/// Every cached program and where it was found, sorted by name
+pub fn cached_programs() -> Vec<(String, PathBuf)> {
+    let cache = get_resolution_cache().read().unwrap();
+    let mut programs: Vec<_> = cache
+        .programs
+        .iter()
+        .map(|(name, path)| (name.clone(), path.clone()))
+        .collect();
+    programs.sort();
+    programs
+}
+
+/// Look up a program found by an earlier search of the same PATH
+/// A program that has since been removed (or lost its execute bit) is searched for again
+fn cached_program_path(program: &str, path_dirs: &[String]) -> Option<PathBuf> {
+    if get_option("hashall") != Some(true) {
+        return None;
+    }
+
+    let cache = get_resolution_cache().read().unwrap();
+    if cache.search_path != path_dirs {
+        return None;
+    }
+    cache
+        .programs
+        .get(program)
+        .filter(|path| is_executable_file(path))
+        .cloned()
+}
+
+/// Remember where a PATH search found a program
+fn cache_program_path(program: &str, path_dirs: &[String], path: &Path) {
+    if get_option("hashall") != Some(true) {
+        return;
+    }
+
+    let mut cache = get_resolution_cache().write().unwrap();
+    if cache.search_path != path_dirs {
+        cache.search_path = path_dirs.to_vec();
+        cache.programs.clear();
+    }
+    cache
+        .programs
+        .insert(program.to_string(), path.to_path_buf());
+}
+
+fn is_executable_file(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// Report a command that couldn't be run
+/// The message goes to stderr, so a capture gets it in its stderr; with `ttyerrors` set it's
+/// also written to the terminal when stderr is somewhere else, so it isn't missed interactively
+fn report_exec_error(message: &str) {
+    use std::io::{IsTerminal, Write};
+
+    eprintln!("{}", message);
+    if get_option("ttyerrors") == Some(true)
+        && !std::io::stderr().is_terminal()
+        && let Ok(mut tty) = std::fs::OpenOptions::new().write(true).open("/dev/tty")
+    {
+        writeln!(tty, "{}", message).ok();
+    }
+}
+
 /// Resolve program path and execute with arguments (never returns on success)
 pub fn resolve_and_exec(program: &str, args: &[String]) -> ! {
     // Resolve the program path using POSIX rules
     let prog_path = match resolve_program_path(program) {
         Ok(path) => path,
         Err(error) => {
-            eprintln!("{}", error.message());
+            report_exec_error(error.message());
             std::process::exit(error.exit_code());
         }
     };
@@ -33,7 +144,11 @@ pub fn resolve_and_exec(program: &str, args: &[String]) -> ! {
 
     // Execute with environment
     let err = execve(&prog_cstr, &argv, &envp);
-    eprintln!("Failed to execute {}: {}", program, err.unwrap_err());
+    report_exec_error(&format!(
+        "Failed to execute {}: {}",
+        program,
+        err.unwrap_err()
+    ));
     std::process::exit(127);
 }
 
@@ -62,8 +177,17 @@ fn resolve_program_path(program: &str) -> Result<PathBuf, ProgramResolutionError
             use std::os::unix::fs::PermissionsExt;
             match std::fs::metadata(&path) {
                 Ok(metadata) => {
+                    // Directories have execute bits for traversal, but can't be run
+                    if metadata.is_dir() {
+                        return Err(ProgramResolutionError::PermissionDenied(format!(
+                            "{}: Is a directory",
+                            program
+                        )));
+                    }
+
+                    // Neither can sockets, FIFOs or devices, whatever their mode
                     let permissions = metadata.permissions();
-                    if permissions.mode() & 0o111 == 0 {
+                    if !metadata.is_file() || permissions.mode() & 0o111 == 0 {
                         return Err(ProgramResolutionError::PermissionDenied(format!(
                             "{}: Permission denied",
                             program
@@ -125,6 +249,11 @@ fn resolve_program_path(program: &str) -> Result<PathBuf, ProgramResolutionError
         }
     };
 
+    // Reuse an earlier search of the same PATH
+    if let Some(path) = cached_program_path(program, &path_dirs) {
+        return Ok(path);
+    }
+
     // Search each directory in PATH
     for dir in &path_dirs {
         if dir.is_empty() {
@@ -133,14 +262,15 @@ fn resolve_program_path(program: &str) -> Result<PathBuf, ProgramResolutionError
 
         let candidate = PathBuf::from(dir).join(program);
 
-        // Check if file exists and is executable
+        // Check if it's an executable regular file (directories are skipped)
         if candidate.exists() {
             #[cfg(unix)]
             {
                 use std::os::unix::fs::PermissionsExt;
                 if let Ok(metadata) = std::fs::metadata(&candidate) {
                     let permissions = metadata.permissions();
-                    if permissions.mode() & 0o111 != 0 {
+                    if metadata.is_file() && permissions.mode() & 0o111 != 0 {
+                        cache_program_path(program, &path_dirs, &candidate);
                         return Ok(candidate);
                     }
                 }
@@ -1,5 +1,7 @@
 use super::super::builtins::get_builtin;
 use super::super::env::EnvValue;
+use super::super::quote::{shell_join, shell_quote};
+use super::resolution::hash_program;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
@@ -22,6 +24,24 @@ impl ShellResult {
             ShellResult::Captured { exit_code, .. } => *exit_code,
         }
     }
+
+    /// Replace the exit code regardless of variant
+    pub fn with_exit_code(self, new_code: u8) -> Self {
+        match self {
+            ShellResult::ExitOnly { .. } => ShellResult::ExitOnly {
+                exit_code: new_code,
+            },
+            ShellResult::Captured {
+                stdout_fd,
+                stderr_fd,
+                ..
+            } => ShellResult::Captured {
+                exit_code: new_code,
+                stdout_fd,
+                stderr_fd,
+            },
+        }
+    }
 }
 
 /// Public interface for executing commands from Python bindings
@@ -45,9 +65,103 @@ pub enum ExecRequest {
     WithEnv {
         request: Box<ExecRequest>,
         env_overlay: HashMap<String, EnvValue>,
+        /// Whether the command also gets the rest of the shell's variables, not just the
+        /// essential ones (PATH, HOME, PWD)
+        inherit: bool,
+    },
+    Input {
+        request: Box<ExecRequest>,
+        data: Vec<u8>,
+    },
+    InputRedirect {
+        request: Box<ExecRequest>,
+        source: RedirectSource,
     },
 }
 
+impl ExecRequest {
+    /// Write this request as an operand of a redirect or overlay
+    /// Pipelines are parenthesized since the operator applies to the whole pipeline
+    fn fmt_operand(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecRequest::Pipeline { .. } => write!(f, "({})", self),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+/// Render a request as an equivalent shell command line (for display and logging)
+impl std::fmt::Display for ExecRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecRequest::Program { name, args } => {
+                write!(f, "{}", shell_quote(name))?;
+                if !args.is_empty() {
+                    write!(f, " {}", shell_join(args))?;
+                }
+                Ok(())
+            }
+            ExecRequest::Pipeline { stages } => {
+                for (i, stage) in stages.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", stage)?;
+                }
+                Ok(())
+            }
+            ExecRequest::Subshell { request } => write!(f, "({})", request),
+            ExecRequest::Redirect { request, target } => {
+                request.fmt_operand(f)?;
+                match target {
+                    RedirectTarget::FilePath {
+                        path,
+                        append,
+                        clobber,
+                        ..
+                    } => {
+                        let operator = if *append {
+                            ">>"
+                        } else if *clobber {
+                            ">|"
+                        } else {
+                            ">"
+                        };
+                        write!(f, " {} {}", operator, shell_quote(path))
+                    }
+                    RedirectTarget::FileDescriptor { fd } => write!(f, " >&{}", fd),
+                }
+            }
+            ExecRequest::WithEnv {
+                request,
+                env_overlay,
+                ..
+            } => {
+                // Sort for stable output since the overlay is a HashMap
+                let mut keys: Vec<&String> = env_overlay.keys().collect();
+                keys.sort();
+                for key in keys {
+                    let value = env_overlay[key].to_string_repr();
+                    write!(f, "{}={} ", key, shell_quote(&value))?;
+                }
+                request.fmt_operand(f)
+            }
+            ExecRequest::Input { request, data } => {
+                let data = String::from_utf8_lossy(data);
+                write!(f, "printf %s {} | ", shell_quote(&data))?;
+                request.fmt_operand(f)
+            }
+            ExecRequest::InputRedirect { request, source } => {
+                request.fmt_operand(f)?;
+                match source {
+                    RedirectSource::FilePath { path } => write!(f, " < {}", shell_quote(path)),
+                    RedirectSource::FileDescriptor { fd } => write!(f, " <&{}", fd),
+                }
+            }
+        }
+    }
+}
+
 /// Represents errors that can occur during program path resolution
 #[derive(Debug)]
 pub enum ProgramResolutionError {
@@ -85,7 +199,22 @@ impl ProgramResolutionError {
 
 #[derive(Debug, Clone)]
 pub enum RedirectTarget {
-    FilePath { path: String, append: bool },
+    FilePath {
+        path: String,
+        append: bool,
+        clobber: bool,
+        /// Permission bits for a newly created file (None uses the default 0666 & ~umask)
+        mode: Option<u32>,
+    },
+    FileDescriptor {
+        fd: i32,
+    },
+}
+
+/// Where an input redirect reads stdin from
+#[derive(Debug, Clone)]
+pub enum RedirectSource {
+    FilePath { path: String },
     FileDescriptor { fd: i32 },
 }
 
@@ -114,6 +243,15 @@ pub enum CommandSpec {
     WithEnv {
         runnable: Box<CommandSpec>,
         env_overlay: HashMap<String, EnvValue>,
+        inherit: bool,
+    },
+    Input {
+        runnable: Box<CommandSpec>,
+        data: Vec<u8>,
+    },
+    InputRedirect {
+        runnable: Box<CommandSpec>,
+        source: RedirectSource,
     },
 }
 
@@ -151,10 +289,22 @@ impl std::fmt::Debug for CommandSpec {
             CommandSpec::WithEnv {
                 runnable,
                 env_overlay,
+                inherit,
             } => f
                 .debug_struct("WithEnv")
                 .field("runnable", runnable)
                 .field("env_overlay", env_overlay)
+                .field("inherit", inherit)
+                .finish(),
+            CommandSpec::Input { runnable, data } => f
+                .debug_struct("Input")
+                .field("runnable", runnable)
+                .field("data", &String::from_utf8_lossy(data))
+                .finish(),
+            CommandSpec::InputRedirect { runnable, source } => f
+                .debug_struct("InputRedirect")
+                .field("runnable", runnable)
+                .field("source", source)
                 .finish(),
         }
     }
@@ -173,6 +323,8 @@ impl From<&ExecRequest> for CommandSpec {
                         args: args.clone(),
                     }
                 } else {
+                    // Resolve now, in the shell process, so later runs can use the cached path
+                    hash_program(name);
                     CommandSpec::Command {
                         program: name.clone(),
                         args: args.clone(),
@@ -215,9 +367,19 @@ impl From<&ExecRequest> for CommandSpec {
             ExecRequest::WithEnv {
                 request,
                 env_overlay,
+                inherit,
             } => CommandSpec::WithEnv {
                 runnable: Box::new(CommandSpec::from(request.as_ref())),
                 env_overlay: env_overlay.clone(),
+                inherit: *inherit,
+            },
+            ExecRequest::Input { request, data } => CommandSpec::Input {
+                runnable: Box::new(CommandSpec::from(request.as_ref())),
+                data: data.clone(),
+            },
+            ExecRequest::InputRedirect { request, source } => CommandSpec::InputRedirect {
+                runnable: Box::new(CommandSpec::from(request.as_ref())),
+                source: source.clone(),
             },
         }
     }
@@ -0,0 +1,457 @@
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::signal::{
+    SaFlags, SigAction, SigHandler, SigSet, Signal, kill, killpg, sigaction, signal,
+};
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::{Pid, getpgid, getpgrp, getpid, setpgid, tcgetpgrp, tcsetpgrp};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Once, OnceLock, RwLock};
+
+/// State of a background job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    /// Stopped by a signal (e.g. SIGSTOP) until it's continued
+    Stopped,
+    /// Finished with the given exit code
+    Done(u8),
+}
+
+impl JobState {
+    /// Human-readable state, as shown by the jobs builtin
+    pub fn describe(&self) -> String {
+        match self {
+            JobState::Running => "Running".to_string(),
+            JobState::Stopped => "Stopped".to_string(),
+            JobState::Done(0) => "Done".to_string(),
+            JobState::Done(code) => format!("Exit {}", code),
+        }
+    }
+
+    /// Check if the job has finished
+    pub fn is_done(&self) -> bool {
+        matches!(self, JobState::Done(_))
+    }
+}
+
+/// A command running in the background, or a foreground command that was stopped
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: usize,
+    /// The job's first process
+    pub pid: Pid,
+    pub command: String,
+    pub state: JobState,
+    /// Processes that haven't finished yet, in pipeline order
+    /// A background job is a single process leading its own process group, while a stopped
+    /// foreground pipeline has one per stage
+    pub processes: Vec<Pid>,
+    /// The job's process group, unless it's in the shell's (a command stopped without job control)
+    pub group: Option<Pid>,
+    /// The process whose exit code is the job's (a pipeline's final stage)
+    last_process: Pid,
+}
+
+/// Global job table (kept in id order)
+static JOB_TABLE: OnceLock<RwLock<Vec<Job>>> = OnceLock::new();
+
+fn get_job_table() -> &'static RwLock<Vec<Job>> {
+    JOB_TABLE.get_or_init(|| RwLock::new(Vec::new()))
+}
+
+/// PIDs of disowned jobs that may still be running
+/// They're no longer reported, but are still reaped quietly so they don't linger as zombies
+static DISOWNED: OnceLock<RwLock<Vec<Pid>>> = OnceLock::new();
+
+fn get_disowned() -> &'static RwLock<Vec<Pid>> {
+    DISOWNED.get_or_init(|| RwLock::new(Vec::new()))
+}
+
+/// Whether the shell suspends foreground commands on Ctrl-Z (only the interactive shell does)
+static JOB_CONTROL: AtomicBool = AtomicBool::new(false);
+
+/// Check if job control is enabled
+pub fn job_control_enabled() -> bool {
+    JOB_CONTROL.load(Ordering::SeqCst)
+}
+
+/// SIGTSTP handler for the shell itself, which mustn't be suspended along with the command it's
+/// waiting on - catching the signal (rather than ignoring it) means exec restores the default
+/// action, so the command still stops
+extern "C" fn handle_sigtstp(_signal: libc::c_int) {}
+
+/// Restore SIGTSTP's default action in every forked child, so a child shell (e.g. one running a
+/// redirect or subshell) is suspended along with the commands it runs
+extern "C" fn reset_sigtstp_in_child() {
+    unsafe {
+        libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+    }
+}
+
+/// Enable job control: Ctrl-Z suspends the foreground command and returns to the shell, which
+/// records it as a stopped job
+pub fn enable_job_control() {
+    static ENABLE: Once = Once::new();
+    ENABLE.call_once(|| {
+        // SA_RESTART keeps the waitpid() for the running command from failing with EINTR
+        let action = SigAction::new(
+            SigHandler::Handler(handle_sigtstp),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+        unsafe {
+            sigaction(Signal::SIGTSTP, &action).ok();
+            libc::pthread_atfork(None, None, Some(reset_sigtstp_in_child));
+        }
+        JOB_CONTROL.store(true, Ordering::SeqCst);
+    });
+}
+
+/// Process group for a foreground process about to be forked, or None to stay in the shell's
+///
+/// With job control, each foreground command leads a new process group (which the rest of its
+/// pipeline joins, given the `leader`) and is handed the terminal while it runs, so Ctrl-Z
+/// stops the command but not the shell. Returns 0 for a new group
+pub fn foreground_group(leader: Option<Pid>) -> Option<Pid> {
+    (job_control_enabled() && crate::shell::is_shell_process())
+        .then(|| leader.unwrap_or(Pid::from_raw(0)))
+}
+
+/// Move a just-forked process (`pid`, or 0 for the calling process) into its foreground group
+/// Both the parent and the child call this, so the group is in place whichever runs first
+pub fn enter_foreground_group(pid: Pid, group: Option<Pid>) {
+    let Some(group) = group else {
+        return;
+    };
+    setpgid(pid, group).ok();
+    if group.as_raw() == 0 {
+        give_terminal(if pid.as_raw() == 0 { getpid() } else { pid });
+    }
+}
+
+/// Make a process group the terminal's foreground group, which reads from it and gets the
+/// signals its keys send (e.g. Ctrl-C and Ctrl-Z)
+pub fn give_terminal(group: Pid) {
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        return;
+    }
+    // Changing the foreground group from outside it sends SIGTTOU unless that's ignored
+    let previous = unsafe { signal(Signal::SIGTTOU, SigHandler::SigIgn) };
+    tcsetpgrp(&stdin, group).ok();
+    if let Ok(previous) = previous {
+        unsafe { signal(Signal::SIGTTOU, previous) }.ok();
+    }
+}
+
+/// Take the terminal back from a foreground command once it has finished or stopped
+pub fn reclaim_terminal() {
+    if foreground_group(None).is_none() {
+        return;
+    }
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() && tcgetpgrp(&stdin).is_ok_and(|group| group != getpgrp()) {
+        give_terminal(getpgrp());
+    }
+}
+
+/// The foreground command being run, so its processes that stop can be named and grouped
+#[derive(Default)]
+struct Foreground {
+    command: Option<String>,
+    /// The job its stopped processes were put in, once one has stopped
+    job: Option<usize>,
+}
+
+static FOREGROUND: OnceLock<RwLock<Foreground>> = OnceLock::new();
+
+fn get_foreground() -> &'static RwLock<Foreground> {
+    FOREGROUND.get_or_init(|| RwLock::new(Foreground::default()))
+}
+
+/// Add a job to the table and return its id
+/// Like other shells, a job gets the lowest id not currently in use
+fn insert_job(
+    jobs: &mut Vec<Job>,
+    pid: Pid,
+    command: String,
+    state: JobState,
+    group: Option<Pid>,
+) -> usize {
+    let id = (1..)
+        .find(|id| jobs.iter().all(|job| job.id != *id))
+        .unwrap();
+    let pos = jobs
+        .iter()
+        .position(|job| job.id > id)
+        .unwrap_or(jobs.len());
+    jobs.insert(
+        pos,
+        Job {
+            id,
+            pid,
+            command,
+            state,
+            processes: vec![pid],
+            group,
+            last_process: pid,
+        },
+    );
+    id
+}
+
+/// Add a background job (a process leading its own process group) and return its id
+pub fn add_job(pid: Pid, command: String) -> usize {
+    let mut jobs = get_job_table().write().unwrap();
+    insert_job(&mut jobs, pid, command, JobState::Running, Some(pid))
+}
+
+/// Run `f` as the foreground command `command`
+/// Any of its processes stopped while the shell waits on them become one job named after it,
+/// which is announced once `f` returns
+pub fn in_foreground<T>(command: String, f: impl FnOnce() -> T) -> T {
+    let previous = std::mem::replace(
+        &mut *get_foreground().write().unwrap(),
+        Foreground {
+            command: Some(command.clone()),
+            job: None,
+        },
+    );
+    let result = f();
+    let foreground = std::mem::replace(&mut *get_foreground().write().unwrap(), previous);
+
+    if let Some(id) = foreground.job {
+        // Start a new line after the ^Z the terminal echoed
+        if std::io::stderr().is_terminal() {
+            eprintln!();
+        }
+        eprintln!("[{}]+ Stopped\t{}", id, command);
+    }
+    result
+}
+
+/// Record a foreground process that was stopped by a signal and return its job's id
+/// Later stopped processes of the same foreground command (e.g. the rest of a pipeline, in
+/// stage order) join the same job
+pub fn add_stopped_process(pid: Pid) -> usize {
+    let mut foreground = get_foreground().write().unwrap();
+    let mut jobs = get_job_table().write().unwrap();
+
+    if let Some(job) = foreground
+        .job
+        .and_then(|id| jobs.iter_mut().find(|job| job.id == id))
+    {
+        job.processes.push(pid);
+        job.last_process = pid;
+        job.state = JobState::Stopped;
+        return job.id;
+    }
+
+    let command = foreground
+        .command
+        .clone()
+        .unwrap_or_else(|| format!("process {}", pid));
+    let group = getpgid(Some(pid)).ok().filter(|group| *group != getpgrp());
+    let id = insert_job(&mut jobs, pid, command, JobState::Stopped, group);
+    foreground.job = Some(id);
+    id
+}
+
+/// Exit status of a process killed by signal `signum`, as POSIX shells report it: 128 + signum
+/// Linux signal numbers (real-time ones included) run 1-64, so this always fits; anything
+/// outside the range a status can hold is clamped
+pub fn signal_exit_code(signum: i32) -> u8 {
+    (128 + signum.clamp(1, 127)) as u8
+}
+
+/// waitpid(), except that a process killed by a signal nix has no Signal for (a real-time
+/// signal) is reported as exited with its exit code - nix would fail after reaping it
+pub fn wait_process(pid: Pid, flags: Option<WaitPidFlag>) -> nix::Result<WaitStatus> {
+    let mut status = 0;
+    let flags = flags.map_or(0, |flags| flags.bits());
+    let waited = Errno::result(unsafe { libc::waitpid(pid.as_raw(), &mut status, flags) })?;
+    if waited == 0 {
+        return Ok(WaitStatus::StillAlive);
+    }
+
+    let pid = Pid::from_raw(waited);
+    if libc::WIFSIGNALED(status) && Signal::try_from(libc::WTERMSIG(status)).is_err() {
+        let exit_code = signal_exit_code(libc::WTERMSIG(status));
+        return Ok(WaitStatus::Exited(pid, exit_code.into()));
+    }
+    WaitStatus::from_raw(pid, status)
+}
+
+/// Check unfinished jobs without blocking, recording any that stopped, continued or finished
+///
+/// Only job PIDs are waited on (never -1), so children owned by Python code (e.g. subprocess)
+/// are left for their owners to reap
+fn poll_jobs(jobs: &mut [Job]) {
+    let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+    for job in jobs.iter_mut().filter(|job| !job.state.is_done()) {
+        let last_process = job.last_process;
+        let mut exit_code = None;
+        let mut state = job.state;
+
+        job.processes.retain(|pid| {
+            let code = match wait_process(*pid, Some(flags)) {
+                Ok(WaitStatus::Exited(_pid, code)) => code as u8,
+                Ok(WaitStatus::Signaled(_pid, signal, _core_dump)) => {
+                    signal_exit_code(signal as i32)
+                }
+                Ok(WaitStatus::Stopped(..)) => {
+                    state = JobState::Stopped;
+                    return true;
+                }
+                Ok(WaitStatus::Continued(_pid)) => {
+                    state = JobState::Running;
+                    return true;
+                }
+                // No change since the last check
+                Ok(_) => return true,
+                // Reaped by someone else, so the exit status is lost - report it as finished
+                Err(_) => 0,
+            };
+            if *pid == last_process {
+                exit_code = Some(code);
+            }
+            false
+        });
+
+        job.state = if job.processes.is_empty() {
+            JobState::Done(exit_code.unwrap_or(0))
+        } else {
+            state
+        };
+    }
+}
+
+/// Parse a job spec (`%N`, or a bare job id `N`) into a job id
+pub fn parse_job_spec(spec: &str) -> Option<usize> {
+    spec.strip_prefix('%').unwrap_or(spec).parse().ok()
+}
+
+/// Get the id of the job fg uses when none is given: the one with the highest id
+pub fn current_job_id() -> Option<usize> {
+    let jobs = get_job_table().read().unwrap();
+    jobs.iter().map(|job| job.id).max()
+}
+
+/// Remove a job from the table to run it in the foreground, with its state brought up to date
+/// Returns None if there's no job with that id
+pub fn take_job(id: usize) -> Option<Job> {
+    let mut jobs = get_job_table().write().unwrap();
+    let pos = jobs.iter().position(|job| job.id == id)?;
+    poll_jobs(&mut jobs[pos..=pos]);
+    Some(jobs.remove(pos))
+}
+
+/// Remove a job from the table without signalling it, so it's no longer tracked
+/// Returns the removed job, or None if there's no job with that id
+pub fn disown_job(id: usize) -> Option<Job> {
+    let mut jobs = get_job_table().write().unwrap();
+    let pos = jobs.iter().position(|job| job.id == id)?;
+    let job = jobs.remove(pos);
+    get_disowned()
+        .write()
+        .unwrap()
+        .extend(job.processes.iter().copied());
+    Some(job)
+}
+
+/// Remove every job from the table without signalling any of them
+/// Returns the removed jobs, in id order
+pub fn disown_all_jobs() -> Vec<Job> {
+    let ids: Vec<usize> = get_job_table()
+        .read()
+        .unwrap()
+        .iter()
+        .map(|job| job.id)
+        .collect();
+    ids.into_iter().filter_map(disown_job).collect()
+}
+
+/// Reap any disowned jobs that have finished, discarding their status
+fn reap_disowned() {
+    get_disowned().write().unwrap().retain(|pid| {
+        matches!(
+            waitpid(*pid, Some(WaitPidFlag::WNOHANG)),
+            Ok(WaitStatus::StillAlive | WaitStatus::Stopped(..) | WaitStatus::Continued(_))
+        )
+    });
+}
+
+/// Get every job's current state, then drop finished jobs from the table
+/// Finished jobs are therefore reported exactly once
+pub fn report_jobs() -> Vec<Job> {
+    reap_disowned();
+    let mut jobs = get_job_table().write().unwrap();
+    poll_jobs(&mut jobs);
+    let report = jobs.clone();
+    jobs.retain(|job| !job.state.is_done());
+    report
+}
+
+/// Send a signal to every process of a job
+pub fn signal_job(job: &Job, signal: Signal) {
+    if let Some(group) = job.group {
+        killpg(group, signal).ok();
+    } else {
+        for pid in &job.processes {
+            kill(*pid, signal).ok();
+        }
+    }
+}
+
+/// Send SIGHUP to every unfinished job, like an interactive shell does when it exits
+/// A background job is its own process group, so every process in a pipeline gets the signal.
+/// Disowned and nohup'd jobs aren't in the table, so they're left running
+pub fn hangup_jobs() {
+    let mut jobs = get_job_table().write().unwrap();
+    poll_jobs(&mut jobs);
+    for job in jobs.iter().filter(|job| !job.state.is_done()) {
+        signal_job(job, Signal::SIGHUP);
+        // A stopped job can't act on SIGHUP until it's continued
+        signal_job(job, Signal::SIGCONT);
+    }
+    jobs.clear();
+}
+
+/// Reap finished jobs, removing them from the table
+/// Returns the jobs that finished, in id order
+pub fn reap_finished_jobs() -> Vec<Job> {
+    report_jobs()
+        .into_iter()
+        .filter(|job| job.state.is_done())
+        .collect()
+}
+
+/// Print a notification for each background job that finished since the last check
+/// The REPL calls this before each prompt, so finished jobs don't linger as zombies
+pub fn notify_finished_jobs() {
+    for job in reap_finished_jobs() {
+        eprintln!("[{}] {}\t{}", job.id, job.state.describe(), job.command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_exit_code_is_128_plus_signum() {
+        assert_eq!(signal_exit_code(libc::SIGKILL), 137);
+        assert_eq!(signal_exit_code(libc::SIGTERM), 143);
+        assert_eq!(signal_exit_code(libc::SIGINT), 130);
+        assert_eq!(signal_exit_code(64), 192);
+    }
+
+    #[test]
+    fn signal_exit_code_clamps_out_of_range_signals() {
+        assert_eq!(signal_exit_code(0), 129);
+        assert_eq!(signal_exit_code(127), 255);
+        assert_eq!(signal_exit_code(200), 255);
+    }
+}
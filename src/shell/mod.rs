@@ -1,10 +1,32 @@
 pub mod builtins;
 pub mod env;
 pub mod exec;
+pub mod jobs;
+pub mod options;
+pub mod pager;
+pub mod quote;
+pub mod tempfiles;
+pub mod terminal;
 
 // Re-export commonly used types and functions
 pub use env::{
-    EnvValue, all_var_keys, all_vars, contains_var, get_var, initialize_environment, set_last_exit,
-    set_var, unset_var, var_count,
+    EnvError, EnvValue, add_to_list, all_var_keys, all_vars, clear_vars, contains_var, export_list,
+    get_dir_stack, get_last_exit, get_pid, get_ppid, get_var, initialize_environment,
+    is_shell_process, os_sync_enabled, set_last_exit, set_os_sync, set_os_sync_hook, set_var,
+    unset_var, var_count,
 };
-pub use exec::{ExecRequest, RedirectTarget, execute};
+pub use exec::{ExecRequest, RedirectSource, RedirectTarget, execute};
+pub use options::{get_option, get_pipe_size, set_option, set_pipe_size};
+pub use quote::{shell_quote, split_words};
+pub use tempfiles::{cleanup_temp_paths, make_temp};
+
+/// Tidy up before the shell exits: hang up background jobs and remove mktemp() scratch files
+/// Does nothing in a forked child (e.g. `exit` run as a pipeline stage), which owns neither
+pub fn shutdown() {
+    if !is_shell_process() {
+        return;
+    }
+
+    jobs::hangup_jobs();
+    cleanup_temp_paths();
+}
@@ -0,0 +1,108 @@
+use std::sync::{OnceLock, RwLock};
+
+/// Shell-wide behavior toggles
+pub struct ShellOptions {
+    /// Refuse to truncate existing files with `>` redirection
+    pub noclobber: bool,
+    /// A pipeline fails with the exit code of its rightmost failing stage
+    pub pipefail: bool,
+    /// Builtin output taller than the terminal is shown through $PAGER
+    pub pager: bool,
+    /// Remember where commands were found in PATH instead of searching every time
+    pub hashall: bool,
+    /// Sourced scripts and -c code stop at the first statement that leaves $? non-zero
+    pub errexit: bool,
+    /// Command resolution errors also go to the terminal when stderr is captured or redirected
+    pub ttyerrors: bool,
+    /// Print each command and the CommandSpec it runs as to stderr before running it
+    pub debug: bool,
+    /// Print each command to stderr instead of running it
+    pub dryrun: bool,
+    /// Buffer size for the pipes between pipeline stages, or None for the system default
+    pub pipe_size: Option<usize>,
+}
+
+impl ShellOptions {
+    /// Create the default set of shell options (everything off except hashall, as in other shells)
+    pub fn new() -> Self {
+        Self {
+            noclobber: false,
+            pipefail: false,
+            pager: false,
+            hashall: true,
+            errexit: false,
+            ttyerrors: false,
+            debug: false,
+            dryrun: false,
+            pipe_size: None,
+        }
+    }
+}
+
+/// Names of all supported shell options, in display order
+pub const OPTION_NAMES: &[&str] = &[
+    "noclobber",
+    "pipefail",
+    "pager",
+    "hashall",
+    "errexit",
+    "ttyerrors",
+    "debug",
+    "dryrun",
+];
+
+/// Global shell options instance
+static SHELL_OPTIONS: OnceLock<RwLock<ShellOptions>> = OnceLock::new();
+
+/// Get a reference to the global shell options
+pub(crate) fn get_shell_options() -> &'static RwLock<ShellOptions> {
+    SHELL_OPTIONS.get_or_init(|| RwLock::new(ShellOptions::new()))
+}
+
+/// Get the value of a shell option by name
+///
+/// Returns None if the option doesn't exist
+pub fn get_option(name: &str) -> Option<bool> {
+    let options = get_shell_options().read().unwrap();
+    match name {
+        "noclobber" => Some(options.noclobber),
+        "pipefail" => Some(options.pipefail),
+        "pager" => Some(options.pager),
+        "hashall" => Some(options.hashall),
+        "errexit" => Some(options.errexit),
+        "ttyerrors" => Some(options.ttyerrors),
+        "debug" => Some(options.debug),
+        "dryrun" => Some(options.dryrun),
+        _ => None,
+    }
+}
+
+/// Set the value of a shell option by name
+///
+/// Returns false if the option doesn't exist
+pub fn set_option(name: &str, value: bool) -> bool {
+    let mut options = get_shell_options().write().unwrap();
+    match name {
+        "noclobber" => options.noclobber = value,
+        "pipefail" => options.pipefail = value,
+        "pager" => options.pager = value,
+        "hashall" => options.hashall = value,
+        "errexit" => options.errexit = value,
+        "ttyerrors" => options.ttyerrors = value,
+        "debug" => options.debug = value,
+        "dryrun" => options.dryrun = value,
+        _ => return false,
+    }
+    true
+}
+
+/// Get the buffer size requested for the pipes between pipeline stages
+pub fn get_pipe_size() -> Option<usize> {
+    get_shell_options().read().unwrap().pipe_size
+}
+
+/// Set the buffer size for the pipes between pipeline stages, or None for the system default
+/// Only Linux can resize pipes, and only up to its limit - larger sizes are reduced to it
+pub fn set_pipe_size(size: Option<usize>) {
+    get_shell_options().write().unwrap().pipe_size = size;
+}
@@ -0,0 +1,77 @@
+use std::io::Write;
+use std::os::fd::OwnedFd;
+
+use super::builtins::stdout_is_tty;
+use super::env::get_var;
+use super::exec::{ExecRequest, spawn_with_stdin_pipe, wait_for_child};
+use super::options::get_option;
+
+/// Write builtin output, through $PAGER when it won't fit on the screen
+///
+/// Paging only happens with the `pager` option on, stdout a terminal, and more lines than the
+/// terminal is tall - otherwise (or if the pager can't be run) the text is written directly
+pub fn page_output(text: &str) {
+    if let Some(command) = pager_command()
+        && text.lines().count() > terminal_rows()
+        && run_pager(command, text)
+    {
+        return;
+    }
+
+    let mut stdout = std::io::stdout();
+    stdout.write_all(text.as_bytes()).ok();
+    stdout.flush().ok();
+}
+
+/// The pager to use for builtin output, split into the program and its arguments
+/// Returns None when paging is off, stdout isn't a terminal, or PAGER is `cat`
+fn pager_command() -> Option<Vec<String>> {
+    if get_option("pager") != Some(true) || !stdout_is_tty() {
+        return None;
+    }
+
+    let mut command: Vec<String> = get_var("PAGER")
+        .map(|value| value.to_string_repr())
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    if command.is_empty() {
+        command.push("less".to_string());
+    }
+
+    if command[0] == "cat" {
+        None
+    } else {
+        Some(command)
+    }
+}
+
+/// Height of the terminal on stdout, or usize::MAX if it can't be determined (never page)
+fn terminal_rows() -> usize {
+    match crossterm::terminal::size() {
+        Ok((_cols, rows)) if rows > 0 => rows as usize,
+        _ => usize::MAX,
+    }
+}
+
+/// Feed text to the pager and wait for the user to quit it
+/// Returns false if the pager couldn't be run, so the caller can write the text itself
+fn run_pager(mut command: Vec<String>, text: &str) -> bool {
+    let name = command.remove(0);
+    let (child, stdin) = spawn_with_stdin_pipe(&ExecRequest::Program {
+        name,
+        args: command,
+    });
+
+    // Quitting the pager early closes the pipe - the shell ignores SIGPIPE, so that's just EPIPE
+    write_all(stdin, text);
+
+    // Exit status 127 means the pager wasn't found, and it has displayed nothing
+    wait_for_child(child).exit_code() != 127
+}
+
+fn write_all(fd: OwnedFd, text: &str) {
+    let mut file = std::fs::File::from(fd);
+    file.write_all(text.as_bytes()).ok();
+}
@@ -0,0 +1,181 @@
+/// Check if a character can appear unquoted in a shell word
+/// Matches the conservative set used by Python's shlex.quote
+fn is_safe_char(c: char) -> bool {
+    c.is_ascii_alphanumeric()
+        || matches!(c, '@' | '%' | '+' | '=' | ':' | ',' | '.' | '/' | '-' | '_')
+}
+
+/// Quote an argument so a POSIX shell would read it back as a single literal word
+///
+/// Arguments made only of safe characters are returned unchanged. Anything else is wrapped in
+/// single quotes, with embedded single quotes written as '"'"' (close, quoted quote, reopen).
+pub fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(is_safe_char) {
+        return arg.to_string();
+    }
+
+    format!("'{}'", arg.replace('\'', "'\"'\"'"))
+}
+
+/// Quote each argument and join them with spaces
+pub fn shell_join<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|arg| shell_quote(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Split a command line into words the way a POSIX shell would, without any expansion
+///
+/// Supported:
+///   - Words separated by runs of spaces, tabs and newlines (the default IFS)
+///   - Single quotes: everything up to the closing quote is literal
+///   - Double quotes: literal, except backslash escapes `"`, `\`, `$`, `` ` `` and newline
+///   - Backslash outside quotes: the next character is literal (backslash-newline is dropped)
+///   - Adjacent quoted and unquoted parts join into one word (`foo"bar"baz` is `foobarbaz`)
+///   - Empty quotes (`''` or `""`) are an empty word
+///
+/// Not supported: globbing, `$VAR`/`~`/command substitution (all kept literally), and shell
+/// operators - an unquoted `|`, `&`, `;`, `<`, `>`, `(` or `)` is an error
+pub fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    // The word being built, or None between words (so "" can still start an empty word)
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => words.extend(word.take()),
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(escaped) => word.get_or_insert_default().push(escaped),
+                None => return Err("trailing backslash".to_string()),
+            },
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(escaped @ ('"' | '\\' | '$' | '`')) => word.push(escaped),
+                            Some(other) => {
+                                word.push('\\');
+                                word.push(other);
+                            }
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '|' | '&' | ';' | '<' | '>' | '(' | ')' => {
+                return Err(format!("unsupported shell operator '{}'", c));
+            }
+            _ => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_quote_leaves_plain_args() {
+        assert_eq!(shell_quote("ls"), "ls");
+        assert_eq!(shell_quote("/usr/bin/env"), "/usr/bin/env");
+        assert_eq!(shell_quote("--color=auto"), "--color=auto");
+    }
+
+    #[test]
+    fn shell_quote_wraps_spaces_and_metacharacters() {
+        assert_eq!(shell_quote("hello world"), "'hello world'");
+        assert_eq!(shell_quote("a|b"), "'a|b'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote("it's"), r#"'it'"'"'s'"#);
+        assert_eq!(shell_quote("'"), r#"''"'"''"#);
+    }
+
+    #[test]
+    fn shell_quote_round_trips_through_split_words() {
+        for arg in [
+            "plain",
+            "two words",
+            "it's",
+            "\"quoted\"",
+            "back\\slash",
+            "",
+            "a\tb",
+        ] {
+            assert_eq!(split_words(&shell_quote(arg)), Ok(vec![arg.to_string()]));
+        }
+    }
+
+    #[test]
+    fn shell_join_quotes_each_arg() {
+        assert_eq!(shell_join(&["echo", "a b", "c"]), "echo 'a b' c");
+    }
+
+    fn words(line: &str) -> Vec<String> {
+        split_words(line).expect("line should split")
+    }
+
+    #[test]
+    fn split_words_on_whitespace() {
+        assert_eq!(words("ls  -l\t/tmp\n"), ["ls", "-l", "/tmp"]);
+        assert_eq!(words("   "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn split_words_escaped_spaces() {
+        assert_eq!(words(r"touch my\ file"), ["touch", "my file"]);
+        assert_eq!(words("a\\\nb"), ["ab"]);
+    }
+
+    #[test]
+    fn split_words_concatenates_adjacent_parts() {
+        assert_eq!(words(r#"foo"bar"baz"#), ["foobarbaz"]);
+        assert_eq!(words(r#"'a b'"c d"e"#), ["a bc de"]);
+    }
+
+    #[test]
+    fn split_words_empty_quotes_are_empty_words() {
+        assert_eq!(words(r#"echo '' """#), ["echo", "", ""]);
+        assert_eq!(words(r#"a''b"#), ["ab"]);
+    }
+
+    #[test]
+    fn split_words_double_quote_escapes() {
+        assert_eq!(words(r#""a\"b\$c\d""#), [r#"a"b$c\d"#]);
+        assert_eq!(words(r#"'$HOME \n'"#), [r"$HOME \n"]);
+    }
+
+    #[test]
+    fn split_words_errors() {
+        assert!(split_words("echo 'open").is_err());
+        assert!(split_words("echo \"open").is_err());
+        assert!(split_words("echo \\").is_err());
+        assert!(split_words("ls | wc").is_err());
+        assert!(split_words("sleep 1 &").is_err());
+    }
+}
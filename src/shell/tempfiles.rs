@@ -0,0 +1,90 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{DirBuilder, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+use super::env::get_var;
+
+/// How many names to try before giving up on finding an unused one
+const MAX_ATTEMPTS: usize = 100;
+
+/// Temporary paths to remove when the shell exits
+static TEMP_PATHS: OnceLock<RwLock<Vec<PathBuf>>> = OnceLock::new();
+
+fn get_temp_paths() -> &'static RwLock<Vec<PathBuf>> {
+    TEMP_PATHS.get_or_init(|| RwLock::new(Vec::new()))
+}
+
+/// The directory temporary paths go in by default ($TMPDIR, falling back to the system default)
+fn default_temp_dir() -> PathBuf {
+    match get_var("TMPDIR").map(|value| value.to_string_repr()) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::temp_dir(),
+    }
+}
+
+/// Generate a random name in the style of mktemp (shp.XXXXXXXXXX)
+fn random_name(suffix: &str) -> String {
+    // Each RandomState is seeded from the OS, which is random enough for unique names
+    let bits = RandomState::new().build_hasher().finish();
+    format!("shp.{:010x}{}", bits & 0xff_ffff_ffff, suffix)
+}
+
+/// Create a new temporary file (mode 0600) or directory (mode 0700)
+///
+/// The path is created atomically so it can't collide with an existing one. When `cleanup` is
+/// set, the path is removed by cleanup_temp_paths() when the shell exits.
+pub fn make_temp(
+    dir: Option<&Path>,
+    suffix: &str,
+    directory: bool,
+    cleanup: bool,
+) -> io::Result<PathBuf> {
+    let dir = dir.map(Path::to_path_buf).unwrap_or_else(default_temp_dir);
+
+    for _ in 0..MAX_ATTEMPTS {
+        let path = dir.join(random_name(suffix));
+        let created = if directory {
+            DirBuilder::new().mode(0o700).create(&path)
+        } else {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+                .map(|_| ())
+        };
+
+        match created {
+            Ok(()) => {
+                if cleanup {
+                    get_temp_paths().write().unwrap().push(path.clone());
+                }
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("could not find an unused name in {}", dir.display()),
+    ))
+}
+
+/// Remove every temporary path created with cleanup enabled
+/// Paths that were already removed (or moved away) are skipped silently
+pub fn cleanup_temp_paths() {
+    let paths = std::mem::take(&mut *get_temp_paths().write().unwrap());
+    for path in paths.iter().rev() {
+        if path.is_dir() {
+            std::fs::remove_dir_all(path).ok();
+        } else {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use super::builtins::stdout_is_tty;
+use super::env::{EnvValue, set_var};
+
+/// Set by SIGWINCH, cleared once COLUMNS and LINES have caught up with the new size
+static RESIZED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Set COLUMNS and LINES from the terminal, and keep them current as it's resized
+/// Does nothing when stdout isn't a terminal, so inherited values are left alone
+pub fn track_terminal_size() {
+    if !stdout_is_tty() {
+        return;
+    }
+
+    let resized = RESIZED.get_or_init(|| Arc::new(AtomicBool::new(false)));
+    signal_hook::flag::register(signal_hook::consts::SIGWINCH, Arc::clone(resized)).ok();
+    update_terminal_size();
+}
+
+/// Pick up a resize that happened since the last check
+/// SIGWINCH only raises a flag, so this is called at safe points (e.g. before each prompt)
+pub fn refresh_terminal_size() {
+    if RESIZED
+        .get()
+        .is_some_and(|resized| resized.swap(false, Ordering::SeqCst))
+    {
+        update_terminal_size();
+    }
+}
+
+/// Size of the terminal on stdout as (columns, rows), or None if there isn't one
+pub fn terminal_size() -> Option<(u16, u16)> {
+    if !stdout_is_tty() {
+        return None;
+    }
+    crossterm::terminal::size()
+        .ok()
+        .filter(|&(cols, rows)| cols > 0 && rows > 0)
+}
+
+fn update_terminal_size() {
+    if let Some((cols, rows)) = terminal_size() {
+        // Readonly values are left as they are
+        set_var("COLUMNS".to_string(), EnvValue::Integer(cols as i64)).ok();
+        set_var("LINES".to_string(), EnvValue::Integer(rows as i64)).ok();
+    }
+}
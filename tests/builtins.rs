@@ -0,0 +1,391 @@
+mod common;
+
+use common::{Scratch, command, marker_pager, script, script_in};
+
+#[test]
+fn readonly_variables_cannot_change() {
+    let run = script(
+        r#"
+        from shp import *
+        from shp.builtins import *
+        readonly("MODE=production")()
+        try:
+            env["MODE"] = "test"
+        except ValueError as e:
+            print("set:", e)
+        try:
+            del env["MODE"]
+        except ValueError as e:
+            print("del:", e)
+        print(env["MODE"])
+        readonly()()
+        "#,
+    )
+    .success();
+    assert!(
+        run.stdout.starts_with(
+            "set: MODE: readonly variable\n\
+             del: MODE: cannot unset: readonly variable\n\
+             production\n"
+        ),
+        "{}",
+        run.stdout
+    );
+    assert!(
+        run.stdout.contains("readonly MODE=production\n"),
+        "{}",
+        run.stdout
+    );
+}
+
+#[test]
+fn captured_builtins_have_no_interactive_formatting() {
+    let scratch = Scratch::new();
+    std::fs::create_dir(scratch.file("sub")).unwrap();
+    let run = script_in(
+        &scratch,
+        r#"
+        import os
+        from shp import *
+        os.chdir("sub")
+        print(capture(cmd("dirs")).read_stdout() == os.getcwd() + "\n")
+        print(repr(capture(cmd("which", "cd")).read_stdout()))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "True\n'cd\\n'\n");
+}
+
+#[test]
+fn cd_without_home() {
+    let scratch = Scratch::new();
+    std::fs::create_dir(scratch.file("~")).unwrap();
+    let run = script_in(
+        &scratch,
+        r#"
+        import os
+        from shp import *
+        from shp.builtins import *
+        start = os.getcwd()
+        for home in [None, ""]:
+            if home is None:
+                del env["HOME"]
+            else:
+                env["HOME"] = home
+            print(cd()().exit_code, cd("~/x")().exit_code, os.getcwd() == start)
+        # Only a leading ~ or ~/ is expanded, so a directory named ~ is still reachable
+        print(cd("./~")().exit_code, os.path.basename(os.getcwd()))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "1 1 True\n1 1 True\n0 ~\n");
+    assert_eq!(
+        run.stderr,
+        "cd: HOME not set\ncd: ~/x: HOME not set, cannot expand ~\n".repeat(2)
+    );
+}
+
+#[test]
+fn cd_double_dash_takes_the_path_literally() {
+    let scratch = Scratch::new();
+    for dir in ["-weird-dir", "-", "other"] {
+        std::fs::create_dir(scratch.file(dir)).unwrap();
+    }
+    let run = script_in(
+        &scratch,
+        r#"
+        import os
+        from pathlib import Path
+        from shp import *
+        from shp.builtins import *
+        start = os.getcwd()
+        print(cmd("cd", "--", "-weird-dir")().exit_code, os.path.basename(os.getcwd()))
+        cd(start)()
+        print(cd(Path("-"))().exit_code, os.path.basename(os.getcwd()))
+        cd(start + "/other")()
+        # A bare - still goes back to OLDPWD
+        print(str(env["OLDPWD"]) == start + "/-")
+        print(cd("-")().exit_code, os.path.basename(os.getcwd()))
+        print(str(env["OLDPWD"]) == start + "/other")
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "0 -weird-dir\n0 -\nTrue\n0 -\nTrue\n");
+}
+
+#[test]
+fn captured_builtin_output_is_not_paged() {
+    let scratch = Scratch::new();
+    let pager = marker_pager(&scratch);
+    let run = script_in(
+        &scratch,
+        &format!(
+            r#"
+            from shp import *
+            set_option("pager", True)
+            env["PAGER"] = "{}"
+            for i in range(100):
+                env[f"PAGED_{{i}}"] = str(i)
+            result = capture(cmd("declare"))
+            print(sum(" PAGED_" in line for line in result.read_stdout().splitlines()))
+            "#,
+            pager
+        ),
+    )
+    .success();
+    assert_eq!(run.stdout, "100\n");
+    assert!(!scratch.file("paged").exists());
+}
+
+#[test]
+fn program_locations_are_cached_until_path_changes() {
+    let scratch = Scratch::new();
+    for dir in ["first", "second"] {
+        std::fs::create_dir(scratch.file(dir)).unwrap();
+    }
+    scratch.script("second/tool", "echo second");
+    let run = script_in(
+        &scratch,
+        r##"
+        import os
+        from shp import *
+        first, second = os.path.abspath("first"), os.path.abspath("second")
+        env["PATH"] = [first, second, "/usr/bin", "/bin"]
+        cmd("tool")()
+        print(capture(cmd("hash")).read_stdout() == f"tool={second}/tool\n")
+
+        # Found earlier in PATH now, but the cached location is still used
+        with open("first/tool", "w") as f:
+            f.write("#!/bin/sh\necho first\n")
+        os.chmod("first/tool", 0o755)
+        cmd("tool")()
+
+        # Changing PATH forgets every cached location
+        env["PATH"] = [first, second, "/usr/bin", "/bin", "/nonexistent"]
+        print(repr(capture(cmd("hash")).read_stdout()))
+        cmd("tool")()
+        "##,
+    )
+    .success();
+    assert_eq!(run.stdout, "second\nTrue\nsecond\n''\nfirst\n");
+}
+
+#[test]
+fn which_lists_executables_matching_a_glob() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let scratch = Scratch::new();
+    let bin = scratch.file("bin");
+    std::fs::create_dir(&bin).unwrap();
+    for (name, mode) in [
+        ("pyone", 0o755),
+        ("pytwo", 0o755),
+        ("pydata", 0o644),
+        ("other", 0o755),
+    ] {
+        let path = bin.join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    let run = script_in(
+        &scratch,
+        r#"
+        import os
+        from shp import *
+        env["PATH"] = os.path.abspath("bin")
+        print(cmd("which", "-s", "nothing*")().exit_code)
+        cmd("which", "py*")()
+        "#,
+    )
+    .success();
+    let bin = bin.display();
+    assert_eq!(run.stdout, format!("1\n{bin}/pyone\n{bin}/pytwo\n"));
+}
+
+#[test]
+fn declare_forces_variable_types() {
+    let run = script(
+        r#"
+        from shp import *
+        cmd("declare", "-i", "COUNT=5")()
+        cmd("declare", "-a", "ITEMS=a:b:c", "ONE=solo")()
+        cmd("declare", "-l", "NAME=MiXeD", "DIGITS=42")()
+        env["TEXT"] = "7"
+        cmd("declare", "-i", "TEXT")()
+        for name in ["COUNT", "ITEMS", "ONE", "NAME", "DIGITS", "TEXT"]:
+            print(name, repr(env[name]))
+        print(cmd("declare", "-i", "BAD=abc")().exit_code, "BAD" in env)
+        cmd("declare", "-p", "COUNT", "ITEMS", "DIGITS")()
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "COUNT 5\nITEMS ['a', 'b', 'c']\nONE ['solo']\nNAME 'mixed'\nDIGITS '42'\nTEXT 7\n\
+         1 False\n\
+         [int] COUNT=5\n[str] DIGITS=42\n[list] ITEMS=a:b:c\n"
+    );
+    assert_eq!(run.stderr, "declare: abc: not an integer\n");
+}
+
+#[test]
+fn directories_and_special_files_are_not_run() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let scratch = Scratch::new();
+    std::fs::create_dir_all(scratch.file("sub")).unwrap();
+    std::fs::create_dir_all(scratch.file("bin/tool")).unwrap();
+    let fifo = scratch.file("fifo");
+    let made = std::process::Command::new("mkfifo")
+        .arg(&fifo)
+        .status()
+        .unwrap();
+    assert!(made.success());
+    std::fs::set_permissions(&fifo, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let run = script_in(
+        &scratch,
+        r#"
+        import os
+        from shp import *
+        print(cmd("./sub")().exit_code)
+        print(cmd(os.path.abspath("fifo"))().exit_code)
+        # A directory in PATH named like the command is skipped, not run
+        env["PATH"] = [os.path.abspath("bin")]
+        print(cmd("tool")().exit_code, cmd("which", "-s", "tool")().exit_code)
+        "#,
+    );
+    assert_eq!(run.stdout, "126\n126\n127 1\n");
+    assert_eq!(
+        run.stderr,
+        format!(
+            "./sub: Is a directory\n{}: Permission denied\ntool: command not found\n",
+            fifo.display()
+        )
+    );
+}
+
+#[test]
+fn directory_changes_are_quiet_off_a_terminal() {
+    let scratch = Scratch::new();
+    std::fs::create_dir(scratch.file("sub")).unwrap();
+    let run = script_in(
+        &scratch,
+        r#"
+        import os
+        from shp import *
+        start = os.getcwd()
+        cmd("cd", "sub")()
+        result = capture(cmd("cd", "-"))
+        print(repr(result.read_stdout()), result.exit_code, os.getcwd() == start)
+        print(repr(capture(cmd("pushd", "sub")).read_stdout()), os.path.basename(os.getcwd()))
+        print(repr(capture(cmd("popd")).read_stdout()), os.getcwd() == start)
+        # Plain runs from a script are quiet too
+        cmd("cd", "sub")()
+        cmd("cd", "-")()
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "'' 0 True\n'' sub\n'' True\n");
+}
+
+#[test]
+fn not_found_messages_are_captured() {
+    let run = script(
+        r#"
+        from shp import *
+        result = capture(cmd("definitely-missing-cmd"))
+        print(repr(result.read_stderr()), result.exit_code, env["?"])
+        "#,
+    );
+    assert_eq!(
+        run.stdout,
+        "'definitely-missing-cmd: command not found\\n' 127 127\n"
+    );
+    assert_eq!(run.stderr, "");
+    assert_eq!(run.status, 127);
+}
+
+#[test]
+fn hash_caches_clears_and_reports_missing_programs() {
+    let scratch = Scratch::new();
+    std::fs::create_dir(scratch.file("bin")).unwrap();
+    scratch.script("bin/tool", "echo tool");
+    let run = script_in(
+        &scratch,
+        r##"
+        import os
+        from shp import *
+        bin = os.path.abspath("bin")
+        env["PATH"] = [bin, "/usr/bin", "/bin"]
+        print(cmd("hash", "tool")().exit_code)
+        print(capture(cmd("hash")).read_stdout() == f"tool={bin}/tool\n")
+        print(cmd("hash", "-r")().exit_code, repr(capture(cmd("hash")).read_stdout()))
+        result = capture(cmd("hash", "definitely-missing-cmd"))
+        print(result.exit_code, repr(result.read_stderr()))
+        print(repr(capture(cmd("hash")).read_stdout()))
+        "##,
+    );
+    assert_eq!(
+        run.stdout,
+        "0\nTrue\n0 ''\n1 'hash: definitely-missing-cmd: not found\\n'\n''\n"
+    );
+}
+
+#[test]
+fn dirstack_follows_pushd_and_popd() {
+    let scratch = Scratch::new();
+    for dir in ["one", "two"] {
+        std::fs::create_dir(scratch.file(dir)).unwrap();
+    }
+    let run = script_in(
+        &scratch,
+        r#"
+        from pathlib import Path
+        from shp import *
+        start = Path.cwd()
+        print(dirstack())
+        cmd("pushd", "one")()
+        cmd("pushd", str(start / "two"))()
+        stack = dirstack()
+        print(stack == [start, start / "one"], all(isinstance(d, Path) for d in stack))
+        # A copy - changing it leaves the stack alone
+        stack.clear()
+        print(len(dirstack()))
+        cmd("popd")()
+        print(dirstack() == [start], Path.cwd() == start / "one")
+        cmd("popd")()
+        print(dirstack(), Path.cwd() == start)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "[]\nTrue True\n2\nTrue True\n[] True\n");
+}
+
+#[test]
+fn exit_truncates_codes_to_a_byte() {
+    for (code, status) in [("256", 0), ("-1", 255), ("3", 3)] {
+        let run = command(&format!(
+            "from shp import *\ncmd('exit', '{}')()\nprint('still running')",
+            code
+        ));
+        assert_eq!(
+            (run.status, run.stdout.as_str()),
+            (status, ""),
+            "exit {}",
+            code
+        );
+    }
+}
+
+#[test]
+fn exit_with_non_numeric_code() {
+    let run = command(
+        r#"
+        from shp.builtins import *
+        exit("abc")()
+        "#,
+    );
+    assert_eq!(run.status, 2);
+    assert_eq!(run.stderr, "exit: abc: numeric argument required\n");
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use common::{Scratch, command, run, script, shell, shell_with_rc};
+
+#[test]
+fn env_file_is_sourced_at_startup() {
+    let scratch = Scratch::new();
+    scratch.write("env.py", "GREETING = 'from env file'\n");
+    let mut ship = shell_with_rc(&scratch);
+    ship.env("ENV", scratch.file("env.py"))
+        .args(["-c", "print(GREETING)"]);
+    assert_eq!(run(ship, None).success().stdout, "from env file\n");
+
+    // A missing file is ignored
+    let mut ship = shell_with_rc(&scratch);
+    ship.env("ENV", scratch.file("missing.py"))
+        .args(["-c", "print('ok')"]);
+    let output = run(ship, None).success();
+    assert_eq!(
+        (output.stdout.as_str(), output.stderr.as_str()),
+        ("ok\n", "")
+    );
+}
+
+#[test]
+fn empty_home_does_not_source_init_from_cwd() {
+    let scratch = Scratch::new();
+    scratch.write("init.py", "print('init file')\n");
+    let mut ship = shell_with_rc(&scratch);
+    ship.env("HOME", "").args(["-c", "print('code')"]);
+    assert_eq!(run(ship, None).success().stdout, "code\n");
+}
+
+#[test]
+fn command_exits_with_last_command_status() {
+    let run = command(
+        r#"
+        from shp import *
+        cmd("sh", "-c", "exit 7")()
+        "#,
+    );
+    assert_eq!(run.status, 7);
+
+    let run = command(
+        r#"
+        from shp import *
+        cmd("sh", "-c", "exit 7")()
+        cmd("true")()
+        "#,
+    );
+    assert_eq!(run.status, 0);
+}
+
+#[test]
+fn command_passes_arguments_and_exit_codes() {
+    let scratch = Scratch::new();
+    let mut ship = shell(&scratch);
+    ship.args(["-c", "import sys; print(sys.argv)", "a", "b"]);
+    let output = run(ship, None).success();
+    assert_eq!(output.stdout, "['-c', 'a', 'b']\n");
+
+    assert_eq!(command("raise SystemExit(4)").status, 4);
+    let output = command("1/0");
+    assert_eq!(output.status, 1);
+    assert!(
+        output.stderr.contains("ZeroDivisionError"),
+        "{}",
+        output.stderr
+    );
+}
+
+#[test]
+fn script_file_gets_its_arguments() {
+    let scratch = Scratch::new();
+    let path = scratch.write("args.py", "import sys\nprint(sys.argv[1:])\n");
+    let mut ship = shell(&scratch);
+    ship.arg(&path).args(["x", "y"]);
+    assert_eq!(run(ship, None).success().stdout, "['x', 'y']\n");
+}
+
+#[test]
+fn missing_script_file() {
+    let scratch = Scratch::new();
+    let mut ship = shell(&scratch);
+    ship.arg("missing.py");
+    let output = run(ship, None);
+    assert_eq!(output.status, 127);
+    assert_eq!(output.stderr, "missing.py: No such file\n");
+}
+
+#[test]
+fn invalid_option_prints_usage() {
+    let scratch = Scratch::new();
+    let mut ship = shell(&scratch);
+    ship.arg("--bogus");
+    let output = run(ship, None);
+    assert_eq!(output.status, 2);
+    assert!(
+        output
+            .stderr
+            .starts_with("ship_shell: --bogus: invalid option\nusage:")
+    );
+}
+
+#[test]
+fn output_order_is_kept_when_piped() {
+    let run = script(
+        r#"
+        from shp import *
+        print("before")
+        cmd("echo", "command")()
+        print("after")
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "before\ncommand\nafter\n");
+}
+
+#[test]
+fn piped_stdin_runs_as_a_script() {
+    let scratch = Scratch::new();
+    let piped = "from shp import *\n\
+                 def shout(word):\n    print(word.upper())\n\n\
+                 shout('piped')\n\
+                 cmd('echo', 'from echo')()\n\
+                 cmd('sh', '-c', 'exit 5')()\n";
+    let output = run(shell(&scratch), Some(piped));
+    assert_eq!(output.status, 5);
+    assert_eq!(output.stdout, "PIPED\nfrom echo\n");
+    assert!(!output.stdout.contains("ship>"));
+
+    let output = run(shell(&scratch), Some("print('ok')\n")).success();
+    assert_eq!(output.stdout, "ok\n");
+}
+
+#[test]
+fn fc_needs_the_interactive_shell() {
+    let output = command("from shp import *; cmd('fc')()");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "fc: history is only available in the interactive shell\n"
+    );
+}
+
+#[test]
+fn subshell_needs_the_interactive_shell() {
+    let run = command(
+        r#"
+        import shp
+        try:
+            shp.repl.subshell()
+        except RuntimeError as e:
+            print(e)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "subshell() only works in the interactive REPL\n"
+    );
+}
+
+/// A scratch HOME with an init file and a $ENV file that announce themselves
+fn home_with_init_files() -> Scratch {
+    let scratch = Scratch::new();
+    std::fs::create_dir_all(scratch.file(".config/ship")).unwrap();
+    scratch.write(".config/ship/init.py", "print('init file')\n");
+    scratch.write("env.py", "print('env file')\n");
+    scratch.write("other.py", "print('rcfile')\n");
+    scratch
+}
+
+#[test]
+fn init_files_are_sourced_by_default() {
+    let scratch = home_with_init_files();
+    let mut ship = shell_with_rc(&scratch);
+    ship.env("ENV", scratch.file("env.py"))
+        .args(["-c", "print('code')"]);
+    let output = run(ship, None).success();
+    assert_eq!(output.stdout, "init file\nenv file\ncode\n");
+}
+
+#[test]
+fn norc_skips_init_files() {
+    let scratch = home_with_init_files();
+    let mut ship = shell(&scratch);
+    ship.env("ENV", scratch.file("env.py"))
+        .args(["-c", "print('code')"]);
+    let output = run(ship, None).success();
+    assert_eq!(output.stdout, "code\n");
+}
+
+#[test]
+fn rcfile_replaces_the_init_file() {
+    let scratch = home_with_init_files();
+    let mut ship = shell_with_rc(&scratch);
+    ship.args(["--rcfile", "other.py", "-c", "print('code')"]);
+    let output = run(ship, None).success();
+    assert_eq!(output.stdout, "rcfile\ncode\n");
+
+    let mut ship = shell_with_rc(&scratch);
+    ship.args(["--rcfile", "missing.py", "-c", "print('code')"]);
+    let output = run(ship, None).success();
+    assert_eq!(output.stdout, "code\n");
+    assert_eq!(output.stderr, "ship_shell: missing.py: No such file\n");
+}
@@ -0,0 +1,202 @@
+//! Helpers for running the shell binary in integration tests
+//!
+//! Every run gets its own scratch directory as HOME and working directory, and skips the init
+//! file, so tests neither depend on nor touch the environment they run in
+#![allow(dead_code)]
+
+pub mod pty;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a run may take before it's treated as hung
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A temporary directory, removed when dropped
+pub struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    pub fn new() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "ship-shell-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).expect("Failed to create scratch directory");
+        Scratch { dir }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of a file in the scratch directory
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Write a file in the scratch directory and return its path
+    pub fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.file(name);
+        std::fs::write(&path, contents).expect("Failed to write scratch file");
+        path
+    }
+
+    /// Write an executable shell script in the scratch directory and return its path
+    pub fn script(&self, name: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = self.write(name, &format!("#!/bin/sh\n{}\n", body));
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .expect("Failed to make scratch script executable");
+        path
+    }
+
+    /// Read a file in the scratch directory
+    pub fn read(&self, name: &str) -> String {
+        std::fs::read_to_string(self.file(name)).expect("Failed to read scratch file")
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// The outcome of running the shell
+#[derive(Debug)]
+pub struct Run {
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Run {
+    /// Panic with the run's output unless it exited successfully
+    pub fn success(self) -> Self {
+        assert_eq!(self.status, 0, "shell failed: {:#?}", self);
+        self
+    }
+}
+
+/// A command running the shell binary with --norc, in the scratch directory and with it as HOME
+pub fn shell(scratch: &Scratch) -> Command {
+    let mut command = shell_with_rc(scratch);
+    command.arg("--norc");
+    command
+}
+
+/// Like shell(), but without --norc, so the shell sources the init file in the scratch HOME
+pub fn shell_with_rc(scratch: &Scratch) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ship_shell"));
+    command
+        .current_dir(scratch.path())
+        .env("HOME", scratch.path())
+        .env("PWD", scratch.path())
+        .stdin(Stdio::null());
+    command
+}
+
+/// Run a command to completion, feeding it `stdin` if given
+/// Panics if it takes longer than TIMEOUT, killing it first
+pub fn run(mut command: Command, stdin: Option<&str>) -> Run {
+    if stdin.is_some() {
+        command.stdin(Stdio::piped());
+    }
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start the shell");
+
+    // Feed stdin and drain the output concurrently, so no pipe fills up and blocks the shell
+    let input = stdin.map(str::to_owned);
+    let mut child_stdin = child.stdin.take();
+    let writer = thread::spawn(move || {
+        if let (Some(input), Some(pipe)) = (input, child_stdin.as_mut()) {
+            std::io::Write::write_all(pipe, input.as_bytes()).ok();
+        }
+    });
+    let stdout = drain(child.stdout.take().unwrap());
+    let stderr = drain(child.stderr.take().unwrap());
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().expect("Failed to wait for the shell") {
+            break status;
+        }
+        if started.elapsed() > TIMEOUT {
+            child.kill().ok();
+            child.wait().ok();
+            panic!("shell timed out after {:?}", TIMEOUT);
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    writer.join().ok();
+
+    Run {
+        status: status.code().unwrap_or(-1),
+        stdout: stdout.join().unwrap(),
+        stderr: stderr.join().unwrap(),
+    }
+}
+
+fn drain(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        pipe.read_to_end(&mut output).ok();
+        String::from_utf8_lossy(&output).into_owned()
+    })
+}
+
+/// Run Python code as a script file in the given scratch directory
+pub fn script_in(scratch: &Scratch, code: &str) -> Run {
+    let path = scratch.write("test_script.py", &dedent(code));
+    let mut command = shell(scratch);
+    command.arg(path);
+    run(command, None)
+}
+
+/// Run Python code as a script file in a fresh scratch directory
+pub fn script(code: &str) -> Run {
+    script_in(&Scratch::new(), code)
+}
+
+/// Run Python code with -c in a fresh scratch directory
+pub fn command(code: &str) -> Run {
+    let scratch = Scratch::new();
+    let mut command = shell(&scratch);
+    command.arg("-c").arg(dedent(code));
+    run(command, None)
+}
+
+/// Remove the indentation shared by every non-blank line, so scripts can be written inline
+pub fn dedent(code: &str) -> String {
+    let indent = code
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    code.lines()
+        .map(|line| line.get(indent..).unwrap_or("").trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+/// A pager that leaves a marker behind, so tests can tell whether it ran
+pub fn marker_pager(scratch: &Scratch) -> String {
+    let pager = scratch.script("pager.sh", "touch \"$(dirname \"$0\")/paged\"\ncat");
+    pager.to_string_lossy().into_owned()
+}
@@ -0,0 +1,196 @@
+//! Driving the interactive shell through a pseudo-terminal
+
+use nix::libc;
+use nix::pty::{Winsize, openpty};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Scratch, TIMEOUT, shell};
+
+/// The shell's REPL running on a pseudo-terminal
+pub struct Pty {
+    master: File,
+    output: Arc<Mutex<Vec<u8>>>,
+    child: Child,
+    /// How much of the (cleaned) output expect() has already matched past
+    seen: usize,
+}
+
+impl Pty {
+    /// Start the REPL (with --norc) on a new terminal, as the session leader
+    pub fn spawn(scratch: &Scratch) -> Self {
+        let winsize = Winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = openpty(Some(&winsize), None).expect("Failed to open pseudo-terminal");
+        let slave = File::from(pty.slave);
+
+        let mut command = shell(scratch);
+        command
+            .env("TERM", "xterm")
+            .stdin(Stdio::from(slave.try_clone().unwrap()))
+            .stdout(Stdio::from(slave.try_clone().unwrap()))
+            .stderr(Stdio::from(slave));
+        unsafe {
+            command.pre_exec(|| {
+                // Give the shell the terminal as its controlling terminal, as a login would
+                nix::unistd::setsid().ok();
+                libc::ioctl(0, libc::TIOCSCTTY as _, 0);
+                Ok(())
+            });
+        }
+        let child = command.spawn().expect("Failed to start the shell");
+
+        let master = File::from(pty.master);
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut reader = master.try_clone().unwrap();
+        let mut responder = master.try_clone().unwrap();
+        let collected = Arc::clone(&output);
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            // Reads fail (EIO) once the shell and everything it started have closed the terminal
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                let chunk = &buf[..n];
+                // The line editor asks where the cursor is, and waits for an answer
+                let queries = chunk.windows(4).filter(|w| w == b"\x1b[6n").count();
+                for _ in 0..queries {
+                    responder.write_all(b"\x1b[1;1R").ok();
+                }
+                collected.lock().unwrap().extend_from_slice(chunk);
+            }
+        });
+
+        let mut pty = Pty {
+            master,
+            output,
+            child,
+            seen: 0,
+        };
+        // Anything typed before the line editor is ready would be lost
+        pty.expect("ship> ");
+        pty
+    }
+
+    /// Type text at the terminal
+    pub fn send(&mut self, text: &str) {
+        self.master.write_all(text.as_bytes()).unwrap();
+    }
+
+    /// Type a line and press return
+    pub fn send_line(&mut self, line: &str) {
+        self.send(&format!("{}\r", line));
+    }
+
+    /// Wait for `text` to appear in the output after the last match, and return the output up
+    /// to and including it, without terminal escape sequences or carriage returns
+    pub fn expect(&mut self, text: &str) -> String {
+        let started = Instant::now();
+        loop {
+            let output = self.output();
+            if let Some(pos) = output[self.seen..].find(text) {
+                let end = self.seen + pos + text.len();
+                let matched = output[self.seen..end].to_string();
+                self.seen = end;
+                return matched;
+            }
+            if started.elapsed() > TIMEOUT {
+                self.child.kill().ok();
+                panic!(
+                    "timed out waiting for {:?} in:\n{}",
+                    text,
+                    &output[self.seen..]
+                );
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Everything the terminal has shown so far, cleaned up as for expect()
+    pub fn output(&self) -> String {
+        strip_escapes(&String::from_utf8_lossy(&self.output.lock().unwrap()))
+    }
+
+    /// Close the shell with Ctrl-D and return its exit status
+    pub fn finish(mut self) -> i32 {
+        self.send("\x04");
+        let started = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status.code().unwrap_or(-1);
+            }
+            if started.elapsed() > TIMEOUT {
+                self.child.kill().ok();
+                panic!("shell didn't exit:\n{}", self.output());
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Resize the terminal, which sends SIGWINCH to its foreground process group
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        use std::os::fd::AsRawFd;
+
+        let winsize = Winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let result = unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) };
+        assert_eq!(result, 0, "Failed to resize the terminal");
+    }
+
+    /// The shell's process id
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            self.child.kill().ok();
+            self.child.wait().ok();
+        }
+    }
+}
+
+/// Remove terminal escape sequences (CSI, OSC and two-character ones) and carriage returns
+fn strip_escapes(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => {
+                    // Parameters, then a final byte in @..~
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                Some(']') => {
+                    // Ends with BEL or ESC \
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\r' => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
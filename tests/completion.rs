@@ -0,0 +1,58 @@
+mod common;
+
+use common::{Scratch, script, script_in};
+
+#[test]
+fn registered_completions_are_suggested() {
+    let run = script(
+        r#"
+        import shp
+        shp.repl.register_completions("fakecmd", ["checkout", "commit", "--help", "--verbose"])
+        print(shp.repl.complete("fakecmd('ch", 11))
+        print(shp.repl.complete("fakecmd('c", 10))
+        print(shp.repl.complete("prog('fakecmd')('--", 19))
+        print(shp.repl.complete("cmd('fakecmd', '--v", 19))
+        print(shp.repl.complete("othercmd('--", 12))
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "['checkout']\n\
+         ['checkout', 'commit']\n\
+         ['--help', '--verbose']\n\
+         ['--verbose']\n\
+         []\n"
+    );
+}
+
+#[test]
+fn paths_are_completed_from_the_file_system() {
+    let scratch = Scratch::new();
+    std::fs::create_dir_all(scratch.file("dir_one")).unwrap();
+    std::fs::create_dir_all(scratch.file("dir_two")).unwrap();
+    scratch.write("file.txt", "");
+    scratch.write(".hidden", "");
+    let run = script_in(
+        &scratch,
+        r#"
+        import shp
+        print(shp.repl.complete('ls /tm', 6))
+        print(shp.repl.complete("ls ./d", 6))
+        print(shp.repl.complete("cmd('cat', './fi", 16))
+        print(shp.repl.complete("ls ./", 5))
+        print(shp.repl.complete("ls ./.h", 7))
+        print(shp.repl.complete("ls dir_one/x", 12))
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "['/tmp/']\n\
+         ['./dir_one/', './dir_two/']\n\
+         ['./file.txt']\n\
+         ['./dir_one/', './dir_two/', './file.txt', './test_script.py']\n\
+         ['./.hidden']\n\
+         []\n"
+    );
+}
@@ -0,0 +1,373 @@
+mod common;
+
+use common::{Scratch, run, script, script_in, shell};
+
+#[test]
+fn env_variable_is_a_regular_variable() {
+    let run = script(
+        r#"
+        from shp import *
+        env["ENV"] = "/some/file.py"
+        print(env["ENV"])
+        del env["ENV"]
+        print("ENV" in env)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "/some/file.py\nFalse\n");
+}
+
+#[test]
+fn shell_managed_variables_are_integers() {
+    let run = script(
+        r#"
+        import os
+        from shp import *
+        cmd("sh", "-c", "exit 3")()
+        print(last_exit(), env["?"])
+        print(pid() == os.getpid() == env["$"], ppid() == os.getppid() == env["PPID"])
+        print(type(last_exit()).__name__, type(pid()).__name__, type(ppid()).__name__)
+        for key in ["?", "PPID"]:
+            try:
+                env[key] = "text"
+            except ValueError as e:
+                print(e)
+        env["?"] = 0
+        print(last_exit())
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "3 3\nTrue True\nint int int\n\
+         ?: shell-managed variable must be an integer\n\
+         PPID: shell-managed variable must be an integer\n\
+         0\n"
+    );
+}
+
+#[test]
+fn pid_cannot_be_assigned() {
+    let run = script(
+        r#"
+        import os
+        from shp import *
+        for value in ["bogus", 42]:
+            try:
+                env["$"] = value
+            except ValueError as e:
+                print(e)
+        print(env["$"] == os.getpid())
+        print([e for e in env.export_list() if e.startswith("$=")])
+        cmd("sh", "-c", "env | grep -c '^\\$=' || true")()
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "$: cannot set: shell-managed variable\n\
+         $: cannot set: shell-managed variable\n\
+         True\n[]\n0\n"
+    );
+}
+
+#[test]
+fn sync_os_mirrors_changes_into_os_environ() {
+    let run = script(
+        r#"
+        import os
+        from shp import *
+        env.sync_os(True)
+        env["SYNCED"] = 42
+        print(os.environ.get("SYNCED"))
+        del env["SYNCED"]
+        print(os.environ.get("SYNCED"))
+        env.sync_os(False)
+        env["UNSYNCED"] = "no"
+        print(os.environ.get("UNSYNCED"))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "42\nNone\nNone\n");
+}
+
+#[test]
+fn mktemp_paths_are_removed_at_exit() {
+    let scratch = Scratch::new();
+    let run = script_in(
+        &scratch,
+        r#"
+        import os
+        from shp import *
+        file = mktemp(dir=".", suffix=".txt")
+        directory = mktemp_dir(dir=".")
+        (directory / "inner").write_text("x")
+        kept = mktemp(dir=".", cleanup=False)
+        print(type(file).__name__, file.is_file(), file.suffix, directory.is_dir())
+        print(os.path.abspath(file))
+        print(os.path.abspath(directory))
+        print(os.path.abspath(kept))
+        "#,
+    )
+    .success();
+    let lines: Vec<&str> = run.stdout.lines().collect();
+    assert_eq!(lines[0], "PosixPath True .txt True");
+    assert!(!std::path::Path::new(lines[1]).exists());
+    assert!(!std::path::Path::new(lines[2]).exists());
+    assert!(std::path::Path::new(lines[3]).is_file());
+}
+
+#[test]
+fn pop_and_clear() {
+    let run = script(
+        r#"
+        from shp import *
+        from shp.builtins import *
+        env["A"] = 1
+        print(env.pop("A"), "A" in env)
+        print(env.pop("MISSING", "default"), env.pop("MISSING", None))
+        try:
+            env.pop("MISSING")
+        except KeyError:
+            print("KeyError")
+        readonly("KEEP=1")()
+        env["GONE"] = 2
+        env.clear()
+        print(len(env), "GONE" in env, env["KEEP"])
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "1 False\ndefault None\nKeyError\n1 False 1\n");
+}
+
+#[test]
+fn decimals_reach_child_processes_with_a_decimal_point() {
+    let run = script(
+        r#"
+        from shp import *
+        env["WHOLE"] = 1.0
+        env["NEGATIVE"] = -2.5
+        sh("printenv WHOLE NEGATIVE")()
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "1.0\n-2.5\n");
+}
+
+#[test]
+fn iterating_env_yields_variable_names() {
+    let run = script(
+        r#"
+        from shp import *
+        env["ITER_A"] = "1"
+        env["ITER_B"] = "2"
+        names = list(env)
+        print(sorted(k for k in names if k.startswith("ITER_")), names == list(env.keys()))
+        # Keys are snapshotted, so the environment can change while iterating
+        for key in env:
+            if key.startswith("ITER_"):
+                del env[key]
+        print([k for k in env if k.startswith("ITER_")], "HOME" in list(env))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "['ITER_A', 'ITER_B'] True\n[] True\n");
+}
+
+#[test]
+fn unsetting_internal_variables() {
+    let run = script(
+        r#"
+        from shp import *
+        env["PS1"] = "custom> "
+        env["OLDPWD"] = "/tmp"
+        del env["PS1"]
+        del env["OLDPWD"]
+        print(env.get("PS1"), env.get("OLDPWD"), "PS1" in env)
+        for key in ["?", "$", "PPID"]:
+            try:
+                del env[key]
+            except ValueError as e:
+                print(e)
+        print(env["?"], isinstance(env["$"], int), isinstance(env["PPID"], int))
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "None None False\n\
+         ?: cannot unset: shell-managed variable\n\
+         $: cannot unset: shell-managed variable\n\
+         PPID: cannot unset: shell-managed variable\n\
+         0 True True\n"
+    );
+}
+
+#[test]
+fn expand_interpolates_shell_variables() {
+    let run = script(
+        r#"
+        from shp import *
+        env["NAME"] = "ship"
+        env["COUNT"] = 3
+        print(env.expand("$NAME has ${COUNT} masts, ${MISSING:-no} sails, $$1"))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "ship has 3 masts, no sails, $1\n");
+}
+
+#[test]
+fn terminal_size_has_defaults_without_a_terminal() {
+    let scratch = Scratch::new();
+    let mut ship = shell(&scratch);
+    ship.env_remove("COLUMNS").env_remove("LINES").args([
+        "-c",
+        "from shp import *; print(env.get('COLUMNS'), env.get('LINES'), terminal_size())",
+    ]);
+    let run = run(ship, None).success();
+    assert_eq!(run.stdout, "None None (80, 24)\n");
+}
+
+#[test]
+fn in_place_or_merges_a_dict() {
+    let run = script(
+        r#"
+        from pathlib import Path
+        from shp import *
+        before = env
+        env |= {"MERGED_A": "one", "MERGED_B": 2, "MERGED_C": Path("/tmp")}
+        print(env is before, repr(env["MERGED_A"]), repr(env["MERGED_B"]), repr(env["MERGED_C"]))
+        cmd("sh", "-c", "echo $MERGED_A $MERGED_B")()
+        for bad in [[("X", "1")], "X=1"]:
+            try:
+                env |= bad
+            except TypeError as e:
+                print(e)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "True 'one' 2 PosixPath('/tmp')\n\
+         one 2\n\
+         unsupported operand type(s) for |=: 'ShipEnv' and 'list'\n\
+         unsupported operand type(s) for |=: 'ShipEnv' and 'str'\n"
+    );
+}
+
+#[test]
+fn empty_parent_variables_stay_set() {
+    let scratch = Scratch::new();
+    let mut ship = shell(&scratch);
+    ship.env("SHIP_EMPTY", "").env_remove("SHIP_UNSET").args([
+        "-c",
+        "from shp import *; print(repr(env['SHIP_EMPTY']), 'SHIP_EMPTY' in env, 'SHIP_UNSET' in env); \
+         cmd('sh', '-c', 'env | grep ^SHIP_')()",
+    ]);
+    let run = run(ship, None).success();
+    assert_eq!(run.stdout, "'' True False\nSHIP_EMPTY=\n");
+}
+
+#[test]
+fn env_inherit_false_keeps_only_essential_variables() {
+    let run = script(
+        r#"
+        from shp import *
+        env["SHIP_UNRELATED"] = "dropped"
+        listing = cmd("env").env_inherit(False).with_env(SHIP_OVERLAY="kept")
+        print(sorted(line.split("=")[0] for line in listing.capture().read_stdout().splitlines()))
+        print(capture(cmd("printenv", "SHIP_UNRELATED")).read_stdout().strip())
+        (cmd("env") | cmd("grep", "-c", "^PATH=")).env_inherit(False)()
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "['HOME', 'PATH', 'PWD', 'SHIP_OVERLAY']\ndropped\n1\n"
+    );
+}
+
+#[test]
+fn append_does_not_duplicate_path_entries() {
+    let run = script(
+        r#"
+        from pathlib import Path
+        from shp import *
+        env["PATH"] = [Path("/usr/bin"), Path("/bin")]
+        env.append("PATH", "/usr/bin", "/opt/bin")
+        env.prepend("PATH", "/bin/")
+        sh("printenv PATH")()
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "/bin/:/usr/bin:/opt/bin\n");
+}
+
+#[test]
+fn invalid_variable_names_are_rejected() {
+    let run = script(
+        r#"
+        from shp import *
+        for name in ["A=B", "", "A\0B"]:
+            try:
+                env[name] = "value"
+            except ValueError as e:
+                print(e)
+        # The shell's own special names are still fine
+        env["?"] = 3
+        print(env["?"], "A" in env)
+        "#,
+    );
+    assert_eq!(
+        run.stdout,
+        "\"A=B\": invalid variable name\n\
+         \"\": invalid variable name\n\
+         \"A\\0B\": invalid variable name\n\
+         3 False\n"
+    );
+    // A script exits with $?
+    assert_eq!(run.status, 3);
+}
+
+#[test]
+fn export_list_matches_the_child_environment() {
+    let run = script(
+        r#"
+        from pathlib import Path
+        from shp import *
+        env["SHIP_LIST"] = [Path("/usr/bin"), "/bin"]
+        env["SHIP_EMPTY"] = None
+        env["SHIP_NUMBER"] = 7
+        exported = env.export_list()
+        print([e for e in exported if e.startswith("SHIP_")])
+        print(not any(e.startswith(("?=", "$=")) for e in exported))
+        child = capture(cmd("env", "-0")).read_stdout().split("\0")[:-1]
+        print(exported == sorted(child))
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "['SHIP_EMPTY=', 'SHIP_LIST=/usr/bin:/bin', 'SHIP_NUMBER=7']\nTrue\nTrue\n"
+    );
+}
+
+#[test]
+fn get_in_walks_nested_lists() {
+    let run = script(
+        r#"
+        from shp import *
+        env["SEARCH"] = [["/a", "/b"], "/c"]
+        print(env.get_in(["SEARCH", 0, -1]), env.get_in(["SEARCH", 1]))
+        print(env.get_in(["SEARCH", "key"], "none"), env.get_in(["SEARCH", 0, 5], "none"))
+        print(env.get_in(["SEARCH", 1, 0], "none"), env.get_in(["SHIP_MISSING", 0]))
+        try:
+            env.get_in([])
+        except ValueError:
+            print("ValueError")
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "/b /c\nnone none\nnone None\nValueError\n");
+}
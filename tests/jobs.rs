@@ -0,0 +1,145 @@
+mod common;
+
+use common::{Scratch, script, script_in};
+
+#[test]
+fn nohup_commands_survive_sighup() {
+    let scratch = Scratch::new();
+    let run = script_in(
+        &scratch,
+        r#"
+        import os, signal, time
+        from shp import *
+        pid = cmd("sh", "-c", "sleep 0.5; touch survived").nohup()
+        print(os.getsid(pid) != os.getsid(0), len(jobs()))
+        time.sleep(0.2)
+        # What a hangup delivers to the process group
+        os.killpg(os.getpgid(pid), signal.SIGHUP)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "True 0\n");
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while !scratch.file("survived").exists() {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "nohup command was killed"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn disowned_jobs_leave_the_job_table() {
+    let run = script(
+        r#"
+        import os, signal
+        from shp import *
+        for _ in range(3):
+            cmd("sleep", "30").background()
+        pids = {job["id"]: job["pid"] for job in jobs()}
+        print(sorted(pids))
+        print(cmd("disown", "%2")().exit_code, [job["id"] for job in jobs()])
+        # Disowning doesn't stop the job
+        os.kill(pids[2], 0)
+        print(cmd("disown", "%9")().exit_code)
+        print(cmd("disown", "-a")().exit_code, jobs())
+        for pid in pids.values():
+            os.kill(pid, signal.SIGKILL)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "[1, 2, 3]\n0 [1, 3]\n1\n0 []\n");
+    assert_eq!(run.stderr, "disown: %9: no such job\n");
+}
+
+#[test]
+fn exiting_hangs_up_background_jobs() {
+    let scratch = Scratch::new();
+    let run = script_in(
+        &scratch,
+        r#"
+        import time
+        from shp import *
+        def waiter(name):
+            # Off the test's output pipes, which stay open while anything holds them
+            script = f"exec >/dev/null 2>&1; trap 'echo hup > {name}; exit 0' HUP; while :; do sleep 0.05; done"
+            return cmd("sh", "-c", script)
+        waiter("tracked").background()
+        waiter("disowned").background()
+        print(jobs()[1]["pid"], end="")
+        cmd("disown", "%2")()
+        # Let the traps be installed before the shell exits
+        time.sleep(0.3)
+        "#,
+    )
+    .success();
+    let disowned: i32 = run.stdout.parse().unwrap();
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while !scratch.file("tracked").exists() {
+        assert!(std::time::Instant::now() < deadline, "job wasn't hung up");
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let disowned_survived = !scratch.file("disowned").exists();
+    nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(disowned),
+        nix::sys::signal::SIGKILL,
+    )
+    .ok();
+    assert!(disowned_survived, "disowned job was hung up");
+}
+
+#[test]
+fn stopped_commands_are_recorded_as_jobs() {
+    let run = script(
+        r#"
+        import os, signal
+        from shp import *
+        print(sh("sh -c 'kill -STOP $$'")().exit_code)
+        [job] = jobs()
+        print(job['id'], job['state'], job['command'])
+        os.kill(job['pid'], signal.SIGKILL)
+        "#,
+    );
+    assert_eq!(run.stdout, "147\n1 Stopped sh -c 'kill -STOP $$'\n");
+    assert_eq!(run.stderr, "[1]+ Stopped\tsh -c 'kill -STOP $$'\n");
+}
+
+#[test]
+fn killed_commands_exit_with_128_plus_signal() {
+    let run = script(
+        r#"
+        from shp import *
+        print(sh("sh -c 'kill -TERM $$'")().exit_code)
+        print(capture(sh("sh -c 'kill -KILL $$'")).exit_code)
+        "#,
+    );
+    assert_eq!(run.stdout, "143\n137\n");
+    // A script exits with the status of the last command it ran
+    assert_eq!(run.status, 137);
+}
+
+#[test]
+fn spawned_processes_can_be_polled_waited_on_and_killed() {
+    let scratch = Scratch::new();
+    let run = script_in(
+        &scratch,
+        r#"
+        import signal
+        from shp import *
+        handle = cmd("sh", "-c", "while [ ! -e go ]; do sleep 0.01; done; exit 3").spawn()
+        print(handle.poll(), handle.exit_code, handle.pid > 0, jobs())
+        open("go", "w").close()
+        print(handle.wait(), handle.poll(), handle.exit_code)
+
+        handle = cmd("sleep", "30").spawn()
+        handle.kill(signal.SIGKILL)
+        print(handle.wait())
+        handle.kill()
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "None None True []\n3 3 3\n137\n");
+}
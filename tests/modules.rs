@@ -0,0 +1,183 @@
+mod common;
+
+use common::{Scratch, run, script, script_in, shell};
+
+#[test]
+fn modules_stay_importable_after_reload() {
+    let run = script(
+        r#"
+        import sys
+        from shp import *
+        old = sys.modules["shp.builtins"], sys.modules["core"]
+        reload()
+        import core
+        import shp.shell_marker
+        from shp.builtins import *
+        print(sys.modules["shp.builtins"] is not old[0], core is not old[1])
+        print(callable(pushd), callable(core.run_code))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "True True\nTrue True\n");
+}
+
+#[test]
+fn reload_reads_sources_from_ship_source_dir() {
+    let scratch = Scratch::new();
+    let sources = scratch.file("sources");
+    std::fs::create_dir(&sources).unwrap();
+    let shell_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("python/shell");
+    for file in ["core.py", "shell_marker.py"] {
+        std::fs::copy(shell_dir.join(file), sources.join(file)).unwrap();
+    }
+    let builtins = std::fs::read_to_string(shell_dir.join("builtins.py")).unwrap();
+    std::fs::write(sources.join("builtins.py"), builtins + "\nEDITED = True\n").unwrap();
+
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        env["SHIP_SOURCE_DIR"] = "sources"
+        reload()
+        from shp.builtins import EDITED, pushd
+        print(EDITED, callable(pushd))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "True True\n");
+}
+
+#[test]
+fn reload_with_missing_source_keeps_modules() {
+    let run = script(
+        r#"
+        import sys
+        from shp import *
+        old = sys.modules["shp.builtins"]
+        env["SHIP_SOURCE_DIR"] = "missing"
+        try:
+            reload()
+        except OSError as e:
+            print("OSError")
+        print(sys.modules["shp.builtins"] is old)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "OSError\nTrue\n");
+}
+
+#[test]
+fn reload_with_unloadable_source_keeps_modules() {
+    let scratch = Scratch::new();
+    let sources = scratch.file("sources");
+    std::fs::create_dir(&sources).unwrap();
+    let shell_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("python/shell");
+    for file in ["core.py", "shell_marker.py"] {
+        std::fs::copy(shell_dir.join(file), sources.join(file)).unwrap();
+    }
+    std::fs::write(sources.join("builtins.py"), "X = 1\0\n").unwrap();
+
+    let run = script_in(
+        &scratch,
+        r#"
+        import sys
+        from shp import *
+        old = sys.modules["core"]
+        env["SHIP_SOURCE_DIR"] = "sources"
+        try:
+            reload()
+        except ValueError as e:
+            print("builtins.py" in str(e))
+        print(sys.modules["core"] is old)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "True\nTrue\n");
+}
+
+#[test]
+fn source_reports_errors_with_file_and_line() {
+    let scratch = Scratch::new();
+    scratch.write("bad.py", "x = 1\n\ndef broken(:\n    pass\n");
+    scratch.write("raises.py", "a = 1\nraise RuntimeError('boom')\n");
+    let run = script_in(
+        &scratch,
+        r#"
+        from core import source
+        print(source("bad.py"))
+        print(source("raises.py"))
+        print("still running")
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "False\nFalse\nstill running\n");
+    let dir = scratch.path().display();
+    assert_eq!(
+        run.stderr,
+        format!(
+            "{dir}/bad.py:3: SyntaxError: invalid syntax\n{dir}/raises.py:2: RuntimeError: boom\n"
+        )
+    );
+}
+
+#[test]
+fn init_file_errors_are_reported_with_file_and_line() {
+    let scratch = Scratch::new();
+    scratch.write("rc.py", "ok = True\nundefined_name\n");
+    let mut ship = common::shell_with_rc(&scratch);
+    ship.args(["--rcfile", "rc.py", "-c", "print('started')"]);
+    let run = common::run(ship, None).success();
+    assert_eq!(run.stdout, "started\n");
+    assert!(run.stderr.contains("rc.py:2: NameError"), "{}", run.stderr);
+}
+
+#[test]
+fn add_path_makes_user_modules_importable() {
+    let scratch = Scratch::new();
+    std::fs::create_dir_all(scratch.file("lib")).unwrap();
+    scratch.write("lib/helpers.py", "GREETING = 'from helpers'\n");
+    let run = script_in(
+        &scratch,
+        r#"
+        import os, sys
+        from shp import *
+        try:
+            import helpers
+        except ImportError:
+            print("not yet")
+        add_path("~/lib")
+        add_path("lib")
+        import helpers
+        print(helpers.GREETING, sys.path[0] == os.path.abspath("lib"), sys.path.count(sys.path[0]))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "not yet\nfrom helpers True 1\n");
+}
+
+#[test]
+fn ship_pythonpath_is_added_at_startup() {
+    let scratch = Scratch::new();
+    for (dir, module) in [("one", "first_mod"), ("two", "second_mod")] {
+        std::fs::create_dir_all(scratch.file(dir)).unwrap();
+        scratch.write(
+            &format!("{}/{}.py", dir, module),
+            &format!("NAME = '{}'\n", module),
+        );
+    }
+    let mut ship = shell(&scratch);
+    ship.env(
+        "SHIP_PYTHONPATH",
+        format!(
+            "{}:{}",
+            scratch.file("one").display(),
+            scratch.file("two").display()
+        ),
+    );
+    ship.args([
+        "-c",
+        "import first_mod, second_mod; print(first_mod.NAME, second_mod.NAME)",
+    ]);
+    let run = run(ship, None).success();
+    assert_eq!(run.stdout, "first_mod second_mod\n");
+}
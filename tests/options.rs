@@ -0,0 +1,140 @@
+mod common;
+
+use common::{Scratch, command, script, script_in};
+
+#[test]
+fn set_toggles_pipefail_for_pipelines() {
+    let run = script(
+        r#"
+        from shp import *
+        failing = cmd("false") | cmd("true")
+        print(failing().exit_code)
+        cmd("set", "-o", "pipefail")()
+        print(get_option("pipefail"), failing().exit_code)
+        cmd("set", "+o", "pipefail")()
+        print(get_option("pipefail"), failing().exit_code)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "0\nTrue 1\nFalse 0\n");
+}
+
+#[test]
+fn set_lists_options() {
+    let run = script(
+        r#"
+        from shp import *
+        cmd("set", "-o", "noclobber")()
+        cmd("set", "-o")()
+        "#,
+    )
+    .success();
+    assert!(
+        run.stdout.contains("noclobber      \ton\n"),
+        "{}",
+        run.stdout
+    );
+    assert!(
+        run.stdout.contains("pipefail       \toff\n"),
+        "{}",
+        run.stdout
+    );
+}
+
+#[test]
+fn set_rejects_unknown_options() {
+    let run = script(
+        r#"
+        from shp import *
+        print(cmd("set", "-o", "bogus")().exit_code)
+        "#,
+    );
+    assert_eq!(run.stdout, "1\n");
+    assert_eq!(run.stderr, "set: bogus: invalid option name\n");
+}
+
+#[test]
+fn errexit_stops_sourced_scripts_at_the_first_failure() {
+    let scratch = Scratch::new();
+    scratch.write(
+        "sourced.py",
+        "from shp import *\nprint('before')\ncmd('sh', '-c', 'exit 3')()\nprint('after')\n",
+    );
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        import core
+        print(core.source("sourced.py"))
+        cmd("set", "-o", "errexit")()
+        print(core.source("sourced.py"), env["?"])
+        print("not reached")
+        "#,
+    );
+    // The failure also leaves $? set in the script that sourced the file, stopping it too
+    assert_eq!(run.stdout, "before\nafter\nTrue\nbefore\nFalse 3\n");
+    assert_eq!(run.status, 3);
+
+    let run = command(
+        r#"
+        from shp import *
+        cmd("set", "-o", "errexit")()
+        print("ran")
+        cmd("false")()
+        print("not reached")
+        "#,
+    );
+    assert_eq!(run.stdout, "ran\n");
+    assert_eq!(run.status, 1);
+}
+
+#[test]
+fn debug_prints_the_spec_and_still_runs() {
+    let scratch = Scratch::new();
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        set_option("debug", True)
+        cmd("touch", "ran")()
+        set_option("debug", False)
+        cmd("true")()
+        "#,
+    )
+    .success();
+    assert!(scratch.file("ran").exists());
+    assert_eq!(
+        run.stderr,
+        "+ touch ran\n  Command { program: \"touch\", args: [\"ran\"] }\n"
+    );
+}
+
+#[test]
+fn dryrun_prints_commands_without_running_them() {
+    let scratch = Scratch::new();
+    std::fs::create_dir(scratch.file("sub")).unwrap();
+    let run = script_in(
+        &scratch,
+        r#"
+        import os
+        from shp import *
+        start = os.getcwd()
+        cmd("set", "-o", "dryrun")()
+        print(cmd("touch", "ran")().exit_code)
+        (cmd("false") > "created")()
+        cmd("cd", "sub")()
+        print(os.getcwd() == start)
+        cmd("set", "+o", "dryrun")()
+        cmd("touch", "after")()
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "0\nTrue\n");
+    assert_eq!(
+        run.stderr,
+        "dry run: touch ran\ndry run: false > created\ndry run: cd sub\n"
+    );
+    assert!(!scratch.file("ran").exists());
+    assert!(!scratch.file("created").exists());
+    assert!(scratch.file("after").exists());
+}
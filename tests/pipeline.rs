@@ -0,0 +1,267 @@
+mod common;
+
+use common::{Scratch, run, script, script_in, shell};
+use nix::sys::signal::{SigHandler, Signal, signal};
+use std::os::unix::process::CommandExt;
+use std::time::{Duration, Instant};
+
+#[test]
+fn captured_pipeline_ending_in_builtin_with_large_output() {
+    let run = script(
+        r#"
+        from shp import *
+        for i in range(200):
+            env[f"BIG{i}"] = "x" * 1000
+        result = capture(cmd("true") | cmd("declare"))
+        print(len(result.read_stdout()) > 200000, result.exit_code)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "True 0\n");
+}
+
+#[test]
+fn captured_pipeline_ending_in_builtin_after_large_predecessor() {
+    let run = script(
+        r#"
+        import os
+        from shp import *
+        result = capture(cmd("seq", "1", "100000") | cmd("pwd"))
+        print(result.read_stdout().strip() == os.getcwd(), result.exit_code)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "True 0\n");
+}
+
+#[test]
+fn captured_pipeline_with_large_output() {
+    let run = script(
+        r#"
+        from shp import *
+        result = capture(cmd("true") | cmd("seq", "1", "100000"))
+        print(len(result.read_stdout().splitlines()), result.exit_code)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "100000 0\n");
+}
+
+#[test]
+fn captured_command_and_subshell_with_large_output() {
+    let run = script(
+        r#"
+        from shp import *
+        result = capture(cmd("seq", "1", "200000"))
+        print(len(result.read_stdout().splitlines()), result.exit_code)
+        result = capture(sub(cmd("seq", "1", "200000")))
+        print(len(result.read_stdout().splitlines()), result.exit_code)
+        result = capture(cmd("wc", "-l").input("x\n" * 200000))
+        print(result.read_stdout().strip(), result.exit_code)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "200000 0\n200000 0\n200000 0\n");
+}
+
+#[test]
+fn capture_all_captures_every_stage() {
+    let run = script(
+        r#"
+        from shp import *
+        results = capture_all(cmd("printf", r"b\na\n") | cmd("sort"))
+        print([(result.read_stdout(), result.exit_code) for result in results])
+        results = capture_all(cmd("seq", "1", "100000") | cmd("sh", "-c", "wc -l; exit 2"))
+        print([(len(result.read_stdout()), result.exit_code) for result in results])
+        "#,
+    );
+    assert_eq!(
+        run.stdout,
+        "[('b\\na\\n', 0), ('a\\nb\\n', 0)]\n[(588895, 0), (7, 2)]\n"
+    );
+    assert_eq!(run.status, 2);
+}
+
+#[test]
+fn stdin_from_is_the_reverse_of_pipe() {
+    let run = script(
+        r#"
+        from shp import *
+        source = cmd("printf", r"b\na\n")
+        sort = cmd("sort")
+        print(sort.stdin_from(source).to_dict() == (source | sort).to_dict())
+        print(sort.stdin_from(source))
+        sort.stdin_from(source)()
+        try:
+            sort.stdin_from(source > "out.txt")
+        except TypeError:
+            print("TypeError")
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "True\nprintf 'b\\na\\n' | sort\na\nb\nTypeError\n"
+    );
+}
+
+#[test]
+fn single_and_empty_pipes() {
+    let run = script(
+        r#"
+        from shp import *
+        print(pipe(cmd("ls")).to_dict() == cmd("ls").to_dict())
+        try:
+            pipe()
+        except ValueError as e:
+            print(e)
+        pipe(cmd("echo", "x"), cmd("tr", "x", "y"))()
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "True\npipe() requires at least one command\ny\n"
+    );
+}
+
+const YES_HEAD: &str = r#"
+from shp import *
+result = (cmd("yes") | cmd("sh", "-c", "head -n 5; exit 3"))()
+print(result.exit_code)
+"#;
+
+#[test]
+fn producers_stop_when_the_consumer_exits() {
+    let started = Instant::now();
+    let run = script(YES_HEAD);
+    assert_eq!(run.stdout, "y\ny\ny\ny\ny\n3\n");
+    assert_eq!(run.status, 3);
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn producers_stop_even_if_the_shell_ignores_sigpipe() {
+    let scratch = Scratch::new();
+    let mut ship = shell(&scratch);
+    ship.args(["-c", YES_HEAD]);
+    // Start the shell with SIGPIPE ignored, as some parent processes do
+    unsafe {
+        ship.pre_exec(|| {
+            signal(Signal::SIGPIPE, SigHandler::SigIgn).map_err(std::io::Error::from)?;
+            Ok(())
+        });
+    }
+    let run = run(ship, None);
+    assert_eq!(run.stdout, "y\ny\ny\ny\ny\n3\n");
+    assert_eq!(run.status, 3);
+}
+
+const LONG_PIPELINE: &str = r#"
+from shp import *
+stages = [cmd("echo", "start")] + [cmd("sed", "s/$/+/") for _ in range(39)]
+pipe(*stages)()
+print(capture(pipe(*stages)).read_stdout().count("+"))
+"#;
+
+#[test]
+fn long_pipelines_complete() {
+    let run = script(LONG_PIPELINE).success();
+    assert_eq!(run.stdout, format!("start{}\n39\n", "+".repeat(39)));
+}
+
+#[test]
+fn long_pipelines_fit_a_small_fd_limit() {
+    let scratch = Scratch::new();
+    let mut ship = shell(&scratch);
+    ship.args(["-c", LONG_PIPELINE]);
+    // Far fewer fds than 39 pipes need if they were all open at once
+    unsafe {
+        ship.pre_exec(|| {
+            let limit = nix::libc::rlimit {
+                rlim_cur: 24,
+                rlim_max: 24,
+            };
+            if nix::libc::setrlimit(nix::libc::RLIMIT_NOFILE, &limit) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let run = run(ship, None).success();
+    assert_eq!(run.stdout, format!("start{}\n39\n", "+".repeat(39)));
+}
+
+#[test]
+fn last_exit_matches_every_way_of_running() {
+    let run = script(
+        r#"
+        from shp import *
+        (cmd("sh", "-c", "exit 3") | cmd("true"))()
+        print(env["?"])
+        (cmd("true") | cmd("sh", "-c", "exit 4"))()
+        print(env["?"])
+        cmd("cd", "/nonexistent")()
+        print(env["?"])
+        (cmd("echo", "x") | cmd("cd", "/nonexistent"))()
+        print(env["?"])
+        capture(cmd("sh", "-c", "exit 5"))
+        print(env["?"])
+        capture(cmd("true") | cmd("sh", "-c", "exit 6"))
+        print(env["?"])
+        cmd("true")()
+        print(env["?"])
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "0\n4\n1\n1\n5\n6\n0\n");
+}
+
+#[test]
+fn builtins_can_start_pipelines() {
+    let scratch = Scratch::new();
+    let run = script_in(
+        &scratch,
+        r#"
+        import os
+        from shp import *
+        start = os.getcwd()
+        (cmd("pwd") | cmd("cat"))()
+        (cmd("echo", "hi") | cmd("tr", "a-z", "A-Z"))()
+        print(repr(capture(cmd("echo", "one", "two") | cmd("cat")).read_stdout()))
+        # cd in an earlier stage only changes that stage's directory
+        (cmd("cd", "/") | cmd("cat"))()
+        print(os.getcwd() == start)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        format!("{}\nHI\n'one two\\n'\nTrue\n", scratch.path().display())
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pipe_size_resizes_pipes_between_stages() {
+    let limit = std::fs::read_to_string("/proc/sys/fs/pipe-max-size").unwrap();
+    let run = script(
+        r#"
+        from shp import *
+        # F_GETPIPE_SZ on the stage's stdout, the pipe to cat
+        size = cmd("python3", "-c", "import fcntl; print(fcntl.fcntl(1, 1032))") | cmd("cat")
+        size()
+        set_pipe_size(1024 * 1024)
+        size()
+        set_pipe_size(1 << 40)
+        size()
+        set_pipe_size(None)
+        size()
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        format!("65536\n1048576\n{}\n65536\n", limit.trim())
+    );
+}
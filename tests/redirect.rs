@@ -0,0 +1,125 @@
+mod common;
+
+use common::{Scratch, script_in};
+
+#[test]
+fn noclobber_refuses_to_overwrite_files() {
+    let scratch = Scratch::new();
+    scratch.write("existing.txt", "original\n");
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        set_option("noclobber", True)
+        print((cmd("echo", "new") > "existing.txt")().exit_code)
+        print((cmd("echo", "created") > "created.txt")().exit_code)
+        print((cmd("echo", "appended") >> "existing.txt")().exit_code)
+        print((cmd("echo", "null") > "/dev/null")().exit_code)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "1\n0\n0\n0\n");
+    assert!(
+        run.stderr.contains("cannot overwrite existing file"),
+        "{}",
+        run.stderr
+    );
+    assert_eq!(scratch.read("existing.txt"), "original\nappended\n");
+    assert_eq!(scratch.read("created.txt"), "created\n");
+}
+
+#[test]
+fn clobber_overrides_noclobber() {
+    let scratch = Scratch::new();
+    scratch.write("existing.txt", "original\n");
+    script_in(
+        &scratch,
+        r#"
+        from shp import *
+        set_option("noclobber", True)
+        (cmd("echo", "new") > "existing.txt").clobber()()
+        "#,
+    )
+    .success();
+    assert_eq!(scratch.read("existing.txt"), "new\n");
+}
+
+#[test]
+fn redirects_overwrite_without_noclobber() {
+    let scratch = Scratch::new();
+    scratch.write("existing.txt", "original\n");
+    script_in(
+        &scratch,
+        r#"
+        from shp import *
+        (cmd("echo", "new") > "existing.txt")()
+        "#,
+    )
+    .success();
+    assert_eq!(scratch.read("existing.txt"), "new\n");
+}
+
+#[test]
+fn create_mode_sets_new_file_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let scratch = Scratch::new();
+    let existing = scratch.write("existing.txt", "old\n");
+    std::fs::set_permissions(&existing, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        (cmd("echo", "hunter2") > "secret.txt").create_mode(0o600)()
+        (cmd("echo", "more") >> "existing.txt").create_mode(0o600)()
+        for bad in [lambda: cmd("echo").create_mode(0o600),
+                    lambda: (cmd("echo") > "x.txt").create_mode(0o200000)]:
+            try:
+                bad()
+            except (TypeError, ValueError) as e:
+                print(type(e).__name__)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "TypeError\nValueError\n");
+    assert_eq!(scratch.read("secret.txt"), "hunter2\n");
+    let mode = |name| {
+        std::fs::metadata(scratch.file(name))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777
+    };
+    assert_eq!(mode("secret.txt"), 0o600);
+    assert_eq!(mode("existing.txt"), 0o644);
+    assert_eq!(scratch.read("existing.txt"), "old\nmore\n");
+}
+
+#[test]
+fn input_and_output_redirects_stack() {
+    let scratch = Scratch::new();
+    scratch.write("in.txt", "b\nc\na\n");
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        sorted_copy = (cmd("sort") < "in.txt") > "out.txt"
+        print(sorted_copy)
+        sorted_copy()
+        ((cmd("sort", "-r") > "reversed.txt") < "in.txt")()
+        ((cmd("cat") < "in.txt") >> "out.txt")()
+        try:
+            sorted_copy | cmd("cat")
+        except TypeError as e:
+            print(e)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "sort < in.txt > out.txt\n\
+         Cannot pipe from a redirected command - redirection must be the final operation\n"
+    );
+    assert_eq!(scratch.read("out.txt"), "a\nb\nc\nb\nc\na\n");
+    assert_eq!(scratch.read("reversed.txt"), "c\nb\na\n");
+}
@@ -0,0 +1,398 @@
+mod common;
+
+use common::pty::Pty;
+use common::{Scratch, marker_pager};
+
+#[test]
+fn bare_runnables_only_run_with_autorun() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("import shp");
+    pty.send_line("cmd('touch', 'ran')");
+    pty.send_line("print('A' + '1')");
+    pty.expect("A1");
+    assert!(scratch.file("ran").exists());
+
+    pty.send_line("shp.repl.set_autorun(False)");
+    pty.send_line("cmd('touch', 'not_run')");
+    let shown = pty.expect("<ShipRunnable: touch not_run>");
+    assert!(!shown.contains("Error"), "{}", shown);
+    pty.send_line("print('A' + '2')");
+    pty.expect("A2");
+    assert!(!scratch.file("not_run").exists());
+
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn dirs_abbreviates_home_on_a_terminal() {
+    let scratch = Scratch::new();
+    std::fs::create_dir_all(scratch.file("project/sub")).unwrap();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp.builtins import *");
+    pty.send_line("pushd('project')");
+    pty.expect("\n~/project\n");
+    pty.send_line("pushd('sub')");
+    pty.expect("\n~/project/sub\n");
+    pty.send_line("dirs()");
+    pty.send_line("print('D' + 'ONE')");
+    let listed = pty.expect("DONE");
+    let mut lines: Vec<&str> = listed
+        .lines()
+        .filter(|line| line.starts_with('~'))
+        .collect();
+    lines.sort();
+    assert_eq!(lines, ["~", "~/project", "~/project/sub"]);
+    pty.send_line("popd()");
+    pty.expect("\n~/project\n");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn higher_priority_hooks_fire_first() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("import shp");
+    pty.send_line("on, REPLHook = shp.repl.on, shp.repl.REPLHook");
+    pty.send_line("on(REPLHook.BeforeExecute, lambda command: print('hook-' + 'first'))");
+    pty.send_line(
+        "on(REPLHook.BeforeExecute, lambda command: print('hook-' + 'urgent'), priority=10)",
+    );
+    pty.send_line("on(REPLHook.BeforeExecute, lambda command: print('hook-' + 'last'))");
+    pty.send_line("print('A' + '1')");
+    let output = pty.expect("A1");
+    // Hooks already registered also fired for the lines registering the later ones
+    let order: Vec<&str> = output
+        .lines()
+        .filter(|line| line.starts_with("hook-"))
+        .collect();
+    assert_eq!(
+        order[order.len() - 3..],
+        ["hook-urgent", "hook-first", "hook-last"]
+    );
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn builtins_describe_themselves_on_a_terminal() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("cmd('which', 'cd')");
+    pty.expect("\ncd: shell built-in command\n");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn finished_background_jobs_are_reported_before_the_prompt() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("import time");
+    pty.send_line("cmd('sh', '-c', 'while [ ! -e go ]; do sleep 0.01; done').background()");
+    pty.send_line("print('A' + '1')");
+    let started = pty.expect("A1");
+    assert!(!started.contains("Done"), "{}", started);
+
+    std::fs::write(scratch.file("go"), "").unwrap();
+    pty.send_line("time.sleep(0.5)");
+    let notice = pty.expect("[1] Done\t");
+    // Reported once the command that was running when it finished returns
+    assert!(notice.contains("time.sleep(0.5)"), "{}", notice);
+    pty.expect("ship> ");
+    // The job was reaped along with the notification
+    pty.send_line("print('jobs', len(jobs()))");
+    pty.expect("jobs 0");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn blank_lines_terminate_multi_line_definitions() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("def greet(name):");
+    pty.send_line("    message = 'hello ' + name");
+    pty.send_line("    return message.upper()");
+    pty.send_line("");
+    pty.send_line("print(greet('a' + 'b'))");
+    pty.expect("HELLO AB");
+
+    // Indentation left on the closing line still counts as blank
+    pty.send_line("for word in ['x', 'y']:");
+    pty.send_line("    print('w-' + word)");
+    pty.send_line("    ");
+    let output = pty.expect("w-y");
+    assert!(output.contains("w-x"), "{}", output);
+
+    // A blank line on its own runs nothing, and an open bracket keeps reading
+    pty.send_line("");
+    pty.send_line("values = [");
+    pty.send_line("");
+    pty.send_line("    1, 2]");
+    pty.send_line("print('sum', sum(values))");
+    let output = pty.expect("sum 3");
+    assert!(!output.contains("Error"), "{}", output);
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn interrupted_capture_raises_and_leaks_no_fds() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("import os, time");
+    pty.send_line("before = len(os.listdir('/proc/self/fd'))");
+    pty.send_line("started = time.time(); capture(cmd('sleep', '30'))");
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    pty.send("\x03");
+    pty.expect("KeyboardInterrupt");
+    pty.send_line("print('quick', time.time() - started < 10)");
+    pty.expect("quick True");
+    pty.send_line("print('leaked', len(os.listdir('/proc/self/fd')) - before)");
+    pty.expect("leaked 0");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn edit_last_runs_the_edited_statement() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("import shp");
+    // A non-interactive "editor" that rewrites the statement in place
+    pty.send_line("env['EDITOR'] = 'sed -i s/first/second/'");
+    pty.send_line("print('first' + '-run')");
+    pty.expect("first-run");
+    pty.send_line("status = shp.repl.edit_last()");
+    let output = pty.expect("second-run");
+    assert!(output.contains("print('second' + '-run')"), "{}", output);
+    pty.send_line("print('status', status)");
+    pty.expect("status 0");
+
+    // The builtin edits the same entry, which is now the rewritten statement
+    pty.send_line("env['EDITOR'] = 'sed -i s/second/third/'");
+    pty.send_line("print('second' + '-again')");
+    pty.expect("second-again");
+    pty.send_line("cmd('fc')()");
+    pty.expect("third-again");
+
+    pty.send_line("env['EDITOR'] = 'false'");
+    pty.send_line("shp.repl.edit_last()");
+    pty.expect("fc: editor exited with status 1");
+    pty.send_line("env['EDITOR'] = ''; env['PATH'] = '/nonexistent'");
+    pty.send_line("shp.repl.edit_last()");
+    pty.expect("fc: no editor found (set EDITOR)");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn long_builtin_output_is_paged_on_a_terminal() {
+    let scratch = Scratch::new();
+    let pager = marker_pager(&scratch);
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line(&format!("env['PAGER'] = '{}'", pager));
+    pty.send_line("for i in range(100): env[f'PAGED_{i}'] = str(i)");
+    pty.send_line("");
+    // Paging is opt-in
+    pty.send_line("cmd('declare')()");
+    pty.send_line("print('A' + '1')");
+    pty.expect("A1");
+    assert!(!scratch.file("paged").exists());
+
+    pty.send_line("set_option('pager', True)");
+    pty.send_line("cmd('declare')()");
+    pty.send_line("print('A' + '2')");
+    pty.expect("A2");
+    assert!(scratch.file("paged").exists());
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn errexit_does_not_end_the_interactive_shell() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("cmd('set', '-o', 'errexit')()");
+    pty.send_line("cmd('false')()");
+    pty.send_line("print('A' + '1', env['?'])");
+    pty.expect("A1 1");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn nohup_output_goes_to_nohup_out_on_a_terminal() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("pid = cmd('echo', 'detached').nohup()");
+    pty.send_line("print('A' + '1')");
+    let output = pty.expect("A1");
+    assert!(!output.contains("\ndetached"), "{}", output);
+    assert_eq!(pty.finish(), 0);
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while !scratch.file("nohup.out").exists()
+        || std::fs::read_to_string(scratch.file("nohup.out"))
+            .unwrap()
+            .is_empty()
+    {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "nohup.out not written"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert_eq!(scratch.read("nohup.out"), "detached\n");
+}
+
+#[test]
+fn colored_prompts_render_without_readline_markers() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("import shp");
+    pty.send_line(r"shp.repl.set_prompt('\\[\x1b[32m\\]gr' + 'een\\[\x1b[0m\\]> ')");
+    // The markers are gone from the rendered prompt (the echoed input still has them)
+    let output = pty.expect("green> ");
+    assert!(output.ends_with("\ngreen> "), "{}", output);
+    pty.send_line("print('A' + '1')");
+    pty.expect("A1");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn ignorespace_keeps_statements_out_of_history() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("import shp");
+    // An "editor" that leaves the statement as it is, so fc just runs it again
+    pty.send_line("env['EDITOR'] = 'true'");
+    pty.send_line("shp.repl.set_history_control(ignorespace=True)");
+    pty.send_line("print('kept' + '1')");
+    pty.expect("kept1");
+    pty.send_line(" print('hidden' + '1')");
+    pty.expect("hidden1");
+    pty.send_line("shp.repl.edit_last()");
+    let output = pty.expect("kept1");
+    assert!(output.contains("print('kept' + '1')"), "{}", output);
+    pty.send_line("print(shp.repl.get_history_control())");
+    pty.expect("{'ignoredups': False, 'ignorespace': True}");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn columns_and_lines_follow_the_terminal_size() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("print('size', env['COLUMNS'], env['LINES'], terminal_size())");
+    pty.expect("size 80 24 (80, 24)");
+    pty.resize(100, 30);
+    pty.send_line("print('size', env['COLUMNS'] + 0, env['LINES'] + 0, terminal_size())");
+    pty.expect("size 100 30 (100, 30)");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn nested_repls_keep_their_own_buffer() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("import shp");
+    pty.send_line("x = 1");
+    pty.send_line("shp.repl.subshell()");
+    pty.expect("Nested REPL (depth 2) - press Ctrl+D to return");
+    pty.expect("ship> ");
+    // A statement left unfinished in the nested REPL doesn't leak into the outer one
+    pty.send_line("x = x + 1");
+    pty.send_line("if True:");
+    pty.send("\x04");
+    pty.expect("Leaving nested REPL");
+    pty.send_line("print('x', x)");
+    let output = pty.expect("x 2");
+    assert!(!output.contains("Error"), "{}", output);
+
+    // Hooks can't start one, since it would fire the hooks again
+    pty.send_line("on, REPLHook = shp.repl.on, shp.repl.REPLHook");
+    pty.send_line("hook = on(REPLHook.BeforeExecute, lambda command: shp.repl.subshell())");
+    pty.send_line("print('A' + '1')");
+    let output = pty.expect("A1");
+    assert!(
+        output.contains("a REPL can't be started from a REPL hook"),
+        "{}",
+        output
+    );
+    assert!(!output.contains("Nested REPL"), "{}", output);
+    pty.send_line("shp.repl.off(REPLHook.BeforeExecute, hook)");
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn ttyerrors_shows_captured_resolution_errors() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("r = capture(cmd('missing-' + 'one'))");
+    pty.send_line("print('A' + '1', r.exit_code)");
+    let output = pty.expect("A1 127");
+    assert!(
+        !output.contains("missing-one: command not found"),
+        "{}",
+        output
+    );
+
+    pty.send_line("set_option('ttyerrors', True)");
+    pty.send_line("r = capture(cmd('missing-' + 'two'))");
+    pty.send_line("print('A' + '2', repr(r.read_stderr()))");
+    let output = pty.expect("A2 'missing-two: command not found\\n'");
+    assert!(
+        output.contains("\nmissing-two: command not found\n"),
+        "{}",
+        output
+    );
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn ctrl_z_suspends_the_foreground_command() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("from shp import *");
+    pty.send_line("cmd('sh', '-c', 'sleep 1; touch resumed')()");
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    pty.send("\x1a");
+    pty.expect("[1]+ Stopped\tsh -c 'sleep 1; touch resumed'");
+    pty.expect("ship> ");
+    pty.send_line("print([(job['id'], job['state']) for job in jobs()])");
+    pty.expect("[(1, 'Stopped')]");
+    assert!(!scratch.file("resumed").exists());
+
+    // fg hands the terminal back and waits for the job to finish
+    pty.send_line("cmd('fg', '%1')()");
+    pty.send_line("print('A' + '1', len(jobs()))");
+    pty.expect("A1 0");
+    assert!(scratch.file("resumed").exists());
+    assert_eq!(pty.finish(), 0);
+}
+
+#[test]
+fn signals_during_input_do_not_end_the_shell() {
+    let scratch = Scratch::new();
+    let mut pty = Pty::spawn(&scratch);
+    pty.send_line("import signal");
+    pty.send_line("signal.signal(signal.SIGUSR1, lambda *args: None)");
+    pty.send_line("print('A' + '1')");
+    pty.expect("A1");
+    pty.expect("ship> ");
+
+    // Arrives while the line editor is waiting for input
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let pid = nix::unistd::Pid::from_raw(pty.pid() as i32);
+    nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGUSR1).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    pty.send_line("print('A' + '2')");
+    let output = pty.expect("A2");
+    assert!(!output.contains("Error"), "{}", output);
+    assert_eq!(pty.finish(), 0);
+}
@@ -0,0 +1,716 @@
+mod common;
+
+use common::{Scratch, script, script_in};
+
+#[test]
+fn pipe_to_feeds_lines_to_python() {
+    let run = script(
+        r#"
+        from shp import *
+        numbers = []
+        result = cmd("seq", "1", "5").pipe_to(lambda line: numbers.append(int(line)))
+        print(numbers, result.exit_code)
+
+        count = [0]
+        def counter(line):
+            count[0] += 1
+        cmd("seq", "1", "100000").pipe_to(counter)
+        print(count[0])
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "[1, 2, 3, 4, 5] 0\n100000\n");
+}
+
+#[test]
+fn pipe_to_callback_errors() {
+    let run = script(
+        r#"
+        from shp import *
+        seen = []
+        def picky(line):
+            seen.append(line)
+            if line == "2":
+                raise ValueError("bad line")
+        cmd("seq", "1", "3").pipe_to(picky)
+        print(seen)
+
+        seen.clear()
+        try:
+            cmd("seq", "1", "3").pipe_to(picky, abort_on_error=True)
+        except ValueError as e:
+            print("raised", e)
+        print(seen)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "['1', '2', '3']\nraised bad line\n['1', '2']\n");
+    assert!(
+        run.stderr.contains("ValueError: bad line"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn captured_invalid_utf8() {
+    let run = script(
+        r#"
+        from shp import *
+        emit = cmd("printf", r"ok\377\n")
+        print(ascii(capture(emit).read_stdout()))
+        print(capture(emit).read_stdout_bytes())
+        try:
+            capture(emit).read_stdout(errors="ignore")
+        except ValueError as e:
+            print(e)
+        try:
+            capture(emit).read_stdout(errors="strict")
+        except UnicodeDecodeError:
+            print("UnicodeDecodeError")
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "'ok\\ufffd\\n'\nb'ok\\xff\\n'\n\
+         Unknown error mode 'ignore' (expected 'replace' or 'strict')\n\
+         UnicodeDecodeError\n"
+    );
+}
+
+#[test]
+fn env_shows_the_merged_overlay() {
+    let run = script(
+        r#"
+        from shp import *
+        runnable = cmd("printenv", "A", "B", "C").with_env(A="1", B="first")
+        runnable = runnable.with_env(B="second", C=3)
+        print(sorted(runnable.env().items()))
+        print(cmd("true").env())
+        runnable()
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "[('A', '1'), ('B', 'second'), ('C', 3)]\n{}\n1\nsecond\n3\n"
+    );
+}
+
+#[test]
+fn watch_runs_a_bounded_number_of_times() {
+    let run = script(
+        r#"
+        from shp import *
+        result = watch(cmd("sh", "-c", "echo tick; exit 3"), interval=0.01, count=3)
+        print(result.exit_code)
+        "#,
+    );
+    // Piped output gets the header but no clear-screen escapes
+    let header = "Every 0.01s: sh -c 'echo tick; exit 3'  (Ctrl-C to stop)\n\n";
+    assert_eq!(
+        run.stdout,
+        format!("{header}tick\n{header}tick\n{header}tick\n3\n")
+    );
+    assert_eq!(run.status, 3);
+}
+
+#[test]
+fn quote_and_command_rendering() {
+    let run = script(
+        r#"
+        from shp import *
+        print(quote("plain"))
+        print(quote("it's here"))
+        print(cmd("echo", "a b", "it's"))
+        print(repr(cmd("echo", "a b")))
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "plain\n'it'\"'\"'s here'\necho 'a b' 'it'\"'\"'s'\n<ShipRunnable: echo 'a b'>\n"
+    );
+}
+
+#[test]
+fn sub_out_substitutes_command_output() {
+    let run = script(
+        r#"
+        from shp import *
+        cmd("echo", sub_out(cmd("echo", "hi")), "there")()
+        print(repr(sub_out(cmd("printf", r"a b\n\n\n"))))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "hi there\n'a b'\n");
+}
+
+#[test]
+fn args_can_be_appended() {
+    let run = script(
+        r#"
+        from shp import *
+        base = cmd("printf", "[%s]")
+        full = base.arg("one").args(["2", "three four"])
+        print(full.to_dict()["args"], base.to_dict()["args"])
+        full()
+        print()
+        try:
+            (base | cmd("cat")).arg("x")
+        except TypeError as e:
+            print(e)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "['[%s]', 'one', '2', 'three four'] ['[%s]']\n\
+         [one][2][three four]\n\
+         Arguments can only be appended to a plain command\n"
+    );
+}
+
+#[test]
+fn output_splits_into_fields_and_tables() {
+    let run = script(
+        r#"
+        from shp import *
+        print(cmd("printf", "a b\n  c\td\n\n").fields())
+        print(cmd("true").fields(), cmd("true").table())
+        print(cmd("printf", "name,age\nann,31\nbob,\n").table(","))
+        print(cmd("printf", "a b\nc  d").table())
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "['a', 'b', 'c', 'd']\n\
+         [] []\n\
+         [['name', 'age'], ['ann', '31'], ['bob', '']]\n\
+         [['a', 'b'], ['c', 'd']]\n"
+    );
+}
+
+#[test]
+fn merged_capture_keeps_write_order() {
+    let run = script(
+        r#"
+        from shp import *
+        interleaved = cmd("sh", "-c", "echo out1; echo err1 >&2; echo out2; echo err2 >&2")
+        merged = capture(interleaved, merge_stderr=True)
+        print(repr(merged.combined), repr(merged.read_stdout()), repr(merged.read_stderr()))
+        separate = interleaved.capture()
+        print(repr(separate.read_stdout()), repr(separate.read_stderr()))
+        for result in [merged, separate]:
+            try:
+                result.combined
+            except RuntimeError as e:
+                print(e)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "'out1\\nerr1\\nout2\\nerr2\\n' '' ''\n\
+         'out1\\nout2\\n' 'err1\\nerr2\\n'\n\
+         combined output not captured (use merge_stderr=True) or already consumed\n\
+         combined output not captured (use merge_stderr=True) or already consumed\n"
+    );
+}
+
+#[test]
+fn then_callback_receives_the_result() {
+    let run = script(
+        r#"
+        from shp import *
+        seen = []
+        result = cmd("sh", "-c", "exit 3").then(lambda r: seen.append((r.exit_code, int(env["?"]))))()
+        print(result.exit_code, seen)
+
+        # Commands run by the callback don't replace $?
+        result = cmd("false").then(lambda r: cmd("true")())()
+        print(result.exit_code, env["?"])
+
+        final = cmd("true").then(print)
+        for misuse in [lambda: final | cmd("cat"), lambda: final > "out.txt",
+                       lambda: final.with_env(X="1"), lambda: final.capture(),
+                       lambda: final.background()]:
+            try:
+                misuse()
+            except TypeError as e:
+                print(e)
+
+        def boom(r):
+            raise RuntimeError("boom")
+        try:
+            cmd("true").then(boom)()
+        except RuntimeError as e:
+            print("raised", e)
+        "#,
+    )
+    .success();
+    let extend =
+        "Cannot extend a runnable with a then() callback - then() must be the final operation\n";
+    let run_only = "A then() callback only runs when the runnable is called - it can't be captured, streamed or run in the background\n";
+    assert_eq!(
+        run.stdout,
+        format!(
+            "3 [(3, 3)]\n1 1\n{}{}raised boom\n",
+            extend.repeat(3),
+            run_only.repeat(2)
+        )
+    );
+}
+
+#[test]
+fn map_lines_transforms_stdout() {
+    let run = script(
+        r#"
+        from shp import *
+        lines = cmd("sh", "-c", "printf 'one\ntwo\nskip\nthree'; exit 4")
+        result = lines.map_lines(lambda line: None if line == "skip" else line.upper())
+        print(result.exit_code)
+        for bad in [lambda line: 5, lambda line: line[10]]:
+            try:
+                lines.map_lines(bad)
+            except (TypeError, IndexError) as e:
+                print(type(e).__name__, e)
+        "#,
+    );
+    // Runs stopped by the callback still record the command's status
+    assert_eq!(run.status, 4);
+    assert_eq!(
+        run.stdout,
+        "ONE\nTWO\nTHREE\n4\n\
+         TypeError map_lines() callback must return a str or None\n\
+         IndexError string index out of range\n"
+    );
+}
+
+#[test]
+fn captured_results_release_their_fds() {
+    let run = script(
+        r#"
+        import os
+        from shp import *
+        def open_fds():
+            return len(os.listdir("/proc/self/fd"))
+        before = open_fds()
+        # Dropped without being read
+        for _ in range(500):
+            capture(cmd("echo", "x"))
+        print(open_fds() - before)
+
+        with capture(cmd("echo", "hi")) as result:
+            held = open_fds() - before
+        print(held, open_fds() - before)
+        try:
+            result.read_stdout()
+        except RuntimeError as e:
+            print(e)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "0\n2 0\nstdout already consumed\n");
+}
+
+#[test]
+fn sh_splits_words_like_a_shell() {
+    let run = script(
+        r#"
+        from shp import *
+        sh(r"""printf '[%s]' my\ file foo"bar"baz '' $HOME""")()
+        try:
+            sh("ls | wc")
+        except ValueError:
+            print()
+            print("ValueError")
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "[my file][foobarbaz][][$HOME]\nValueError\n");
+}
+
+#[test]
+fn runnables_round_trip_through_dicts() {
+    let scratch = Scratch::new();
+    let run = script_in(
+        &scratch,
+        r#"
+        import json
+        from pathlib import Path
+        from shp import *
+        sorted_out = (cmd("printf", "%s\n", "b", "a") | cmd("sort")).with_env(LEVEL=3, FLAGS=["-x", "-y"]) >> "out.txt"
+        spec = sorted_out.to_dict()
+        print(spec["type"], spec["target"]["append"], sorted(spec["runnable"]["env"].items()))
+        # The spec survives a trip through JSON
+        rebuilt = from_dict(json.loads(json.dumps(spec)))
+        print(rebuilt.to_dict() == spec, str(rebuilt) == str(sorted_out))
+        rebuilt()
+        print(from_dict(cmd("ls").with_env(DIR=Path("/tmp")).to_dict()).env())
+        try:
+            from_dict({"type": "bogus"})
+        except ValueError as e:
+            print(e)
+        # A pipeline stage that is itself a pipeline is flattened, as with |
+        inner = (cmd("printf", "%s\n", "c", "a", "b") | cmd("sort")).to_dict()
+        nested = from_dict({"type": "pipeline", "stages": [inner, cmd("head", "-n", "2").to_dict()]})
+        print(len(nested.to_dict()["stages"]))
+        nested()
+        try:
+            from_dict({"type": "pipeline", "stages": [(cmd("ls") >> "x").to_dict(), cmd("wc").to_dict()]})
+        except TypeError:
+            print("TypeError")
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "redirect True [('FLAGS', ['-x', '-y']), ('LEVEL', 3)]\n\
+         True True\n\
+         {'DIR': PosixPath('/tmp')}\n\
+         from_dict(): unknown runnable type 'bogus'\n\
+         3\n\
+         a\n\
+         b\n\
+         TypeError\n"
+    );
+    assert_eq!(scratch.read("out.txt"), "a\nb\n");
+}
+
+#[test]
+fn on_output_sees_lines_that_still_reach_stdout() {
+    let run = script(
+        r#"
+        from shp import *
+        count = 0
+        def progress(line):
+            global count
+            count += 1
+        result = cmd("sh", "-c", "seq 1 5; exit 2").on_output(progress)
+        print(count, result.exit_code)
+
+        # A failing callback is reported once and the output keeps flowing
+        def bad(line):
+            raise ValueError("bad " + line)
+        print(cmd("seq", "1", "3").on_output(bad).exit_code)
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "1\n2\n3\n4\n5\n5 2\n1\n2\n3\n0\n");
+    assert!(
+        run.stderr.starts_with("Error in on_output callback:\n"),
+        "{}",
+        run.stderr
+    );
+    assert!(
+        run.stderr.ends_with("ValueError: bad 1\n"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn results_compare_like_exit_codes() {
+    let run = script(
+        r#"
+        from shp import *
+        ok, failed = cmd("true")(), cmd("sh", "-c", "exit 3")()
+        print(ok == 0, ok != 0, failed == 3, failed != 0, failed == 0)
+        print(int(ok), int(failed))
+        # Truthy on success, like a shell condition - the opposite of the exit code's truthiness
+        print(bool(ok), bool(failed))
+        print(ok == "0", failed == cmd("sh", "-c", "exit 3")())
+        "#,
+    );
+    assert_eq!(
+        run.stdout,
+        "True False True True False\n0 3\nTrue False\nFalse True\n"
+    );
+}
+
+#[test]
+fn failures_raise_specific_ship_errors() {
+    let run = script(
+        r#"
+        import io
+        from shp import *
+        try:
+            cmd("definitely-not-a-command")().raise_for_status()
+        except CommandNotFound as e:
+            print("CommandNotFound", e)
+        try:
+            cmd("sh", "-c", "exit 4")().raise_for_status()
+        except CommandError as e:
+            print("CommandError", e)
+        try:
+            cmd("echo") > io.StringIO()
+        except RedirectError as e:
+            print("RedirectError", e)
+        print(all(issubclass(error, ShipError) for error in [CommandNotFound, CommandError, RedirectError]))
+        print(cmd("true")().raise_for_status().exit_code)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "CommandNotFound command not found (exit code 127)\n\
+         CommandError command failed with exit code 4\n\
+         RedirectError Redirect target has no file descriptor: UnsupportedOperation: fileno\n\
+         True\n\
+         0\n"
+    );
+}
+
+#[test]
+fn capture_method_matches_capture_function() {
+    let run = script(
+        r#"
+        from shp import *
+        for runnable in [
+            cmd("sh", "-c", "echo out; echo err >&2; exit 2"),
+            cmd("printf", "b\na\n") | cmd("sort"),
+            sub(cmd("echo", "nested")),
+        ]:
+            method, function = runnable.capture(), capture(runnable)
+            print(
+                method.read_stdout() == function.read_stdout(),
+                method.read_stderr() == function.read_stderr(),
+                method.exit_code == function.exit_code,
+            )
+        print(repr((cmd("printf", "b\na\n") | cmd("sort")).capture().read_stdout()))
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "True True True\n".repeat(3) + "'a\\nb\\n'\n");
+}
+
+#[test]
+fn programs_can_be_paths() {
+    let scratch = Scratch::new();
+    scratch.script("tool", "echo ran tool \"$@\"");
+    let run = script_in(
+        &scratch,
+        r#"
+        import shutil
+        from pathlib import Path
+        from shp import *
+        echo = Path(shutil.which("echo"))
+        cmd(echo, "from", "cmd")()
+        prog(echo)("from", "prog")()
+        # A relative path is run as a file rather than searched for in PATH
+        env["PATH"] = "/usr/bin:/bin"
+        cmd(Path("tool"), "arg")()
+        print(cmd(Path("tool")).to_dict()["program"])
+        try:
+            cmd(5)
+        except TypeError as e:
+            print(e)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "from cmd\nfrom prog\nran tool arg\n./tool\n\
+         program name must be a str or path-like object, not int\n"
+    );
+}
+
+#[test]
+fn input_feeds_data_to_stdin() {
+    let run = script(
+        r#"
+        from shp import *
+        print(repr(cmd("sort").input("b\na\n").capture().read_stdout()))
+        print(repr(cmd("cat").input(b"\x00bytes\xff").capture().read_stdout_bytes()))
+        (cmd("tr", "a-z", "A-Z").input("piped\n") | cmd("rev"))()
+        cmd("wc", "-l").input("")()
+        try:
+            cmd("cat").input(5)
+        except TypeError as e:
+            print(e)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "'a\\nb\\n'\n\
+         b'\\x00bytes\\xff'\n\
+         DEPIP\n\
+         0\n\
+         input() data must be str or bytes, not int\n"
+    );
+}
+
+#[test]
+fn pty_capture_runs_on_a_terminal() {
+    let scratch = Scratch::new();
+    for name in ["a", "b", "c"] {
+        scratch.write(name, "");
+    }
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        check = cmd('sh', '-c', '[ -t 1 ] && echo tty || echo pipe')
+        print(repr(capture(check).read_stdout()))
+        print(repr(check.capture(pty=True).read_stdout()))
+        print(repr(capture(cmd('ls', 'a', 'b', 'c')).read_stdout()))
+        print(repr(capture(cmd('ls', 'a', 'b', 'c'), pty=True).read_stdout()))
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "'pipe\\n'\n'tty\\n'\n'a\\nb\\nc\\n'\n'a  b  c\\n'\n"
+    );
+}
+
+#[test]
+fn columns_parse_tabular_output() {
+    let scratch = Scratch::new();
+    scratch.write(
+        "ps.txt",
+        "  PID TTY  COMMAND\n    1 ?    /sbin/init splash\n\n  42 pts/0 sleep 30\n",
+    );
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        for row in cmd('cat', 'ps.txt').capture().columns():
+            print(row)
+        for row in cmd('cat', 'ps.txt').capture().columns(maxsplit=1):
+            print(row)
+        for row in cmd('cat', 'ps.txt').capture().columns(header=False, maxsplit=2):
+            print(row)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "{'PID': '1', 'TTY': '?', 'COMMAND': '/sbin/init splash'}\n\
+         {'PID': '42', 'TTY': 'pts/0', 'COMMAND': 'sleep 30'}\n\
+         {'PID': '1', 'TTY': '?    /sbin/init splash'}\n\
+         {'PID': '42', 'TTY': 'pts/0 sleep 30'}\n\
+         {0: 'PID', 1: 'TTY', 2: 'COMMAND'}\n\
+         {0: '1', 1: '?', 2: '/sbin/init splash'}\n\
+         {0: '42', 1: 'pts/0', 2: 'sleep 30'}\n"
+    );
+}
+
+#[test]
+fn retry_reruns_until_success() {
+    let scratch = Scratch::new();
+    // Fails until it has been run three times
+    scratch.script(
+        "flaky",
+        "n=$(( $(cat count 2>/dev/null || echo 0) + 1 ))\necho $n > count\n[ $n -ge 3 ]",
+    );
+    let run = script_in(
+        &scratch,
+        r#"
+        import os, time
+        from shp import *
+        started = time.time()
+        result = cmd('./flaky').retry(attempts=5, delay=0.1, backoff=2.0)()
+        # Waits 0.1s, then 0.2s
+        print(result.exit_code, open('count').read().strip(), time.time() - started >= 0.3)
+        os.remove('count')
+        result = cmd('./flaky').retry(attempts=2, delay=0)()
+        print(result.exit_code, open('count').read().strip())
+        "#,
+    );
+    assert_eq!(run.stdout, "0 3 True\n1 2\n");
+    assert_eq!(run.status, 1);
+}
+
+#[test]
+fn captured_results_print_as_their_stdout() {
+    let run = script(
+        r#"
+        from shp import *
+        result = capture(cmd("sh", "-c", "printf 'out\\377\\n'; echo err >&2"))
+        print(ascii(str(result)), str(result) == str(result))
+        print(ascii(result))
+        print(ascii(result.read_stdout()))
+        print(ascii(result))
+        # Long output is cut short
+        print(len(repr(capture(cmd("seq", "1000")))) < 200)
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "'out\\ufffd\\n' True\n\
+         <CapturedResult: exit_code=0 stdout=\"out\\ufffd\\n\" stderr=\"err\\n\">\n\
+         'out\\ufffd\\n'\n\
+         <CapturedResult: exit_code=0 stdout=<consumed> stderr=\"err\\n\">\n\
+         True\n"
+    );
+}
+
+#[test]
+fn program_attributes_build_subcommands() {
+    let scratch = Scratch::new();
+    scratch.script("git", "echo \"$@\"");
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        git = prog("./git")
+        print(git.status("-s").to_dict()["args"])
+        git.status("-s")()
+        git.remote.add("origin", "url")()
+        git.list_upgradable()()
+        print(git.name)
+        try:
+            git._private
+        except AttributeError:
+            print("AttributeError")
+        "#,
+    )
+    .success();
+    assert_eq!(
+        run.stdout,
+        "['status', '-s']\nstatus -s\nremote add origin url\nlist-upgradable\n./git\nAttributeError\n"
+    );
+}
+
+#[test]
+fn describe_marks_builtins_in_a_pipeline() {
+    let scratch = Scratch::new();
+    let run = script_in(
+        &scratch,
+        r#"
+        from shp import *
+        plan = ((cmd("pwd") | cmd("cat", "-n").with_env(A="1")) > "out.txt").describe()
+        print(plan == {
+            "type": "redirect",
+            "target": {"path": "out.txt", "append": False, "clobber": False, "mode": None},
+            "runnable": {
+                "type": "pipeline",
+                "stages": [
+                    {"type": "command", "program": "pwd", "args": [], "builtin": True},
+                    {
+                        "type": "with_env",
+                        "env": {"A": "1"},
+                        "inherit": True,
+                        "runnable": {
+                            "type": "command",
+                            "program": "cat",
+                            "args": ["-n"],
+                            "builtin": False,
+                        },
+                    },
+                ],
+            },
+        })
+        "#,
+    )
+    .success();
+    assert_eq!(run.stdout, "True\n");
+    // Describing runs nothing
+    assert!(!scratch.file("out.txt").exists());
+}